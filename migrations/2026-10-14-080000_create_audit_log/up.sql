@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE `audit_log`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`actor` VARCHAR,
+	`action` VARCHAR NOT NULL,
+	`target` VARCHAR,
+	`ip` VARCHAR,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX `audit_log_created_at` ON `audit_log`(`created_at`);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE `idempotency_keys`(
+	`key` VARCHAR NOT NULL PRIMARY KEY,
+	`request_url` VARCHAR NOT NULL,
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`) ON DELETE CASCADE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
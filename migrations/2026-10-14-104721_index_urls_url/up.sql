@@ -0,0 +1,2 @@
+-- Your SQL goes here
+CREATE INDEX `urls_url` ON `urls`(`url`);
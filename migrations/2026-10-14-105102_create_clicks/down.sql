@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS `clicks`;
+ALTER TABLE `urls` DROP COLUMN `public_stats`;
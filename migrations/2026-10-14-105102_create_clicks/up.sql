@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `public_stats` BOOL NOT NULL DEFAULT FALSE;
+
+CREATE TABLE `clicks`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`) ON DELETE CASCADE,
+	`clicked_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX `clicks_url_id_clicked_at` ON `clicks`(`url_id`, `clicked_at`);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS `urls_created_at`;
+ALTER TABLE `urls` DROP COLUMN `created_at`;
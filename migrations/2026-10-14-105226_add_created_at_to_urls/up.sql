@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- SQLite can't add a column with a non-constant default, so the table is rebuilt.
+CREATE TABLE `urls_new`(
+	`id` VARCHAR NOT NULL PRIMARY KEY,
+	`url` VARCHAR NOT NULL,
+	`disabled` BOOL NOT NULL DEFAULT FALSE,
+	`public_stats` BOOL NOT NULL DEFAULT FALSE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO `urls_new`(`id`, `url`, `disabled`, `public_stats`)
+SELECT `id`, `url`, `disabled`, `public_stats` FROM `urls`;
+
+DROP TABLE `urls`;
+ALTER TABLE `urls_new` RENAME TO `urls`;
+
+CREATE INDEX `urls_url` ON `urls`(`url`);
+CREATE INDEX `urls_created_at` ON `urls`(`created_at`);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `untracked_clicks` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE `clicks` ADD COLUMN `referrer` VARCHAR;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE `conversions`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`click_id` INTEGER NOT NULL REFERENCES `clicks`(`id`) ON DELETE CASCADE,
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`) ON DELETE CASCADE,
+	`event` VARCHAR NOT NULL,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(`click_id`, `event`)
+);
+
+CREATE INDEX `conversions_url_id` ON `conversions`(`url_id`);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `redirect_mode` VARCHAR NOT NULL DEFAULT 'http';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX `clicks_token`;
+
+ALTER TABLE `clicks` DROP COLUMN `token`;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE `clicks` ADD COLUMN `token` VARCHAR;
+
+CREATE UNIQUE INDEX `clicks_token` ON `clicks`(`token`);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE `link_checks`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`) ON DELETE CASCADE,
+	`checked_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`status` INTEGER,
+	`failure` VARCHAR,
+	`redirects` INTEGER NOT NULL,
+	`duration_ms` INTEGER NOT NULL,
+	`latest` BOOL NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX `link_checks_url_id_checked_at` ON `link_checks`(`url_id`, `checked_at`);
+CREATE INDEX `link_checks_latest` ON `link_checks`(`latest`, `id`);
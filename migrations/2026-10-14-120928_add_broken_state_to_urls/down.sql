@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `urls` DROP COLUMN `broken_at`;
+ALTER TABLE `urls` DROP COLUMN `failed_checks`;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `failed_checks` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `urls` ADD COLUMN `broken_at` TIMESTAMP;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE `api_tokens`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name` VARCHAR NOT NULL,
+	`token_hash` VARCHAR NOT NULL UNIQUE,
+	`scopes` VARCHAR NOT NULL,
+	`expires_at` TIMESTAMP,
+	`last_used_at` TIMESTAMP,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`revoked_at` TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `urls` DROP COLUMN `quarantined`;
+ALTER TABLE `urls` DROP COLUMN `spam_score`;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `spam_score` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `urls` ADD COLUMN `quarantined` BOOL NOT NULL DEFAULT FALSE;
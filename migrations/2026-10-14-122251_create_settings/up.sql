@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE `settings`(
+	`name` VARCHAR NOT NULL PRIMARY KEY,
+	`value` VARCHAR NOT NULL,
+	`updated_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER `urls_update_updated_at`;
+DROP TRIGGER `urls_insert_updated_at`;
+ALTER TABLE `urls` DROP COLUMN `creator_ip`;
+ALTER TABLE `urls` DROP COLUMN `created_by`;
+ALTER TABLE `urls` DROP COLUMN `updated_at`;
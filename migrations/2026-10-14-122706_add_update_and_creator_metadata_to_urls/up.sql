@@ -0,0 +1,21 @@
+-- Your SQL goes here
+-- SQLite can't add a column defaulting to CURRENT_TIMESTAMP, so triggers keep it current.
+ALTER TABLE `urls` ADD COLUMN `updated_at` TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE `urls` ADD COLUMN `created_by` VARCHAR;
+ALTER TABLE `urls` ADD COLUMN `creator_ip` VARCHAR;
+
+UPDATE `urls` SET
+	`updated_at` = `created_at`,
+	`created_by` = (SELECT `actor` FROM `audit_log` WHERE `action` = 'create' AND `target` = `urls`.`id` ORDER BY `id` LIMIT 1),
+	`creator_ip` = (SELECT `ip` FROM `audit_log` WHERE `action` = 'create' AND `target` = `urls`.`id` ORDER BY `id` LIMIT 1);
+
+CREATE TRIGGER `urls_insert_updated_at` AFTER INSERT ON `urls`
+BEGIN
+	UPDATE `urls` SET `updated_at` = NEW.`created_at` WHERE `id` = NEW.`id`;
+END;
+
+-- Only changes to what a link does count, not bookkeeping by background workers.
+CREATE TRIGGER `urls_update_updated_at` AFTER UPDATE OF `url`, `disabled`, `public_stats`, `pixels`, `interstitial`, `redirect_mode`, `publish_at`, `quarantined` ON `urls`
+BEGIN
+	UPDATE `urls` SET `updated_at` = CURRENT_TIMESTAMP WHERE `id` = NEW.`id`;
+END;
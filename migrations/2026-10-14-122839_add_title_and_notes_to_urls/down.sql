@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER `urls_update_updated_at`;
+CREATE TRIGGER `urls_update_updated_at` AFTER UPDATE OF `url`, `disabled`, `public_stats`, `pixels`, `interstitial`, `redirect_mode`, `publish_at`, `quarantined` ON `urls`
+BEGIN
+	UPDATE `urls` SET `updated_at` = CURRENT_TIMESTAMP WHERE `id` = NEW.`id`;
+END;
+
+ALTER TABLE `urls` DROP COLUMN `notes`;
+ALTER TABLE `urls` DROP COLUMN `title`;
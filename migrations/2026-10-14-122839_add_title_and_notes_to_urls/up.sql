@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `title` VARCHAR;
+ALTER TABLE `urls` ADD COLUMN `notes` VARCHAR;
+
+DROP TRIGGER `urls_update_updated_at`;
+CREATE TRIGGER `urls_update_updated_at` AFTER UPDATE OF `url`, `disabled`, `public_stats`, `pixels`, `interstitial`, `redirect_mode`, `publish_at`, `quarantined`, `title`, `notes` ON `urls`
+BEGIN
+	UPDATE `urls` SET `updated_at` = CURRENT_TIMESTAMP WHERE `id` = NEW.`id`;
+END;
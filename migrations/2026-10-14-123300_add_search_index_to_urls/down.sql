@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER `urls_search_update`;
+DROP TRIGGER `urls_search_delete`;
+DROP TRIGGER `urls_search_insert`;
+DROP TABLE `urls_search`;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- The index refers to links by rowid, which VACUUM may renumber, so it has to be
+-- rebuilt after one.
+CREATE VIRTUAL TABLE `urls_search` USING fts5(`url`, `title`, `notes`, content = 'urls', content_rowid = 'rowid');
+
+INSERT INTO `urls_search`(`urls_search`) VALUES ('rebuild');
+
+CREATE TRIGGER `urls_search_insert` AFTER INSERT ON `urls`
+BEGIN
+	INSERT INTO `urls_search`(`rowid`, `url`, `title`, `notes`) VALUES (NEW.`rowid`, NEW.`url`, NEW.`title`, NEW.`notes`);
+END;
+
+CREATE TRIGGER `urls_search_delete` AFTER DELETE ON `urls`
+BEGIN
+	INSERT INTO `urls_search`(`urls_search`, `rowid`, `url`, `title`, `notes`) VALUES ('delete', OLD.`rowid`, OLD.`url`, OLD.`title`, OLD.`notes`);
+END;
+
+CREATE TRIGGER `urls_search_update` AFTER UPDATE OF `url`, `title`, `notes` ON `urls`
+BEGIN
+	INSERT INTO `urls_search`(`urls_search`, `rowid`, `url`, `title`, `notes`) VALUES ('delete', OLD.`rowid`, OLD.`url`, OLD.`title`, OLD.`notes`);
+	INSERT INTO `urls_search`(`rowid`, `url`, `title`, `notes`) VALUES (NEW.`rowid`, NEW.`url`, NEW.`title`, NEW.`notes`);
+END;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `og_title` VARCHAR;
+ALTER TABLE `urls` ADD COLUMN `og_description` VARCHAR;
+ALTER TABLE `urls` ADD COLUMN `og_image` VARCHAR;
+
+DROP TRIGGER `urls_update_updated_at`;
+CREATE TRIGGER `urls_update_updated_at` AFTER UPDATE OF `url`, `disabled`, `public_stats`, `pixels`, `interstitial`, `redirect_mode`, `publish_at`, `quarantined`, `title`, `notes`, `og_title`, `og_description`, `og_image` ON `urls`
+BEGIN
+	UPDATE `urls` SET `updated_at` = CURRENT_TIMESTAMP WHERE `id` = NEW.`id`;
+END;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `indexable` BOOL NOT NULL DEFAULT FALSE;
+
+DROP TRIGGER `urls_update_updated_at`;
+CREATE TRIGGER `urls_update_updated_at` AFTER UPDATE OF `url`, `disabled`, `public_stats`, `pixels`, `interstitial`, `redirect_mode`, `publish_at`, `quarantined`, `title`, `notes`, `og_title`, `og_description`, `og_image`, `indexable` ON `urls`
+BEGIN
+	UPDATE `urls` SET `updated_at` = CURRENT_TIMESTAMP WHERE `id` = NEW.`id`;
+END;
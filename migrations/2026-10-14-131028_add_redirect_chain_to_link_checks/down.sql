@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `link_checks` DROP COLUMN `downgraded`;
+ALTER TABLE `link_checks` DROP COLUMN `final_url`;
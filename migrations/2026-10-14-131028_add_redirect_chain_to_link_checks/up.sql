@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `link_checks` ADD COLUMN `final_url` VARCHAR;
+ALTER TABLE `link_checks` ADD COLUMN `downgraded` BOOL NOT NULL DEFAULT FALSE;
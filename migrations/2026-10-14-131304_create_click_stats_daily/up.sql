@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE `click_stats_daily`(
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`),
+	`day` DATE NOT NULL,
+	`clicks` BIGINT NOT NULL,
+	`visitors` BIGINT NOT NULL,
+	PRIMARY KEY(`url_id`, `day`)
+);
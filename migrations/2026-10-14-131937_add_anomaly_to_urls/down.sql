@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `urls` DROP COLUMN `anomaly_at`;
+ALTER TABLE `urls` DROP COLUMN `anomaly`;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `urls` ADD COLUMN `anomaly` VARCHAR;
+ALTER TABLE `urls` ADD COLUMN `anomaly_at` TIMESTAMP;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS `bundle_links`;
+DROP TABLE IF EXISTS `bundles`;
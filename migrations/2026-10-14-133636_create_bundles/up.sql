@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE `bundles`(
+	`id` VARCHAR NOT NULL PRIMARY KEY,
+	`title` VARCHAR NOT NULL,
+	`description` VARCHAR,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`updated_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE `bundle_links`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`bundle_id` VARCHAR NOT NULL REFERENCES `bundles`(`id`),
+	`position` INTEGER NOT NULL,
+	`title` VARCHAR NOT NULL,
+	`url` VARCHAR NOT NULL,
+	`icon` VARCHAR
+);
+
+CREATE INDEX `bundle_links_bundle_id_position` ON `bundle_links`(`bundle_id`, `position`);
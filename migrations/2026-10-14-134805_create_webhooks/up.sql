@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE `webhooks`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`url` VARCHAR NOT NULL,
+	`secret` VARCHAR NOT NULL,
+	`types` VARCHAR,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
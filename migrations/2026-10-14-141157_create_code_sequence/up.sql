@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- A single row holding the last number handed out as a sequential code.
+CREATE TABLE `code_sequence`(
+	`id` INTEGER NOT NULL PRIMARY KEY CHECK (`id` = 1),
+	`last_value` BIGINT NOT NULL
+);
+
+INSERT INTO `code_sequence` (`id`, `last_value`) VALUES (1, 0);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- Links moved out of `urls` after going unclicked for `COLD_LINK_MONTHS`, with
+-- their row and daily click counts kept as JSON until restored.
+CREATE TABLE `archived_links`(
+	`id` VARCHAR NOT NULL PRIMARY KEY,
+	`link` TEXT NOT NULL,
+	`daily_stats` TEXT NOT NULL,
+	`last_clicked_at` TIMESTAMP,
+	`archived_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
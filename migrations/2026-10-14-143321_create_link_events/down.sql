@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS `link_events_broken`;
+DROP TRIGGER IF EXISTS `link_events_quarantined`;
+DROP TRIGGER IF EXISTS `link_events_disabled`;
+DROP TRIGGER IF EXISTS `link_events_edit`;
+DROP TRIGGER IF EXISTS `link_events_destination`;
+DROP TRIGGER IF EXISTS `link_events_delete`;
+DROP TRIGGER IF EXISTS `link_events_insert`;
+DROP TRIGGER IF EXISTS `link_events_immutable`;
+DROP TABLE IF EXISTS `link_events`;
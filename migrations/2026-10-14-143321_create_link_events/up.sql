@@ -0,0 +1,66 @@
+-- Your SQL goes here
+-- Every change to a link, with where it pointed and whether it was disabled
+-- afterwards. Rows outlive their link and are never changed once written.
+CREATE TABLE `link_events`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`url_id` VARCHAR NOT NULL,
+	`event` VARCHAR NOT NULL,
+	`url` TEXT NOT NULL,
+	`title` TEXT,
+	`disabled` BOOL NOT NULL,
+	`at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX `link_events_url_id_at` ON `link_events`(`url_id`, `at`);
+
+INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`, `at`)
+SELECT `id`, 'created', `url`, `title`, `disabled`, `created_at` FROM `urls`;
+
+CREATE TRIGGER `link_events_immutable` BEFORE UPDATE ON `link_events`
+BEGIN
+	SELECT RAISE(ABORT, 'link events are append-only');
+END;
+
+-- Archived links are moved into `archived_links` before leaving `urls`, and out
+-- of it only after coming back.
+CREATE TRIGGER `link_events_insert` AFTER INSERT ON `urls`
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (NEW.`id`, CASE WHEN EXISTS (SELECT 1 FROM `archived_links` WHERE `id` = NEW.`id`) THEN 'restored' ELSE 'created' END, NEW.`url`, NEW.`title`, NEW.`disabled`);
+END;
+
+CREATE TRIGGER `link_events_delete` AFTER DELETE ON `urls`
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (OLD.`id`, CASE WHEN EXISTS (SELECT 1 FROM `archived_links` WHERE `id` = OLD.`id`) THEN 'archived' ELSE 'deleted' END, OLD.`url`, OLD.`title`, OLD.`disabled`);
+END;
+
+CREATE TRIGGER `link_events_destination` AFTER UPDATE OF `url` ON `urls` WHEN NEW.`url` IS NOT OLD.`url`
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (NEW.`id`, 'destination_changed', NEW.`url`, NEW.`title`, NEW.`disabled`);
+END;
+
+CREATE TRIGGER `link_events_edit` AFTER UPDATE OF `title`, `notes` ON `urls` WHEN NEW.`title` IS NOT OLD.`title` OR NEW.`notes` IS NOT OLD.`notes`
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (NEW.`id`, 'edited', NEW.`url`, NEW.`title`, NEW.`disabled`);
+END;
+
+CREATE TRIGGER `link_events_disabled` AFTER UPDATE OF `disabled` ON `urls` WHEN NEW.`disabled` IS NOT OLD.`disabled`
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (NEW.`id`, CASE WHEN NEW.`disabled` THEN 'disabled' ELSE 'enabled' END, NEW.`url`, NEW.`title`, NEW.`disabled`);
+END;
+
+CREATE TRIGGER `link_events_quarantined` AFTER UPDATE OF `quarantined` ON `urls` WHEN NEW.`quarantined` IS NOT OLD.`quarantined`
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (NEW.`id`, CASE WHEN NEW.`quarantined` THEN 'quarantined' ELSE 'approved' END, NEW.`url`, NEW.`title`, NEW.`disabled`);
+END;
+
+CREATE TRIGGER `link_events_broken` AFTER UPDATE OF `broken_at` ON `urls` WHEN (NEW.`broken_at` IS NULL) IS NOT (OLD.`broken_at` IS NULL)
+BEGIN
+	INSERT INTO `link_events` (`url_id`, `event`, `url`, `title`, `disabled`)
+	VALUES (NEW.`id`, CASE WHEN NEW.`broken_at` IS NULL THEN 'repaired' ELSE 'broken' END, NEW.`url`, NEW.`title`, NEW.`disabled`);
+END;
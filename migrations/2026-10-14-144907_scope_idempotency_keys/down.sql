@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE `idempotency_keys`;
+CREATE TABLE `idempotency_keys`(
+	`key` VARCHAR NOT NULL PRIMARY KEY,
+	`request_url` VARCHAR NOT NULL,
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`) ON DELETE CASCADE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Keys are only ever retried by whoever sent them first, so they're stored per
+-- caller. Stored keys are dropped; they expire within IDEMPOTENCY_KEY_TTL_SECS anyway.
+DROP TABLE `idempotency_keys`;
+CREATE TABLE `idempotency_keys`(
+	`scope` VARCHAR NOT NULL,
+	`key` VARCHAR NOT NULL,
+	`request_url` VARCHAR NOT NULL,
+	`url_id` VARCHAR NOT NULL REFERENCES `urls`(`id`) ON DELETE CASCADE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY (`scope`, `key`)
+);
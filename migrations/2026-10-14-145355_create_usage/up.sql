@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE `usage`(
+	`actor` VARCHAR NOT NULL,
+	`period` VARCHAR NOT NULL,
+	`links_created` BIGINT NOT NULL DEFAULT 0,
+	`redirects` BIGINT NOT NULL DEFAULT 0,
+	PRIMARY KEY (`actor`, `period`)
+);
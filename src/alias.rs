@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State}, response::{IntoResponse, Response}, Extension
+};
+use deadpool_diesel::sqlite;
+use serde::Serialize;
+
+use crate::bundles;
+use crate::confusables;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html;
+use crate::links;
+use crate::negotiate::{Format, Representation};
+use crate::scan;
+use crate::telemetry;
+use crate::AppConfig;
+
+const MAX_ALIAS_LENGTH: usize = 64;
+/// Segments a vanity path such as `go/product/launch` may have.
+const MAX_SEGMENTS: usize = 5;
+
+/// Path prefixes of compatibility APIs, which only clash with aliases that have
+/// more segments, e.g. `v4/shorten`.
+const API_PREFIXES: [&str; 2] = ["rest", "v4"];
+
+/// Pages every link has below its code, which `<code>/<page>` would shadow.
+const LINK_PAGES: [&str; 2] = ["screenshot", "stats"];
+
+/// Path prefixes used by the service itself, which can never be handed out as aliases.
+pub const BUILTIN_RESERVED: [&str; 3] = ["api", "badge", "url"];
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Availability {
+    Free,
+    Reserved,
+    Taken,
+    Invalid,
+    /// Mixes scripts or imitates Latin letters, as used to spoof other links.
+    Confusable
+}
+
+#[derive(Serialize)]
+pub struct AvailabilityResponse {
+    alias: String,
+    status: Availability
+}
+
+impl Availability {
+    fn as_str(self) -> &'static str {
+        match self {
+            Availability::Free => "free",
+            Availability::Reserved => "reserved",
+            Availability::Taken => "taken",
+            Availability::Invalid => "invalid",
+            Availability::Confusable => "confusable"
+        }
+    }
+}
+
+impl Representation for AvailabilityResponse {
+    fn to_text(&self) -> String {
+        self.status.as_str().into()
+    }
+
+    fn to_html(&self) -> String {
+        format!(
+            "<span class=\"alias-{}\"><code>{}</code> is {}</span>",
+            self.status.as_str(),
+            html::escape(&self.alias),
+            self.status.as_str()
+        )
+    }
+}
+
+/// Aliases may use letters and digits of any script, `-`, `_` and anything in the
+/// id alphabet, in up to [`MAX_SEGMENTS`] segments separated by `/`. Letters that
+/// could spoof other aliases are caught by [`precheck`].
+pub fn is_valid(config: &AppConfig, alias: &str) -> bool {
+    alias.split('/').count() <= MAX_SEGMENTS
+        && alias.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment.chars().count() <= MAX_ALIAS_LENGTH
+                && segment.chars().all(|c| {
+                    c.is_alphanumeric() || c == '-' || c == '_' || config.nano_id_alphabet.contains(&c)
+                })
+        })
+}
+
+/// Whether a multi-segment path starts below a prefix of the service, e.g. `api/…`.
+pub fn is_reserved_prefix(config: &AppConfig, path: &str) -> bool {
+    let Some((first, _)) = path.split_once('/') else {
+        return false
+    };
+    BUILTIN_RESERVED.iter()
+        .chain(API_PREFIXES.iter())
+        .copied()
+        .chain(config.reserved_aliases.iter().map(String::as_str))
+        .any(|reserved| reserved.eq_ignore_ascii_case(first))
+}
+
+pub fn is_reserved(config: &AppConfig, alias: &str) -> bool {
+    let shadowed_page = alias.split_once('/')
+        .is_some_and(|(_, page)| LINK_PAGES.contains(&page));
+    shadowed_page
+        || is_reserved_prefix(config, alias)
+        || scan::is_honeypot(config, alias)
+        || BUILTIN_RESERVED.iter()
+            .copied()
+            .chain(config.reserved_aliases.iter().map(String::as_str))
+            .any(|reserved| reserved.eq_ignore_ascii_case(alias))
+}
+
+/// Checks everything about an alias that doesn't need the database.
+pub fn precheck(config: &AppConfig, alias: &str) -> Option<Availability> {
+    // An alias shaped like a generated code must carry a valid check character to be reachable.
+    if !is_valid(config, alias) || !links::passes_checks(config, alias) {
+        Some(Availability::Invalid)
+    } else if confusables::is_confusable(alias) {
+        Some(Availability::Confusable)
+    } else if is_reserved(config, alias) {
+        Some(Availability::Reserved)
+    } else {
+        None
+    }
+}
+
+pub async fn check_availability(
+    Path(alias): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    format: Format
+) -> Result<Response, ApiError> {
+    let alias = links::normalize_code(&config, alias);
+    if let Some(status) = precheck(&config, &alias) {
+        return Ok(format.respond(AvailabilityResponse { alias, status }).into_response())
+    }
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let lookup = alias.clone();
+    let taken = conn.interact(move |conn| bundles::code_taken(conn, &lookup))
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let status = if taken { Availability::Taken } else { Availability::Free };
+    let mut response = format.respond(AvailabilityResponse { alias, status }).into_response();
+    // Probing free codes is how the namespace is enumerated, so they count against the scan limit.
+    if !taken {
+        response.extensions_mut().insert(scan::Miss);
+    }
+    Ok(response)
+}
@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::{BigInt, Text, Timestamp}
+};
+use metrics::counter;
+use serde::Serialize;
+
+use crate::schema::urls;
+use crate::telemetry;
+
+/// The span of recent clicks each analysis looks at.
+const WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+/// The span before the window that click rates are compared against.
+const BASELINE: chrono::Duration = chrono::Duration::days(7);
+
+/// Fewer clicks in the window than this are never flagged, however they're spread.
+const MIN_CLICKS: i64 = 100;
+
+/// How many times its usual hourly rate a link must be clicked to count as spiking.
+const SPIKE_FACTOR: i64 = 10;
+
+/// The share of a link's recent clicks one visitor must make to count as flooding it.
+const FLOOD_SHARE_PERCENT: i64 = 50;
+
+/// How long a flag stays after the last window it was found in.
+const FLAG_DURATION: chrono::Duration = chrono::Duration::days(1);
+
+/// What made a link's clicks look abnormal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Anomaly {
+    /// Far more clicks than the link usually gets.
+    Spike,
+    /// Most clicks from a single visitor, i.e. one address and browser.
+    Flood
+}
+
+impl Anomaly {
+    fn name(self) -> &'static str {
+        match self {
+            Anomaly::Spike => "spike",
+            Anomaly::Flood => "flood"
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct RecentClicks {
+    #[diesel(sql_type = Text)]
+    url_id: String,
+    #[diesel(sql_type = BigInt)]
+    clicks: i64,
+    /// Clicks in the baseline period before the window.
+    #[diesel(sql_type = BigInt)]
+    baseline: i64,
+    /// Clicks of the visitor who clicked most in the window.
+    #[diesel(sql_type = BigInt)]
+    top_visitor: i64
+}
+
+/// A link currently flagged, for the admin overview.
+#[derive(Serialize, Queryable, Selectable)]
+#[diesel(table_name = urls)]
+pub struct FlaggedLink {
+    id: String,
+    url: String,
+    anomaly: Option<String>,
+    anomaly_at: Option<NaiveDateTime>
+}
+
+/// Whether `recent` clicks look abnormal. A flood is reported over a spike, as
+/// it's the more specific finding.
+fn classify(recent: &RecentClicks) -> Option<Anomaly> {
+    if recent.clicks < MIN_CLICKS {
+        return None
+    }
+    if recent.top_visitor * 100 >= recent.clicks * FLOOD_SHARE_PERCENT {
+        return Some(Anomaly::Flood)
+    }
+    let hours = BASELINE.num_hours();
+    // Links that weren't clicked before spike once they reach the minimum.
+    (recent.clicks * hours >= recent.baseline * SPIKE_FACTOR).then_some(Anomaly::Spike)
+}
+
+/// Starts analysing recent clicks every `interval`, flagging links with abnormal
+/// patterns and clearing flags a day after they were last seen.
+pub fn spawn(pool: sqlite::Pool, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(err) = analyse(&pool).await {
+                println!("Click anomaly detection failed: {err}");
+            }
+        }
+    });
+}
+
+async fn analyse(pool: &sqlite::Pool) -> Result<(), String> {
+    let now = Utc::now().naive_utc();
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    let flagged = conn.interact(move |conn| {
+        let window_start = now - WINDOW;
+        let recent: Vec<RecentClicks> = diesel::sql_query(
+            "SELECT url_id, COUNT(*) AS clicks, \
+             (SELECT COUNT(*) FROM clicks b WHERE b.url_id = c.url_id AND b.clicked_at >= ? AND b.clicked_at < ?) AS baseline, \
+             COALESCE((SELECT COUNT(*) AS n FROM clicks v WHERE v.url_id = c.url_id AND v.clicked_at >= ? AND v.visitor IS NOT NULL \
+              GROUP BY v.visitor ORDER BY n DESC LIMIT 1), 0) AS top_visitor \
+             FROM clicks c WHERE clicked_at >= ? GROUP BY url_id HAVING clicks >= ?"
+        )
+        .bind::<Timestamp, _>(window_start - BASELINE)
+        .bind::<Timestamp, _>(window_start)
+        .bind::<Timestamp, _>(window_start)
+        .bind::<Timestamp, _>(window_start)
+        .bind::<BigInt, _>(MIN_CLICKS)
+        .load(conn)?;
+
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            let mut flagged = Vec::new();
+            for recent in &recent {
+                let Some(anomaly) = classify(recent) else {
+                    continue
+                };
+                let previous: Option<Option<String>> = urls::table
+                    .find(&recent.url_id)
+                    .select(urls::anomaly)
+                    .get_result(conn)
+                    .optional()?;
+                diesel::update(urls::table.find(&recent.url_id))
+                    .set((urls::anomaly.eq(anomaly.name()), urls::anomaly_at.eq(now)))
+                    .execute(conn)?;
+                // Links staying abnormal are only reported when first flagged.
+                if previous.flatten().as_deref() != Some(anomaly.name()) {
+                    flagged.push((recent.url_id.clone(), anomaly));
+                }
+            }
+            diesel::update(urls::table)
+                .filter(urls::anomaly_at.lt(now - FLAG_DURATION))
+                .set((urls::anomaly.eq(None::<String>), urls::anomaly_at.eq(None::<NaiveDateTime>)))
+                .execute(conn)?;
+            Ok(flagged)
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    for (id, anomaly) in flagged {
+        counter!("click_anomalies_total", "kind" => anomaly.name()).increment(1);
+        println!("Abnormal clicks on {id}: {}", anomaly.name());
+    }
+    Ok(())
+}
+
+/// The links currently flagged, most recently seen first.
+pub fn flagged_links(conn: &mut SqliteConnection) -> QueryResult<Vec<FlaggedLink>> {
+    urls::table
+        .filter(urls::anomaly.is_not_null())
+        .order(urls::anomaly_at.desc())
+        .select(FlaggedLink::as_select())
+        .load(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recent(clicks: i64, baseline: i64, top_visitor: i64) -> RecentClicks {
+        RecentClicks { url_id: "abc".into(), clicks, baseline, top_visitor }
+    }
+
+    #[test]
+    fn classifies_click_patterns() {
+        // 168 hours at 100 clicks each make a usual hour of 100.
+        assert_eq!(classify(&recent(1000, 16_800, 10)), Some(Anomaly::Spike));
+        assert_eq!(classify(&recent(900, 16_800, 10)), None);
+        assert_eq!(classify(&recent(200, 16_800, 150)), Some(Anomaly::Flood));
+        assert_eq!(classify(&recent(100, 0, 1)), Some(Anomaly::Spike));
+        assert_eq!(classify(&recent(99, 0, 99)), None);
+    }
+}
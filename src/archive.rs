@@ -0,0 +1,121 @@
+use std::{
+    sync::OnceLock, time::{Duration, Instant}
+};
+
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use metrics::counter;
+use reqwest::header;
+use tokio::sync::mpsc;
+
+use crate::schema::urls;
+use crate::ssrf;
+use crate::telemetry;
+
+const SAVE_ENDPOINT: &str = "https://web.archive.org/save/";
+const SNAPSHOT_PREFIX: &str = "https://web.archive.org/web/";
+
+/// Links waiting to be archived; beyond this, new ones are skipped rather than queued.
+const QUEUE_SIZE: usize = 1000;
+
+/// Failures are counted in metrics and logged at most this often, so an outage
+/// doesn't print a line per link.
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Saving a page can take the Internet Archive a while.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+struct Job {
+    id: String,
+    url: String
+}
+
+static QUEUE: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+/// Starts submitting new destinations to the Wayback Machine one at a time,
+/// at most one every `interval`, storing each snapshot's URL on its link.
+/// Destinations on internal addresses are skipped unless `allow_private` is set.
+pub fn spawn(pool: sqlite::Pool, interval: Duration, allow_private: bool) {
+    let (sender, mut jobs) = mpsc::channel(QUEUE_SIZE);
+    if QUEUE.set(sender).is_err() {
+        return
+    }
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("HTTP client can be built");
+
+    tokio::spawn(async move {
+        let mut last_report: Option<Instant> = None;
+        let mut unreported = 0;
+        while let Some(job) = jobs.recv().await {
+            let archived = match save(&client, &job.url, allow_private).await {
+                Ok(snapshot) => store(&pool, job.id, snapshot).await,
+                Err(err) => Err(format!("archiving {} failed: {err}", job.url))
+            };
+            match archived {
+                Ok(()) => counter!("archive_snapshots_total").increment(1),
+                Err(err) => {
+                    counter!("archive_failures_total").increment(1);
+                    unreported += 1;
+                    if last_report.is_none_or(|reported| reported.elapsed() >= REPORT_INTERVAL) {
+                        println!("Wayback Machine archiving: {err} ({unreported} failures since the last report)");
+                        last_report = Some(Instant::now());
+                        unreported = 0;
+                    }
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Queues a new link for archiving, if archiving is enabled.
+pub fn enqueue(id: &str, url: &str) {
+    let Some(queue) = QUEUE.get() else {
+        return
+    };
+    let job = Job { id: id.to_owned(), url: url.to_owned() };
+    if queue.try_send(job).is_err() {
+        counter!("archive_skipped_total").increment(1);
+    }
+}
+
+/// Asks the Wayback Machine to save `url` and returns the snapshot's address.
+async fn save(client: &reqwest::Client, url: &str, allow_private: bool) -> Result<String, String> {
+    // The host may have been pointed at an internal address since the link was created.
+    if !allow_private {
+        ssrf::check(url).await?;
+    }
+    let response = client.get(format!("{SAVE_ENDPOINT}{url}"))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?
+        .error_for_status()
+        .map_err(|err| err.to_string())?;
+
+    // The snapshot is announced in Content-Location, or reached by following redirects.
+    let location = response.headers()
+        .get(header::CONTENT_LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(|location| format!("https://web.archive.org{location}"));
+    location
+        .or_else(|| Some(response.url().to_string()))
+        .filter(|snapshot| snapshot.starts_with(SNAPSHOT_PREFIX))
+        .ok_or_else(|| "no snapshot in the response".into())
+}
+
+async fn store(pool: &sqlite::Pool, id: String, snapshot: String) -> Result<(), String> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| format!("can't store snapshot: {err}"))?;
+    conn.interact(move |conn| {
+        diesel::update(urls::table.find(id))
+            .set(urls::archive_url.eq(snapshot))
+            .execute(conn)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Query, State}, Json
+};
+use chrono::NaiveDateTime;
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::schema::audit_log;
+use crate::telemetry;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = audit_log)]
+pub struct AuditEntry {
+    id: i32,
+    actor: Option<String>,
+    action: String,
+    target: Option<String>,
+    ip: Option<String>,
+    created_at: NaiveDateTime
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditEntry<'a> {
+    pub actor: Option<&'a str>,
+    pub action: &'a str,
+    pub target: Option<&'a str>,
+    pub ip: Option<&'a str>
+}
+
+pub fn record(conn: &mut SqliteConnection, entry: NewAuditEntry) -> QueryResult<()> {
+    diesel::insert_into(audit_log::table)
+        .values(entry)
+        .execute(conn)
+        .map(|_| ())
+}
+
+#[derive(Deserialize)]
+pub struct AuditLogQuery {
+    action: Option<String>,
+    actor: Option<String>,
+    target: Option<String>,
+    before: Option<i32>,
+    limit: Option<i64>
+}
+
+pub async fn list_audit_log(
+    Query(query): Query<AuditLogQuery>,
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let entries = conn.interact(move |conn| {
+        let mut statement = audit_log::table
+            .select(AuditEntry::as_select())
+            .order(audit_log::id.desc())
+            .limit(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+            .into_boxed();
+
+        if let Some(action) = query.action {
+            statement = statement.filter(audit_log::action.eq(action));
+        }
+        if let Some(actor) = query.actor {
+            statement = statement.filter(audit_log::actor.eq(actor));
+        }
+        if let Some(target) = query.target {
+            statement = statement.filter(audit_log::target.eq(target));
+        }
+        if let Some(before) = query.before {
+            statement = statement.filter(audit_log::id.lt(before));
+        }
+
+        statement.load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(entries))
+}
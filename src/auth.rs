@@ -0,0 +1,238 @@
+use std::{
+    convert::Infallible, net::{IpAddr, SocketAddr}, sync::Arc
+};
+
+use axum::{
+    async_trait, extract::{ConnectInfo, FromRequestParts, OriginalUri}, http::{header, request::Parts, Method, StatusCode}
+};
+use deadpool_diesel::sqlite;
+use metrics::counter;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::captcha;
+use crate::errors::{self, ApiError, internal_error, unavailable};
+use crate::security_log;
+use crate::telemetry;
+use crate::tokens::{self, ApiToken, Scope};
+use crate::AppConfig;
+
+/// Actor recorded in the audit log for requests authenticated with the admin token.
+pub const ADMIN_ACTOR: &str = "admin";
+
+/// Who a request was authenticated as by the route policy, as it shows up in
+/// the audit log: `admin` for `ADMIN_TOKEN`, `token:<id>` for an API token, or
+/// nobody on routes `ROUTE_POLICY` makes public.
+#[derive(Clone, Debug, Default)]
+pub struct Actor(pub Option<String>);
+
+impl Actor {
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Actor>().cloned().unwrap_or_default())
+    }
+}
+
+/// Lets requests through with `ADMIN_TOKEN`, or an API token with the `admin`
+/// scope. Tokens with only the `read` scope may use `GET` endpoints.
+pub async fn check_admin(pool: &sqlite::Pool, config: &AppConfig, ip: IpAddr, req: &Parts) -> Result<Actor, ApiError> {
+    let Some(admin_token) = config.admin_token.as_deref() else {
+        return Err(
+            errors::coded("admin_disabled", (StatusCode::FORBIDDEN, "Admin API is disabled".into()))
+        )
+    };
+
+    let read_only = req.method == Method::GET || req.method == Method::HEAD;
+    let authorized = match request_token(req) {
+        Some(token) if constant_time_eq(token.as_bytes(), admin_token.as_bytes()) => Some((true, ADMIN_ACTOR.into())),
+        Some(token) => api_token(pool, token)
+            .await?
+            .map(|token| (token.allows(Scope::Admin) || (read_only && token.allows(Scope::Read)), token.actor())),
+        None => None
+    };
+
+    match authorized {
+        Some((true, actor)) => Ok(Actor(Some(actor))),
+        Some((false, _)) => Err(
+            errors::coded("scope_missing", (StatusCode::FORBIDDEN, "Token lacks the scope for this endpoint".into()))
+        ),
+        None => {
+            security_log::record(&config.security_log, "auth_failure", ip, request_path(req));
+            Err(
+                errors::coded("token_invalid", (StatusCode::UNAUTHORIZED, "Invalid admin token".into()))
+            )
+        }
+    }
+}
+
+/// Lets requests through with `ADMIN_TOKEN` or any valid API token, whatever its scopes.
+pub async fn check_key(pool: &sqlite::Pool, config: &AppConfig, ip: IpAddr, req: &Parts) -> Result<Actor, ApiError> {
+    let authorized = match request_token(req) {
+        Some(token) if config.admin_token.as_deref().is_some_and(|admin_token| constant_time_eq(token.as_bytes(), admin_token.as_bytes())) => Some(ADMIN_ACTOR.into()),
+        Some(token) => api_token(pool, token).await?.map(|token| token.actor()),
+        None => None
+    };
+    if let Some(actor) = authorized {
+        return Ok(Actor(Some(actor)))
+    }
+    security_log::record(&config.security_log, "auth_failure", ip, request_path(req));
+    Err(
+        errors::coded("token_required", (StatusCode::UNAUTHORIZED, "An API token is required".into()))
+    )
+}
+
+fn request_token(req: &Parts) -> Option<String> {
+    req.headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(presented_token)
+}
+
+/// The path as requested; nested routers see theirs with the prefix stripped.
+fn request_path(req: &Parts) -> &str {
+    req.extensions
+        .get::<OriginalUri>()
+        .map_or(req.uri.path(), |uri| uri.path())
+}
+
+/// Who is shortening a link: the API token presented, which must have the `create`
+/// or `admin` scope, or nobody for anonymous creation where that's allowed, once
+/// they've solved the CAPTCHA if one is configured.
+pub struct Creator {
+    /// How the creator shows up in the audit log.
+    pub actor: Option<String>,
+    pub ip: IpAddr
+}
+
+#[async_trait]
+impl FromRequestParts<sqlite::Pool> for Creator {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, pool: &sqlite::Pool) -> Result<Self, Self::Rejection> {
+        let config = parts.extensions
+            .get::<Arc<AppConfig>>()
+            .cloned()
+            .expect("config is injected into every request");
+        let ip = parts.extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+            .expect("connect info is served with every request");
+        let presented = parts.headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|authorization| presented_token(authorization).unwrap_or_default());
+
+        let Some(token) = presented else {
+            if config.create_requires_token {
+                return Err(
+                    errors::coded("token_required", (StatusCode::UNAUTHORIZED, "An API token with the create scope is required".into()))
+                )
+            }
+            if let Some(captcha) = config.captcha.as_ref() {
+                if config.trusts(ip) {
+                    counter!("trusted_network_exemptions_total", "limit" => "captcha").increment(1);
+                    security_log::record(&config.security_log, "exempted", ip, request_path(parts));
+                } else {
+                    let response = parts.headers
+                        .get(captcha::RESPONSE_HEADER)
+                        .and_then(|value| value.to_str().ok());
+                    captcha.verify(response, ip).await?;
+                }
+            }
+            return Ok(Creator { actor: None, ip })
+        };
+        if config.admin_token.as_deref().is_some_and(|admin_token| constant_time_eq(token.as_bytes(), admin_token.as_bytes())) {
+            return Ok(Creator { actor: Some(ADMIN_ACTOR.into()), ip })
+        }
+        match api_token(pool, token).await? {
+            Some(token) if token.allows(Scope::Create) || token.allows(Scope::Admin) => {
+                Ok(Creator { actor: Some(token.actor()), ip })
+            }
+            Some(_) => Err(
+                errors::coded("scope_missing", (StatusCode::FORBIDDEN, "Token lacks the create scope".into()))
+            ),
+            None => {
+                let path = parts.extensions
+                    .get::<OriginalUri>()
+                    .map_or(parts.uri.path(), |uri| uri.path());
+                security_log::record(&config.security_log, "auth_failure", ip, path);
+                Err(
+                    errors::coded("token_invalid", (StatusCode::UNAUTHORIZED, "Invalid API token".into()))
+                )
+            }
+        }
+    }
+}
+
+async fn api_token(pool: &sqlite::Pool, token: String) -> Result<Option<ApiToken>, ApiError> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(unavailable)?;
+    conn.interact(move |conn| tokens::authenticate(conn, &token))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)
+}
+
+/// Accepts the token as a bearer token, or as the Basic auth password for clients
+/// such as feed readers that can't send custom headers.
+fn presented_token(authorization: &str) -> Option<String> {
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return Some(token.to_owned())
+    }
+
+    let credentials = STANDARD.decode(authorization.strip_prefix("Basic ")?).ok()?;
+    let credentials = String::from_utf8(credentials).ok()?;
+    credentials.split_once(':')
+        .map(|(_, password)| password.to_owned())
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false
+    }
+    a.iter()
+        .zip(b)
+        .fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_bearer_tokens() {
+        assert_eq!(presented_token("Bearer s3cret").as_deref(), Some("s3cret"));
+    }
+
+    #[test]
+    fn accepts_the_basic_auth_password() {
+        let credentials = STANDARD.encode("feeds:s3cret:with-colon");
+        assert_eq!(presented_token(&format!("Basic {credentials}")).as_deref(), Some("s3cret:with-colon"));
+        let credentials = STANDARD.encode(":s3cret");
+        assert_eq!(presented_token(&format!("Basic {credentials}")).as_deref(), Some("s3cret"));
+    }
+
+    #[test]
+    fn rejects_malformed_credentials() {
+        assert_eq!(presented_token("s3cret"), None);
+        assert_eq!(presented_token("Basic not base64!"), None);
+        assert_eq!(presented_token(&format!("Basic {}", STANDARD.encode("no-colon"))), None);
+        assert_eq!(presented_token(&format!("Basic {}", STANDARD.encode([0xff, b':', 0xfe]))), None);
+    }
+
+    #[test]
+    fn compares_tokens_exactly() {
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3creT"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cret!"));
+    }
+}
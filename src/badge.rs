@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State}, http::{header, StatusCode}, response::IntoResponse, Extension
+};
+use deadpool_diesel::sqlite;
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::stats;
+use crate::telemetry;
+use crate::AppConfig;
+
+const LABEL: &str = "clicks";
+
+/// Rough width of a character in 11px Verdana, which is all shields.io-style badges need.
+const CHAR_WIDTH: usize = 7;
+const PADDING: usize = 10;
+
+pub async fn click_badge(
+    Path(file): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(id) = file.strip_suffix(".svg").map(|id| links::normalize_code(&config, id.into())) else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Badge not found".into()).into()
+        )
+    };
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let total = conn.interact(move |conn| {
+        stats::public_total_clicks(conn, &id)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    // Badges expose click counts, so they follow the same visibility as the stats page.
+    let Some(total) = total else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Badge not found".into()).into()
+        )
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "max-age=300")
+        ],
+        render(LABEL, &format_count(total))
+    ))
+}
+
+fn format_count(count: i64) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1_000..=999_999 => format!("{:.1}k", count as f64 / 1e3),
+        _ => format!("{:.1}M", count as f64 / 1e6)
+    }
+}
+
+fn render(label: &str, value: &str) -> String {
+    let label_width = label.len() * CHAR_WIDTH + PADDING;
+    let value_width = value.len() * CHAR_WIDTH + PADDING;
+    let width = label_width + value_width;
+    let label_x = label_width / 2;
+    let value_x = label_width + value_width / 2;
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {value}">
+<title>{label}: {value}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)">
+<rect width="{label_width}" height="20" fill="#555"/>
+<rect x="{label_width}" width="{value_width}" height="20" fill="#4c1"/>
+<rect width="{width}" height="20" fill="url(#s)"/>
+</g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text>
+<text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="15" fill="#010101" fill-opacity=".3">{value}</text>
+<text x="{value_x}" y="14">{value}</text>
+</g>
+</svg>
+"##
+    )
+}
@@ -0,0 +1,222 @@
+use std::{
+    sync::{atomic::{AtomicUsize, Ordering}, Arc},
+    time::{Duration, Instant}
+};
+
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use reqwest::redirect;
+use tokio::task::JoinSet;
+
+use crate::schema::urls;
+
+/// Codes requested, drawn from the live links in the database.
+const MAX_CODES: i64 = 10_000;
+
+struct BenchOptions {
+    target: String,
+    requests: usize,
+    creations: usize,
+    concurrency: usize,
+    token: Option<String>
+}
+
+fn parse_count(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    value
+        .map(|value| value.replace('_', ""))
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{flag} expects a number"))
+}
+
+fn parse_options(args: &[String]) -> Result<BenchOptions, String> {
+    let mut options = BenchOptions {
+        target: "http://127.0.0.1:3000".into(),
+        requests: 10_000,
+        creations: 1_000,
+        concurrency: 32,
+        token: None
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--target" => options.target = args.next().ok_or("--target expects a URL")?.trim_end_matches('/').to_owned(),
+            "--requests" => options.requests = parse_count(flag, args.next())?,
+            "--creations" => options.creations = parse_count(flag, args.next())?,
+            "--concurrency" => options.concurrency = parse_count(flag, args.next())?.max(1),
+            "--token" => options.token = args.next().cloned(),
+            _ => return Err(
+                format!("Unknown bench option {flag}; expected --target, --requests, --creations, --concurrency or --token")
+            )
+        }
+    }
+    Ok(options)
+}
+
+/// Latencies of one phase, sorted, along with how long it took overall.
+struct Measured {
+    latencies: Vec<Duration>,
+    errors: usize,
+    elapsed: Duration
+}
+
+/// The latency below which `quantile` of the sorted `latencies` fall.
+fn percentile(latencies: &[Duration], quantile: f64) -> Duration {
+    if latencies.is_empty() {
+        return Duration::ZERO
+    }
+    let index = ((latencies.len() as f64 * quantile).ceil() as usize).clamp(1, latencies.len()) - 1;
+    latencies[index]
+}
+
+impl Measured {
+    fn render(&self, phase: &str) -> String {
+        let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+        let throughput = self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON);
+        format!(
+            "{phase:<8}  {:>7} requests  {:>5} errors  {throughput:>9.0} req/s  p50 {:.2} ms  p90 {:.2} ms  p99 {:.2} ms  max {:.2} ms",
+            self.latencies.len(),
+            self.errors,
+            ms(percentile(&self.latencies, 0.5)),
+            ms(percentile(&self.latencies, 0.9)),
+            ms(percentile(&self.latencies, 0.99)),
+            ms(self.latencies.last().copied().unwrap_or_default())
+        )
+    }
+}
+
+/// Runs `requests` calls of `call` on `concurrency` tasks, timing each.
+async fn measure<F, Fut>(requests: usize, concurrency: usize, call: F) -> Measured
+where
+    F: Fn(usize) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = bool> + Send
+{
+    let call = Arc::new(call);
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let mut tasks = JoinSet::new();
+    for _ in 0..concurrency.min(requests.max(1)) {
+        let (call, next) = (call.clone(), next.clone());
+        tasks.spawn(async move {
+            let (mut latencies, mut errors) = (Vec::new(), 0);
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= requests {
+                    return (latencies, errors)
+                }
+                let sent = Instant::now();
+                if call(index).await {
+                    latencies.push(sent.elapsed());
+                } else {
+                    errors += 1;
+                }
+            }
+        });
+    }
+    let mut measured = Measured { latencies: Vec::new(), errors: 0, elapsed: Duration::ZERO };
+    while let Some(Ok((latencies, errors))) = tasks.join_next().await {
+        measured.latencies.extend(latencies);
+        measured.errors += errors;
+    }
+    measured.elapsed = started.elapsed();
+    measured.latencies.sort();
+    measured
+}
+
+/// Measures the redirect hot path: the lookup alone, as a pool checkout and an
+/// `interact` call running the redirect query, then redirects and link creation
+/// over HTTP against a running server. Redirects count clicks and creation adds
+/// links, so run it against a database filled by `shorturl seed`, not production.
+pub async fn run(pool: sqlite::Pool, args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let conn = pool.get()
+        .await
+        .map_err(|err| err.to_string())?;
+    let codes: Vec<String> = conn.interact(|conn| {
+        urls::table
+            .filter(urls::disabled.eq(false))
+            .filter(urls::quarantined.eq(false))
+            .select(urls::id)
+            .order(diesel::dsl::sql::<diesel::sql_types::Integer>("RANDOM()"))
+            .limit(MAX_CODES)
+            .load(conn)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+    drop(conn);
+    if codes.is_empty() {
+        return Err("The database holds no live links; fill it with shorturl seed first".into())
+    }
+    let codes = Arc::new(codes);
+
+    let lookup = {
+        let (pool, codes) = (pool.clone(), codes.clone());
+        measure(options.requests, options.concurrency, move |index| {
+            let (pool, code) = (pool.clone(), codes[index % codes.len()].clone());
+            async move {
+                let Ok(conn) = pool.get().await else {
+                    return false
+                };
+                conn.interact(move |conn| urls::table.find(code).select(urls::url).first::<String>(conn))
+                    .await
+                    .is_ok_and(|found| found.is_ok())
+            }
+        })
+        .await
+    };
+    println!("{}", lookup.render("lookup"));
+
+    let client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .pool_max_idle_per_host(options.concurrency)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let redirects = {
+        let (client, codes, target) = (client.clone(), codes.clone(), options.target.clone());
+        measure(options.requests, options.concurrency, move |index| {
+            let request = client.get(format!("{target}/{}", codes[index % codes.len()]));
+            async move {
+                request.send()
+                    .await
+                    .is_ok_and(|response| response.status().is_redirection() || response.status().is_success())
+            }
+        })
+        .await
+    };
+    println!("{}", redirects.render("redirect"));
+
+    let run_id = nanoid::nanoid!(8);
+    let creations = {
+        let (target, token) = (options.target.clone(), options.token.clone());
+        measure(options.creations, options.concurrency, move |index| {
+            let mut request = client.get(format!("{target}/api/v1/shorten"))
+                .query(&[("url", format!("https://bench.example/{run_id}/{index}"))]);
+            if let Some(token) = token.as_deref() {
+                request = request.bearer_auth(token);
+            }
+            async move {
+                request.send()
+                    .await
+                    .is_ok_and(|response| response.status().is_success())
+            }
+        })
+        .await
+    };
+    println!("{}", creations.render("create"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_nearest_rank_percentiles() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&latencies, 0.5), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&latencies, 1.0), Duration::from_millis(100));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+}
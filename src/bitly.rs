@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State}, http::{header, HeaderMap, StatusCode}, response::{IntoResponse, Response}, Extension, Json
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::auth::Creator;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::idempotency;
+use crate::links::{self, CreateLink};
+use crate::schema::{clicks, urls};
+use crate::stats;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// Errors answered the way Bitly does, which its SDKs parse for `message`.
+pub struct BitlyError(StatusCode, String);
+
+impl From<(StatusCode, String)> for BitlyError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        BitlyError(status, message)
+    }
+}
+
+impl From<ApiError> for BitlyError {
+    fn from(err: ApiError) -> Self {
+        BitlyError(err.status, err.message)
+    }
+}
+
+impl IntoResponse for BitlyError {
+    fn into_response(self) -> Response {
+        let BitlyError(status, description) = self;
+        let message = status.canonical_reason()
+            .unwrap_or("ERROR")
+            .to_ascii_uppercase()
+            .replace(' ', "_");
+        (status, Json(json!({ "message": message, "description": description, "resource": "bitlinks" }))).into_response()
+    }
+}
+
+/// Bitly's spelling of timestamps, e.g. `2024-05-01T12:30:00+0000`.
+fn timestamp(at: NaiveDateTime) -> String {
+    at.and_utc().format("%Y-%m-%dT%H:%M:%S%z").to_string()
+}
+
+/// A short URL without its scheme, which is how Bitly identifies links.
+fn bitlink_id(config: &AppConfig, id: &str) -> String {
+    let short_url = links::short_url(config, id);
+    match short_url.split_once("://") {
+        Some((_, bitlink)) => bitlink.to_owned(),
+        None => short_url
+    }
+}
+
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = urls)]
+struct Link {
+    id: String,
+    url: String,
+    disabled: bool,
+    title: Option<String>,
+    created_at: NaiveDateTime,
+    created_by: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Bitlink {
+    id: String,
+    link: String,
+    long_url: String,
+    title: Option<String>,
+    archived: bool,
+    created_at: String,
+    created_by: Option<String>,
+    custom_bitlinks: Vec<String>,
+    tags: Vec<String>,
+    deeplinks: Vec<String>,
+    references: serde_json::Map<String, serde_json::Value>
+}
+
+impl Bitlink {
+    fn new(config: &AppConfig, link: Link) -> Self {
+        Bitlink {
+            id: bitlink_id(config, &link.id),
+            link: links::short_url(config, &link.id),
+            long_url: link.url,
+            title: link.title,
+            archived: link.disabled,
+            created_at: timestamp(link.created_at),
+            created_by: link.created_by,
+            custom_bitlinks: Vec::new(),
+            tags: Vec::new(),
+            deeplinks: Vec::new(),
+            references: Default::default()
+        }
+    }
+}
+
+/// The body of `POST /v4/shorten` and `POST /v4/bitlinks`. Bitly's `domain` and
+/// `group_guid` are accepted but have no meaning here.
+#[derive(Deserialize)]
+pub struct ShortenRequest {
+    long_url: String,
+    title: Option<String>
+}
+
+async fn load(pool: &sqlite::Pool, id: String) -> Result<Option<Link>, ApiError> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(unavailable)?;
+    conn.interact(move |conn| {
+        urls::table
+            .find(id)
+            .select(Link::as_select())
+            .get_result(conn)
+            .optional()
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)
+}
+
+pub async fn shorten(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    creator: Creator,
+    headers: HeaderMap,
+    Json(request): Json<ShortenRequest>
+) -> Result<(StatusCode, Json<Bitlink>), BitlyError> {
+    let created = links::create(&pool, &config, CreateLink {
+        url: request.long_url,
+        alias: None,
+        public_stats: false,
+        publish_at: None,
+        title: request.title,
+        notes: None,
+        id_strategy: None,
+        idempotency_key: idempotency::key_from_headers(&headers)?,
+        actor: creator.actor,
+        ip: Some(creator.ip.to_string())
+    })
+    .await?;
+
+    let Some(link) = load(&pool, created.id).await? else {
+        return Err(
+            (StatusCode::INTERNAL_SERVER_ERROR, "Link vanished after creation".to_owned()).into()
+        )
+    };
+    Ok((StatusCode::CREATED, Json(Bitlink::new(&config, link))))
+}
+
+#[derive(Deserialize)]
+pub struct ClicksQuery {
+    unit: Option<String>,
+    /// How many units back to count, with `-1`, the default, counting every click.
+    units: Option<i64>
+}
+
+/// `GET /v4/bitlinks/{bitlink}` and `GET /v4/bitlinks/{bitlink}/clicks/summary`,
+/// where `bitlink` is the short URL without its scheme, e.g. `sho.rt/abc`. Only
+/// the code at its end is looked at.
+pub async fn bitlink(
+    Path(path): Path<String>,
+    Query(query): Query<ClicksQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Response, BitlyError> {
+    let (bitlink, summary) = match path.strip_suffix("/clicks/summary") {
+        Some(bitlink) => (bitlink, true),
+        None => (path.as_str(), false)
+    };
+    let code = bitlink.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+    let id = links::normalize_code(&config, code.to_owned());
+    let Some(link) = load(&pool, id).await? else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Bitlink not found".to_owned()).into()
+        )
+    };
+    if !summary {
+        return Ok(Json(Bitlink::new(&config, link)).into_response())
+    }
+
+    let unit = query.unit.unwrap_or_else(|| "day".into());
+    let units = query.units.unwrap_or(-1);
+    let length = match unit.as_str() {
+        "minute" => Duration::minutes(1),
+        "hour" => Duration::hours(1),
+        "day" => Duration::days(1),
+        "week" => Duration::weeks(1),
+        "month" => Duration::days(30),
+        _ => return Err(
+            (StatusCode::BAD_REQUEST, "Unit must be minute, hour, day, week or month".to_owned()).into()
+        )
+    };
+    let now = Utc::now().naive_utc();
+    // Spans reaching back further than timestamps can go count every click.
+    let since = i32::try_from(units)
+        .ok()
+        .filter(|&units| units >= 0)
+        .and_then(|units| length.checked_mul(units))
+        .and_then(|span| now.checked_sub_signed(span));
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let id = link.id;
+    let total_clicks = conn.interact(move |conn| match since {
+        Some(since) => clicks::table
+            .filter(clicks::url_id.eq(&id))
+            .filter(clicks::clicked_at.ge(since))
+            .count()
+            .get_result(conn),
+        None => stats::total_clicks(conn, &id)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok((
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(json!({
+            "total_clicks": total_clicks,
+            "unit": unit,
+            "units": units,
+            "unit_reference": timestamp(now)
+        }))
+    ).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_timestamps_like_bitly() {
+        let at = NaiveDateTime::parse_from_str("2024-05-01 12:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(timestamp(at), "2024-05-01T12:30:00+0000");
+    }
+}
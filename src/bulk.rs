@@ -0,0 +1,200 @@
+use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, State}, http::StatusCode, Extension, Json
+};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links::{self, LinkFilter};
+use crate::schema::urls;
+use crate::telemetry;
+use crate::AppConfig;
+
+const MAX_IDS: usize = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkSelection {
+    Ids(Vec<String>),
+    Filter(BulkFilter)
+}
+
+impl BulkSelection {
+    /// Ids in the spelling links are stored in.
+    fn normalized(self, config: &AppConfig) -> Self {
+        match self {
+            BulkSelection::Ids(ids) => BulkSelection::Ids(
+                ids.into_iter().map(|id| links::normalize_code(config, id)).collect()
+            ),
+            filter => filter
+        }
+    }
+}
+
+/// Named groups of links. Links can't expire or be tagged yet; an `expired` filter
+/// belongs here once they can.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkFilter {
+    Disabled,
+    /// Held back by the spam filter.
+    Quarantined,
+    /// The links an abuse investigation turned up, e.g. `{"matching": {"domain": "example.com"}}`.
+    Matching(LinkFilter)
+}
+
+#[derive(Serialize)]
+pub struct BulkResult {
+    matched: usize,
+    affected: usize,
+    missing: Vec<String>
+}
+
+#[derive(Clone, Copy)]
+enum BulkAction {
+    Delete,
+    Disable,
+    Enable
+}
+
+impl BulkAction {
+    fn audit_action(self) -> &'static str {
+        match self {
+            BulkAction::Delete => "delete",
+            BulkAction::Disable => "disable",
+            BulkAction::Enable => "enable"
+        }
+    }
+}
+
+pub async fn bulk_delete(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(selection): Json<BulkSelection>
+) -> Result<Json<BulkResult>, ApiError> {
+    run(pool, addr, actor, selection.normalized(&config), BulkAction::Delete).await
+}
+
+pub async fn bulk_disable(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(selection): Json<BulkSelection>
+) -> Result<Json<BulkResult>, ApiError> {
+    run(pool, addr, actor, selection.normalized(&config), BulkAction::Disable).await
+}
+
+pub async fn bulk_enable(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(selection): Json<BulkSelection>
+) -> Result<Json<BulkResult>, ApiError> {
+    run(pool, addr, actor, selection.normalized(&config), BulkAction::Enable).await
+}
+
+async fn run(
+    pool: sqlite::Pool,
+    addr: SocketAddr,
+    actor: Actor,
+    selection: BulkSelection,
+    action: BulkAction
+) -> Result<Json<BulkResult>, ApiError> {
+    if let BulkSelection::Ids(ids) = &selection {
+        if ids.len() > MAX_IDS {
+            return Err(
+                (StatusCode::PAYLOAD_TOO_LARGE, format!("At most {MAX_IDS} ids can be changed at once")).into()
+            )
+        }
+    }
+    let matching = match &selection {
+        BulkSelection::Filter(BulkFilter::Matching(filter)) => match filter.condition() {
+            Some(condition) => Some(condition),
+            // An empty filter would match every link.
+            None => return Err(
+                (StatusCode::UNPROCESSABLE_ENTITY, "No criteria given".into()).into()
+            )
+        },
+        _ => None
+    };
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let result = conn.interact(move |conn| {
+        conn.immediate_transaction::<_, diesel::result::Error, _>(|conn| {
+            let matched: Vec<String> = match &selection {
+                BulkSelection::Ids(ids) => urls::table
+                    .filter(urls::id.eq_any(ids))
+                    .select(urls::id)
+                    .load(conn)?,
+                BulkSelection::Filter(BulkFilter::Disabled) => urls::table
+                    .filter(urls::disabled.eq(true))
+                    .select(urls::id)
+                    .load(conn)?,
+                BulkSelection::Filter(BulkFilter::Quarantined) => urls::table
+                    .filter(urls::quarantined.eq(true))
+                    .select(urls::id)
+                    .load(conn)?,
+                BulkSelection::Filter(BulkFilter::Matching(_)) => urls::table
+                    .filter(matching.expect("checked above"))
+                    .select(urls::id)
+                    .load(conn)?
+            };
+
+            // Filters can match more rows than SQLite accepts as bind parameters.
+            let mut affected = 0;
+            for chunk in matched.chunks(MAX_IDS) {
+                affected += match action {
+                    BulkAction::Delete => links::delete(conn, chunk)?,
+                    BulkAction::Disable | BulkAction::Enable => diesel::update(urls::table)
+                        .filter(urls::id.eq_any(chunk))
+                        .set(urls::disabled.eq(matches!(action, BulkAction::Disable)))
+                        .execute(conn)?
+                };
+            }
+
+            let ip = addr.ip().to_string();
+            for id in &matched {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: action.audit_action(),
+                    target: Some(id),
+                    ip: Some(&ip)
+                })?;
+            }
+
+            let missing = match selection {
+                BulkSelection::Ids(ids) => {
+                    let found: HashSet<&String> = matched.iter().collect();
+                    ids.iter()
+                        .filter(|id| !found.contains(id))
+                        .cloned()
+                        .collect()
+                }
+                BulkSelection::Filter(_) => Vec::new()
+            };
+
+            Ok(BulkResult {
+                matched: matched.len(),
+                affected,
+                missing
+            })
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(result))
+}
@@ -0,0 +1,452 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::StatusCode, response::Html, Extension, Json
+};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, result::{DatabaseErrorKind, Error::DatabaseError}
+};
+use serde::{Deserialize, Serialize};
+
+use crate::alias::{self, Availability};
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::cold;
+use crate::errors::{self, ApiError, internal_error, unavailable};
+use crate::html;
+use crate::links::{self, MAX_TITLE_LENGTH};
+use crate::schema::{bundle_links, bundles, urls};
+use crate::telemetry;
+use crate::validate;
+use crate::AppConfig;
+
+/// The most links one bundle lists.
+const MAX_LINKS: usize = 50;
+
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+
+/// What a bundle page shows.
+#[derive(Deserialize)]
+pub struct BundleContent {
+    title: String,
+    description: Option<String>,
+    links: Vec<BundleLinkContent>
+}
+
+#[derive(Deserialize)]
+pub struct BundleLinkContent {
+    title: String,
+    url: String,
+    /// An image shown next to the title, e.g. the destination's logo.
+    icon: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct NewBundleRequest {
+    /// A custom code, following the rules of link aliases.
+    alias: Option<String>,
+    #[serde(flatten)]
+    content: BundleContent
+}
+
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = bundles)]
+struct BundleRow {
+    id: String,
+    title: String,
+    description: Option<String>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime
+}
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = bundle_links)]
+pub struct BundleLink {
+    title: String,
+    url: String,
+    icon: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct Bundle {
+    id: String,
+    short_url: String,
+    title: String,
+    description: Option<String>,
+    links: Vec<BundleLink>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime
+}
+
+impl BundleLinkContent {
+    fn validated(self, config: &AppConfig) -> Result<Self, ApiError> {
+        let Some(title) = links::annotation(Some(self.title), "Link title", MAX_TITLE_LENGTH)? else {
+            return Err(
+                errors::invalid("title", "required", (StatusCode::UNPROCESSABLE_ENTITY, "Every link needs a title".into()))
+            )
+        };
+        let url = validate::pipeline().run(config, self.url)?;
+        let icon = self.icon.filter(|icon| !icon.trim().is_empty());
+        if icon.as_deref().is_some_and(|icon| !links::is_web_url(icon)) {
+            return Err(
+                errors::invalid("icon", "icon_invalid", (StatusCode::UNPROCESSABLE_ENTITY, "Icons must be http or https URLs".into()))
+            )
+        }
+        Ok(BundleLinkContent { title, url, icon })
+    }
+}
+
+impl BundleContent {
+    /// Checks the content the way links are checked, returning it trimmed.
+    fn validated(self, config: &AppConfig) -> Result<Self, ApiError> {
+        let Some(title) = links::annotation(Some(self.title), "Title", MAX_TITLE_LENGTH)? else {
+            return Err(
+                errors::invalid("title", "required", (StatusCode::UNPROCESSABLE_ENTITY, "Bundles need a title".into()))
+            )
+        };
+        let description = links::annotation(self.description, "Description", MAX_DESCRIPTION_LENGTH)?;
+        if self.links.is_empty() || self.links.len() > MAX_LINKS {
+            return Err(
+                errors::invalid("links", "links_count", (StatusCode::UNPROCESSABLE_ENTITY, format!("Bundles list between 1 and {MAX_LINKS} links")))
+            )
+        }
+        let links = self.links
+            .into_iter()
+            .enumerate()
+            .map(|(index, link)| link.validated(config).map_err(|err| err.within(&format!("links[{index}]"))))
+            .collect::<Result<_, _>>()?;
+        Ok(BundleContent { title, description, links })
+    }
+}
+
+/// Whether `code` is used by a link, archived or not, or a bundle, which share one namespace.
+pub fn code_taken(conn: &mut SqliteConnection, code: &str) -> QueryResult<bool> {
+    let link = diesel::select(diesel::dsl::exists(urls::table.find(code))).get_result::<bool>(conn)?;
+    Ok(link || cold::is_archived(conn, code)? || diesel::select(diesel::dsl::exists(bundles::table.find(code))).get_result::<bool>(conn)?)
+}
+
+fn load(conn: &mut SqliteConnection, config: &AppConfig, id: &str) -> QueryResult<Option<Bundle>> {
+    let Some(row) = bundles::table
+        .find(id)
+        .select(BundleRow::as_select())
+        .get_result(conn)
+        .optional()? else {
+        return Ok(None)
+    };
+    let links = bundle_links::table
+        .filter(bundle_links::bundle_id.eq(id))
+        .order(bundle_links::position)
+        .select(BundleLink::as_select())
+        .load(conn)?;
+    Ok(Some(Bundle {
+        short_url: links::short_url(config, &row.id),
+        id: row.id,
+        title: row.title,
+        description: row.description,
+        links,
+        created_at: row.created_at,
+        updated_at: row.updated_at
+    }))
+}
+
+fn save_links(conn: &mut SqliteConnection, id: &str, links: &[BundleLinkContent]) -> QueryResult<()> {
+    diesel::delete(bundle_links::table)
+        .filter(bundle_links::bundle_id.eq(id))
+        .execute(conn)?;
+    for (position, link) in (0..).zip(links) {
+        diesel::insert_into(bundle_links::table)
+            .values((
+                bundle_links::bundle_id.eq(id),
+                bundle_links::position.eq(position),
+                bundle_links::title.eq(&link.title),
+                bundle_links::url.eq(&link.url),
+                bundle_links::icon.eq(&link.icon)
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+pub async fn create_bundle(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<NewBundleRequest>
+) -> Result<(StatusCode, Json<Bundle>), ApiError> {
+    let content = request.content.validated(&config)?;
+    let alias = request.alias.map(|alias| links::normalize_code(&config, alias));
+    let candidates = match alias {
+        Some(alias) => {
+            match alias::precheck(&config, &alias) {
+                Some(Availability::Invalid) => return Err(
+                    (StatusCode::BAD_REQUEST, "Alias may only contain letters, digits, '-', '_' and characters of the id alphabet, in up to 5 segments separated by '/'".into()).into()
+                ),
+                Some(Availability::Confusable) => return Err(
+                    (StatusCode::BAD_REQUEST, "Alias mixes scripts or imitates Latin letters".into()).into()
+                ),
+                Some(_) => return Err(
+                    (StatusCode::CONFLICT, "Alias is reserved".into()).into()
+                ),
+                None => links::Candidates::Drawn(vec![alias])
+            }
+        }
+        None => links::Candidates::new(&config, config.id_strategy, &content.title)
+    };
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let bundle = conn.interact(move |conn| {
+        conn.immediate_transaction(|conn| {
+            // Bundle candidates aren't reusable, so every pick is a free code.
+            let Some(links::Pick::Free(id)) = candidates.pick(conn)? else {
+                return Ok(None)
+            };
+            diesel::insert_into(bundles::table)
+                .values((
+                    bundles::id.eq(&id),
+                    bundles::title.eq(&content.title),
+                    bundles::description.eq(&content.description)
+                ))
+                .execute(conn)?;
+            save_links(conn, &id, &content.links)?;
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "create_bundle",
+                target: Some(&id),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            load(conn, &config, &id)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(bundle) = bundle else {
+        return Err(
+            (StatusCode::CONFLICT, "Alias is already taken".into()).into()
+        )
+    };
+    Ok((StatusCode::CREATED, Json(bundle)))
+}
+
+pub async fn list_bundles(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<Vec<Bundle>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let bundles = conn.interact(move |conn| {
+        let ids: Vec<String> = bundles::table
+            .order(bundles::created_at.desc())
+            .select(bundles::id)
+            .load(conn)?;
+        ids.iter()
+            .filter_map(|id| load(conn, &config, id).transpose())
+            .collect::<QueryResult<Vec<Bundle>>>()
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(bundles))
+}
+
+pub async fn bundle_details(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<Bundle>, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let bundle = conn.interact(move |conn| load(conn, &config, &id))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    let Some(bundle) = bundle else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Bundle not found".into()).into()
+        )
+    };
+    Ok(Json(bundle))
+}
+
+/// Replaces a bundle's title, description and links.
+pub async fn update_bundle(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(content): Json<BundleContent>
+) -> Result<Json<Bundle>, ApiError> {
+    let content = content.validated(&config)?;
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let bundle = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(bundles::table.find(&id))
+                .set((
+                    bundles::title.eq(&content.title),
+                    bundles::description.eq(&content.description),
+                    bundles::updated_at.eq(Utc::now().naive_utc())
+                ))
+                .execute(conn)?;
+            if updated == 0 {
+                return Ok(None)
+            }
+            save_links(conn, &id, &content.links)?;
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "update_bundle",
+                target: Some(&id),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            load(conn, &config, &id)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(bundle) = bundle else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Bundle not found".into()).into()
+        )
+    };
+    Ok(Json(bundle))
+}
+
+pub async fn delete_bundle(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let deleted = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            diesel::delete(bundle_links::table)
+                .filter(bundle_links::bundle_id.eq(&id))
+                .execute(conn)?;
+            let deleted = diesel::delete(bundles::table.find(&id)).execute(conn)?;
+            if deleted > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "delete_bundle",
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(deleted)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if deleted == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Bundle not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Refuses to give a link the code of a bundle or an archived link, in the
+/// link's own transaction, failing like the unique constraint on its code would.
+pub fn ensure_free_for_link(conn: &mut SqliteConnection, code: &str) -> QueryResult<()> {
+    if diesel::select(diesel::dsl::exists(bundles::table.find(code))).get_result::<bool>(conn)? {
+        return Err(DatabaseError(DatabaseErrorKind::UniqueViolation, Box::new(format!("{code} is a bundle"))))
+    }
+    if cold::is_archived(conn, code)? {
+        return Err(DatabaseError(DatabaseErrorKind::UniqueViolation, Box::new(format!("{code} is archived"))))
+    }
+    Ok(())
+}
+
+fn render(bundle: &Bundle) -> Html<String> {
+    let items: String = bundle.links
+        .iter()
+        .map(|link| {
+            let icon = link.icon
+                .as_deref()
+                .map(|icon| format!("<img src=\"{}\" alt=\"\" width=\"24\" height=\"24\"> ", html::escape(icon)))
+                .unwrap_or_default();
+            format!(
+                "<li><a href=\"{}\" rel=\"noopener\">{icon}{}</a></li>\n",
+                html::escape(&link.url),
+                html::escape(&link.title)
+            )
+        })
+        .collect();
+    let description = bundle.description
+        .as_deref()
+        .map(|description| format!("<p>{}</p>\n", html::escape(description)))
+        .unwrap_or_default();
+    let style = "<style>.bundle { list-style: none; padding: 0; } .bundle li { margin: 0.75rem 0; } \
+                 .bundle a { display: block; padding: 0.75rem 1rem; border: 1px solid #ccd; border-radius: 0.5rem; text-decoration: none; } \
+                 .bundle img { vertical-align: middle; }</style>";
+    Html(html::page_with_head(
+        "en",
+        &bundle.title,
+        style,
+        &format!("<h1>{}</h1>\n{description}<ul class=\"bundle\">\n{items}</ul>", html::escape(&bundle.title))
+    ))
+}
+
+/// The page of the bundle with code `id`, for codes no link has.
+pub async fn page(pool: &sqlite::Pool, config: Arc<AppConfig>, id: String) -> Result<Option<Html<String>>, ApiError> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(unavailable)?;
+    let bundle = conn.interact(move |conn| load(conn, &config, &id))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+    Ok(bundle.as_ref().map(render))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_escaped_links() {
+        let now = Utc::now().naive_utc();
+        let bundle = Bundle {
+            id: "me".into(),
+            short_url: "https://sho.rt/me".into(),
+            title: "<Me>".into(),
+            description: None,
+            links: vec![BundleLink {
+                title: "Blog & more".into(),
+                url: "https://example.com/?a=1&b=\"2\"".into(),
+                icon: Some("https://example.com/icon.png".into())
+            }],
+            created_at: now,
+            updated_at: now
+        };
+        let Html(page) = render(&bundle);
+        assert!(page.contains("<h1>&lt;Me&gt;</h1>"));
+        assert!(page.contains("href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\" rel=\"noopener\""));
+        assert!(page.contains("<img src=\"https://example.com/icon.png\""));
+        assert!(page.contains("Blog &amp; more</a>"));
+    }
+}
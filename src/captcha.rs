@@ -0,0 +1,136 @@
+use std::{
+    net::IpAddr, sync::LazyLock, time::Duration
+};
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+
+use crate::errors::{self, ApiError};
+use crate::html::escape;
+
+/// Header API clients creating links anonymously pass the solved challenge's token in.
+pub const RESPONSE_HEADER: &str = "captcha-response";
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("HTTP client can be built")
+});
+
+#[derive(Clone, Copy)]
+pub enum Provider {
+    Turnstile,
+    HCaptcha
+}
+
+impl Provider {
+    pub fn parse(provider: &str) -> Option<Self> {
+        match provider {
+            "turnstile" => Some(Provider::Turnstile),
+            "hcaptcha" => Some(Provider::HCaptcha),
+            _ => None
+        }
+    }
+
+    fn verify_url(self) -> &'static str {
+        match self {
+            Provider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+            Provider::HCaptcha => "https://api.hcaptcha.com/siteverify"
+        }
+    }
+
+    fn script(self) -> &'static str {
+        match self {
+            Provider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/api.js",
+            Provider::HCaptcha => "https://js.hcaptcha.com/1/api.js"
+        }
+    }
+
+    /// The widget's element class, which also names the form field it fills in.
+    fn widget_class(self) -> &'static str {
+        match self {
+            Provider::Turnstile => "cf-turnstile",
+            Provider::HCaptcha => "h-captcha"
+        }
+    }
+}
+
+/// The challenge anonymous visitors have to solve before shortening a link.
+pub struct Captcha {
+    pub provider: Provider,
+    pub site_key: String,
+    pub secret: String,
+    /// Overrides the provider's verification endpoint, e.g. for a compatible self-hosted service.
+    pub verify_url: Option<String>
+}
+
+#[derive(Deserialize)]
+struct Verification {
+    success: bool
+}
+
+impl Captcha {
+    /// Markup rendering the challenge inside a form.
+    pub fn widget(&self) -> String {
+        format!(
+            "<script src=\"{}\" async defer></script>\n\
+             <div class=\"{}\" data-sitekey=\"{}\"></div>",
+            self.provider.script(),
+            self.provider.widget_class(),
+            escape(&self.site_key)
+        )
+    }
+
+    /// Checks a solved challenge's token with the provider. A missing token is
+    /// answered with `401`, a rejected one with `403`, and `503` if the provider
+    /// can't be reached, as creation would otherwise be unguarded.
+    pub async fn verify(&self, response: Option<&str>, ip: IpAddr) -> Result<(), ApiError> {
+        let Some(response) = response.filter(|response| !response.is_empty()) else {
+            return Err(
+                errors::coded("captcha_required", (StatusCode::UNAUTHORIZED, "Solve the CAPTCHA or present an API token to shorten links".into()))
+            )
+        };
+        let ip = ip.to_string();
+        let params = [("secret", self.secret.as_str()), ("response", response), ("remoteip", ip.as_str())];
+        let verification = CLIENT.post(self.verify_url.as_deref().unwrap_or(self.provider.verify_url()))
+            .form(&params)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let verification = match verification {
+            Ok(verification) => verification.json::<Verification>().await,
+            Err(err) => Err(err)
+        };
+        match verification {
+            Ok(Verification { success: true }) => Ok(()),
+            Ok(Verification { success: false }) => Err(
+                errors::coded("captcha_failed", (StatusCode::FORBIDDEN, "CAPTCHA was not solved".into()))
+            ),
+            Err(err) => {
+                println!("CAPTCHA verification failed: {err}");
+                Err(
+                    errors::coded("captcha_unavailable", (StatusCode::SERVICE_UNAVAILABLE, "CAPTCHA can't be verified right now".into()))
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widget_escapes_the_site_key() {
+        let captcha = Captcha {
+            provider: Provider::HCaptcha,
+            site_key: "key\"><script>".into(),
+            secret: "secret".into(),
+            verify_url: None
+        };
+        let widget = captcha.widget();
+        assert!(widget.contains("class=\"h-captcha\""));
+        assert!(!widget.contains("key\"><script>"));
+    }
+}
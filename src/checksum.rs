@@ -0,0 +1,77 @@
+/// Luhn mod N over an arbitrary alphabet: catches every single-character typo
+/// and most swaps of adjacent characters. Summing the base-N digits of a doubled
+/// value only maps each character to a distinct addend when N is even, so odd-sized
+/// alphabets double modulo N instead.
+fn luhn_sum(alphabet: &[char], code: impl DoubleEndedIterator<Item = char>, double_first: bool) -> Option<usize> {
+    let base = alphabet.len();
+    let mut double = double_first;
+    let mut sum = 0;
+    for c in code.rev() {
+        let value = alphabet.iter().position(|&candidate| candidate == c)?;
+        let addend = if double { value * 2 } else { value };
+        sum += if base.is_multiple_of(2) { addend / base + addend % base } else { addend % base };
+        double = !double;
+    }
+    Some(sum % base)
+}
+
+/// The character that makes `code` pass [`is_valid`] when appended to it.
+pub fn check_char(alphabet: &[char], code: &str) -> char {
+    let sum = luhn_sum(alphabet, code.chars(), true).expect("code is drawn from the alphabet");
+    alphabet[(alphabet.len() - sum) % alphabet.len()]
+}
+
+/// Whether the last character of `code` is the check character of the rest.
+pub fn is_valid(alphabet: &[char], code: &str) -> bool {
+    luhn_sum(alphabet, code.chars(), false) == Some(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: [char; 16] = ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b', 'c', 'd', 'e', 'f'];
+
+    fn with_check(alphabet: &[char], code: &str) -> String {
+        let mut checked = code.to_owned();
+        checked.push(check_char(alphabet, code));
+        checked
+    }
+
+    #[test]
+    fn check_char_round_trips() {
+        for code in ["1", "abc", "0f9e8d7c6b", "ffffffffff"] {
+            assert!(is_valid(&HEX, &with_check(&HEX, code)), "{code}");
+        }
+        let emoji: Vec<char> = "🚀🔥🎉🌈🍕".chars().collect();
+        assert!(is_valid(&emoji, &with_check(&emoji, "🚀🚀🔥🍕")));
+    }
+
+    #[test]
+    fn detects_every_single_character_typo() {
+        let safe: Vec<char> = "23456789abcdefghjkmnpqrstuvwxyz".chars().collect();
+        for alphabet in [&HEX[..], &HEX[..7], &safe[..]] {
+            let code: String = alphabet.iter().cycle().skip(3).take(10).collect();
+            let checked: Vec<char> = with_check(alphabet, &code).chars().collect();
+            for position in 0..checked.len() {
+                for &replacement in alphabet.iter().filter(|&&c| c != checked[position]) {
+                    let mut typo = checked.clone();
+                    typo[position] = replacement;
+                    let typo: String = typo.into_iter().collect();
+                    assert!(!is_valid(alphabet, &typo), "{typo} passed");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn detects_adjacent_swaps() {
+        let checked = with_check(&HEX, "12ab");
+        assert!(!is_valid(&HEX, &format!("21ab{}", &checked[4..])));
+    }
+
+    #[test]
+    fn rejects_characters_outside_the_alphabet() {
+        assert!(!is_valid(&HEX, "xyz"));
+    }
+}
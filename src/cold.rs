@@ -0,0 +1,406 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State}, http::StatusCode, Extension, Json
+};
+use chrono::{Months, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::{BigInt, Text, Timestamp}
+};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::config::LiveConfig;
+use crate::cursor::{self, Page};
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::retention;
+use crate::schema::{archived_links, urls};
+use crate::telemetry;
+use crate::AppConfig;
+
+/// How often links are checked for having gone cold.
+const INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Links moved per transaction, so redirects wait on the write lock only briefly.
+const BATCH_SIZE: i64 = 500;
+
+const ACTOR: &str = "cold_storage";
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 1000;
+
+/// Links created and last changed before the cutoff, bound first, without a
+/// click since, that aren't scheduled to go live later. Links with conversions
+/// stay put, as their clicks are kept for them.
+const COLD_LINKS: &str = "SELECT id FROM urls WHERE created_at < ?1 AND updated_at < ?1 \
+    AND (publish_at IS NULL OR publish_at < ?1) \
+    AND NOT EXISTS (SELECT 1 FROM clicks WHERE clicks.url_id = urls.id AND clicked_at >= ?1) \
+    AND NOT EXISTS (SELECT 1 FROM click_stats_daily s WHERE s.url_id = urls.id AND s.day >= date(?1)) \
+    AND NOT EXISTS (SELECT 1 FROM conversions WHERE conversions.url_id = urls.id) \
+    ORDER BY created_at LIMIT ?2";
+
+#[derive(QueryableByName)]
+struct Name {
+    #[diesel(sql_type = Text)]
+    name: String
+}
+
+#[derive(QueryableByName)]
+struct Code {
+    #[diesel(sql_type = Text)]
+    id: String
+}
+
+/// Starts moving links that went unclicked for `COLD_LINK_MONTHS`, along with
+/// their daily click counts, out of `urls` into `archived_links`, which keeps
+/// the table redirects are looked up in small.
+pub fn spawn(pool: sqlite::Pool, live: LiveConfig) {
+    tokio::spawn(async move {
+        loop {
+            let months = live.load().cold_link_months;
+            if months > 0 {
+                match run(&pool, months).await {
+                    Ok(0) => {}
+                    Ok(archived) => counter!("links_archived_total").increment(archived as u64),
+                    Err(err) => println!("Archiving cold links failed: {err}")
+                }
+            }
+            tokio::time::sleep(INTERVAL).await;
+        }
+    });
+}
+
+/// Archives links in batches until none are cold, returning how many were.
+async fn run(pool: &sqlite::Pool, months: u32) -> Result<usize, String> {
+    let Some(cutoff) = Utc::now().naive_utc().checked_sub_months(Months::new(months)) else {
+        return Ok(0)
+    };
+    let mut archived = 0;
+    loop {
+        let conn = telemetry::checkout(pool)
+            .await
+            .map_err(|err| err.to_string())?;
+        let moved = conn.interact(move |conn| archive(conn, cutoff))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+        archived += moved;
+        if moved < BATCH_SIZE as usize {
+            return Ok(archived)
+        }
+    }
+}
+
+/// The columns of `urls`, read from the database so that links archived before
+/// a migration added one still restore.
+fn columns(conn: &mut SqliteConnection) -> QueryResult<Vec<String>> {
+    let columns: Vec<Name> = diesel::sql_query("SELECT name FROM pragma_table_info('urls')").load(conn)?;
+    Ok(columns.into_iter().map(|column| column.name).collect())
+}
+
+fn archive(conn: &mut SqliteConnection, cutoff: NaiveDateTime) -> QueryResult<usize> {
+    conn.immediate_transaction(|conn| {
+        // The raw clicks go with the link, so the days not rolled up yet are first.
+        retention::roll_up(conn, Utc::now().date_naive().and_time(Default::default()))?;
+        let ids: Vec<String> = diesel::sql_query(COLD_LINKS)
+            .bind::<Timestamp, _>(cutoff)
+            .bind::<BigInt, _>(BATCH_SIZE)
+            .load::<Code>(conn)?
+            .into_iter()
+            .map(|cold| cold.id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(0)
+        }
+
+        let fields = columns(conn)?
+            .iter()
+            .map(|column| format!("'{column}', `{column}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        diesel::sql_query(format!(
+            "INSERT INTO archived_links (id, link, daily_stats, last_clicked_at) \
+             SELECT id, json_object({fields}), \
+             (SELECT json_group_array(json_object('day', day, 'clicks', clicks, 'visitors', visitors)) FROM click_stats_daily WHERE url_id = urls.id), \
+             (SELECT MAX(at) FROM (SELECT MAX(clicked_at) AS at FROM clicks WHERE url_id = urls.id \
+              UNION ALL SELECT datetime(MAX(day)) FROM click_stats_daily WHERE url_id = urls.id)) \
+             FROM urls WHERE id IN (SELECT value FROM json_each(?))"
+        ))
+        .bind::<Text, _>(serde_json::to_string(&ids).expect("codes serialize"))
+        .execute(conn)?;
+        links::delete(conn, &ids)?;
+
+        for id in &ids {
+            audit::record(conn, NewAuditEntry {
+                actor: Some(ACTOR),
+                action: "archive_link",
+                target: Some(id),
+                ip: None
+            })?;
+        }
+        Ok(ids.len())
+    })
+}
+
+/// Moves an archived link back into `urls` along with its daily click counts.
+/// Restoring counts as a change, so the link isn't archived again for another
+/// `COLD_LINK_MONTHS`. Returns whether there was such a link.
+fn restore(conn: &mut SqliteConnection, id: &str, actor: Option<&str>, ip: &str) -> QueryResult<bool> {
+    conn.immediate_transaction(|conn| {
+        let keys: Vec<Name> = diesel::sql_query(
+            "SELECT key AS name FROM archived_links, json_each(archived_links.link) WHERE archived_links.id = ?"
+        )
+        .bind::<Text, _>(id)
+        .load(conn)?;
+        if keys.is_empty() {
+            return Ok(false)
+        }
+        // Columns dropped since the link was archived are left behind.
+        let columns: Vec<String> = columns(conn)?
+            .into_iter()
+            .filter(|column| keys.iter().any(|key| key.name == *column))
+            .collect();
+        let values = columns.iter()
+            .map(|column| format!("json_extract(link, '$.{column}')"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let columns = columns.iter()
+            .map(|column| format!("`{column}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        diesel::sql_query(format!("INSERT INTO urls ({columns}) SELECT {values} FROM archived_links WHERE id = ?"))
+            .bind::<Text, _>(id)
+            .execute(conn)?;
+        diesel::sql_query(
+            "INSERT INTO click_stats_daily (url_id, day, clicks, visitors) \
+             SELECT archived_links.id, json_extract(value, '$.day'), json_extract(value, '$.clicks'), json_extract(value, '$.visitors') \
+             FROM archived_links, json_each(archived_links.daily_stats) WHERE archived_links.id = ?"
+        )
+        .bind::<Text, _>(id)
+        .execute(conn)?;
+        diesel::update(urls::table.find(id))
+            .set(urls::updated_at.eq(Utc::now().naive_utc()))
+            .execute(conn)?;
+        diesel::delete(archived_links::table.find(id)).execute(conn)?;
+
+        audit::record(conn, NewAuditEntry {
+            actor,
+            action: "restore_archived_link",
+            target: Some(id),
+            ip: Some(ip)
+        })?;
+        Ok(true)
+    })
+}
+
+/// Whether `code` belongs to an archived link, whose code isn't handed out again.
+pub fn is_archived(conn: &mut SqliteConnection, code: &str) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(archived_links::table.find(code))).get_result(conn)
+}
+
+/// The stored rows of the archived links among `ids`.
+pub fn links(conn: &mut SqliteConnection, ids: &[String]) -> QueryResult<Vec<serde_json::Value>> {
+    let stored: Vec<String> = archived_links::table
+        .filter(archived_links::id.eq_any(ids))
+        .select(archived_links::link)
+        .load(conn)?;
+    Ok(stored.iter().filter_map(|link| serde_json::from_str(link).ok()).collect())
+}
+
+/// Deletes the archived links among `ids`, returning how many there were.
+pub fn delete(conn: &mut SqliteConnection, ids: &[String]) -> QueryResult<usize> {
+    diesel::delete(archived_links::table)
+        .filter(archived_links::id.eq_any(ids))
+        .execute(conn)
+}
+
+/// Strips who created them from the archived links among `ids`.
+pub fn anonymize(conn: &mut SqliteConnection, ids: &[String]) -> QueryResult<usize> {
+    diesel::update(archived_links::table)
+        .filter(archived_links::id.eq_any(ids))
+        .set(archived_links::link.eq(diesel::dsl::sql::<Text>("json_set(link, '$.created_by', NULL, '$.creator_ip', NULL)")))
+        .execute(conn)
+}
+
+#[derive(Deserialize)]
+pub struct ArchivedLinksQuery {
+    /// The `next` cursor of the previous page.
+    after: Option<String>,
+    limit: Option<i64>
+}
+
+#[derive(Serialize, Queryable)]
+pub struct ArchivedLink {
+    id: String,
+    url: String,
+    last_clicked_at: Option<NaiveDateTime>,
+    archived_at: NaiveDateTime
+}
+
+/// The archived links, most recently archived first.
+pub async fn list_archived_links(
+    Query(query): Query<ArchivedLinksQuery>,
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Page<ArchivedLink>>, ApiError> {
+    let after = query.after.as_deref().map(cursor::decode).transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let archived = conn.interact(move |conn| {
+        // One row beyond the page tells whether there's another one.
+        let mut statement = archived_links::table
+            .select((
+                archived_links::id,
+                diesel::dsl::sql::<Text>("json_extract(link, '$.url')"),
+                archived_links::last_clicked_at,
+                archived_links::archived_at
+            ))
+            .order((archived_links::archived_at.desc(), archived_links::id.desc()))
+            .limit(limit + 1)
+            .into_boxed();
+        if let Some((archived_at, id)) = after {
+            statement = statement.filter(
+                archived_links::archived_at.lt(archived_at)
+                    .or(archived_links::archived_at.eq(archived_at).and(archived_links::id.lt(id)))
+            );
+        }
+        statement.load::<ArchivedLink>(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(cursor::paginate(archived, limit, |link| (link.archived_at, link.id.clone()))))
+}
+
+/// Brings an archived link back, redirecting again right away.
+pub async fn restore_archived_link(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let restored = conn.interact(move |conn| restore(conn, &id, actor.name(), &addr.ip().to_string()))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    if !restored {
+        return Err(
+            (StatusCode::NOT_FOUND, "No such archived short URL".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Days;
+    use crate::database;
+    use crate::schema::{click_stats_daily, clicks};
+    use crate::stats;
+
+    /// Every column of the link, except when it was last changed.
+    fn stored_row(conn: &mut SqliteConnection, id: &str) -> serde_json::Value {
+        let fields = columns(conn)
+            .unwrap()
+            .iter()
+            .filter(|column| *column != "updated_at")
+            .map(|column| format!("'{column}', `{column}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let row: Name = diesel::sql_query(format!("SELECT json_object({fields}) AS name FROM urls WHERE id = ?"))
+            .bind::<Text, _>(id)
+            .get_result(conn)
+            .unwrap();
+        serde_json::from_str(&row.name).unwrap()
+    }
+
+    fn rollups(conn: &mut SqliteConnection, id: &str) -> Vec<(chrono::NaiveDate, i64, i64)> {
+        click_stats_daily::table
+            .filter(click_stats_daily::url_id.eq(id))
+            .order(click_stats_daily::day)
+            .select((click_stats_daily::day, click_stats_daily::clicks, click_stats_daily::visitors))
+            .load(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn archived_links_restore_as_they_were() {
+        let mut conn = database::test_database();
+        let now = Utc::now().naive_utc();
+        let long_ago = now.checked_sub_days(Days::new(400)).unwrap();
+        diesel::insert_into(urls::table)
+            .values((
+                urls::id.eq("cold"),
+                urls::url.eq("https://example.com/cold"),
+                urls::public_stats.eq(true),
+                urls::created_at.eq(long_ago),
+                urls::updated_at.eq(long_ago),
+                urls::untracked_clicks.eq(3),
+                urls::pixels.eq("[\"meta\"]"),
+                urls::redirect_mode.eq("meta"),
+                urls::spam_score.eq(2),
+                urls::created_by.eq("token:7"),
+                urls::creator_ip.eq("192.0.2.7"),
+                urls::title.eq("Cold"),
+                urls::notes.eq("Kept for the record"),
+                urls::og_title.eq("Cold preview"),
+                urls::indexable.eq(true)
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        for (days_ago, visitor) in [(390, "v1"), (390, "v2"), (380, "v1")] {
+            diesel::insert_into(clicks::table)
+                .values((
+                    clicks::url_id.eq("cold"),
+                    clicks::clicked_at.eq(now.checked_sub_days(Days::new(days_ago)).unwrap()),
+                    clicks::visitor.eq(visitor)
+                ))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        // Days rolled up before, from clicks pruned since.
+        diesel::insert_into(click_stats_daily::table)
+            .values((
+                click_stats_daily::url_id.eq("cold"),
+                click_stats_daily::day.eq(now.checked_sub_days(Days::new(395)).unwrap().date()),
+                click_stats_daily::clicks.eq(5),
+                click_stats_daily::visitors.eq(4)
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        let row = stored_row(&mut conn, "cold");
+        let total = stats::total_clicks(&mut conn, "cold").unwrap();
+        assert_eq!(total, 11);
+
+        let cutoff = now.checked_sub_months(Months::new(6)).unwrap();
+        assert_eq!(archive(&mut conn, cutoff).unwrap(), 1);
+        let remaining: i64 = clicks::table.filter(clicks::url_id.eq("cold")).count().get_result(&mut conn).unwrap();
+        assert_eq!(remaining, 0);
+        assert!(rollups(&mut conn, "cold").is_empty());
+        assert!(is_archived(&mut conn, "cold").unwrap());
+        // The code isn't handed out to another link while archived.
+        assert!(links::Candidates::Drawn(vec!["cold".into()]).pick(&mut conn).unwrap().is_none());
+
+        assert!(restore(&mut conn, "cold", Some("admin"), "192.0.2.1").unwrap());
+        assert!(!is_archived(&mut conn, "cold").unwrap());
+        assert_eq!(stored_row(&mut conn, "cold"), row);
+        let days: Vec<i64> = rollups(&mut conn, "cold").iter().map(|(_, clicks, _)| *clicks).collect();
+        assert_eq!(days, [5, 2, 1]);
+        assert_eq!(stats::total_clicks(&mut conn, "cold").unwrap(), total);
+        assert!(!restore(&mut conn, "cold", Some("admin"), "192.0.2.1").unwrap());
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State}, http::StatusCode, Extension, Json
+};
+use chrono::{Duration, NaiveDate};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::conversions;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::urls;
+use crate::stats;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// The most links one comparison may hold.
+const MAX_COMPARED: usize = 10;
+
+/// The span compared when `from` isn't given.
+const DEFAULT_DAYS: i64 = 30;
+
+/// The longest span one comparison may cover.
+const MAX_DAYS: i64 = 366;
+
+#[derive(Deserialize)]
+pub struct CompareQuery {
+    /// Comma-separated codes of the links to compare, e.g. the variants of a campaign.
+    ids: String,
+    /// The first day compared. Defaults to 30 days before `to`.
+    from: Option<NaiveDate>,
+    /// The last day compared, included. Defaults to today.
+    to: Option<NaiveDate>,
+    /// The IANA time zone days are counted in, e.g. `Europe/Berlin`. Defaults to UTC.
+    tz: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct ComparedLink {
+    id: String,
+    /// Clicks per day, aligned with the comparison's `days`.
+    clicks: Vec<i64>,
+    /// Unique visitors per day, aligned with the comparison's `days`.
+    visitors: Vec<i64>,
+    total_clicks: i64,
+    conversions: i64,
+    /// Conversions per click, or `None` without clicks.
+    conversion_rate: Option<f64>,
+    /// The link's part of the clicks on all compared links, or `None` if none had any.
+    share: Option<f64>
+}
+
+#[derive(Serialize)]
+pub struct Comparison {
+    from: NaiveDate,
+    to: NaiveDate,
+    tz: String,
+    days: Vec<String>,
+    total_clicks: i64,
+    links: Vec<ComparedLink>
+}
+
+/// The codes in `ids`, in the order given and without repeats.
+fn compared_ids(config: &AppConfig, ids: &str) -> Vec<String> {
+    let mut compared: Vec<String> = Vec::new();
+    for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let id = links::normalize_code(config, id.to_owned());
+        if !compared.contains(&id) {
+            compared.push(id);
+        }
+    }
+    compared
+}
+
+fn ratio(part: i64, whole: i64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 / whole as f64)
+}
+
+/// Daily clicks, unique visitors and conversions of several links side by side,
+/// over the same days, so variants of a campaign can be told apart at a glance.
+pub async fn compare_links(
+    Query(query): Query<CompareQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<Comparison>, ApiError> {
+    let ids = compared_ids(&config, &query.ids);
+    if ids.is_empty() || ids.len() > MAX_COMPARED {
+        return Err(
+            (StatusCode::BAD_REQUEST, format!("ids must name 1 to {MAX_COMPARED} links, separated by commas")).into()
+        )
+    }
+    let tz = stats::time_zone(query.tz.as_deref())?;
+    let to = query.to.unwrap_or_else(|| stats::today(tz));
+    let from = query.from.unwrap_or(to - Duration::days(DEFAULT_DAYS - 1));
+    if from > to {
+        return Err(
+            (StatusCode::BAD_REQUEST, "from must not be after to".into()).into()
+        )
+    }
+    if (to - from).num_days() >= MAX_DAYS {
+        return Err(
+            (StatusCode::BAD_REQUEST, format!("At most {MAX_DAYS} days can be compared at once")).into()
+        )
+    }
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let compared = conn.interact(move |conn| {
+        let found: Vec<String> = urls::table
+            .filter(urls::id.eq_any(&ids))
+            .select(urls::id)
+            .load(conn)?;
+        if let Some(missing) = ids.iter().find(|id| !found.contains(id)) {
+            return Ok(Err(missing.clone()))
+        }
+        let (start, end) = (stats::start_of_day(from, tz), stats::start_of_day(to + Duration::days(1), tz));
+        let mut compared = Vec::new();
+        for id in ids {
+            let daily: Vec<stats::DailyClicks> = stats::daily_clicks(conn, &id, from, tz)?
+                .into_iter()
+                .take((to - from).num_days() as usize + 1)
+                .collect();
+            let conversions = conversions::conversions_between(conn, &id, start, end)?;
+            compared.push((id, daily, conversions));
+        }
+        Ok::<_, diesel::result::Error>(Ok(compared))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let compared = compared.map_err(|missing| (StatusCode::NOT_FOUND, format!("Short URL {missing} not found")))?;
+    let total_clicks: i64 = compared.iter().flat_map(|(_, daily, _)| daily).map(|day| day.clicks).sum();
+    // Days yet to come don't count as empty, so the series may end before `to`.
+    let days = from.iter_days()
+        .take(compared.iter().map(|(_, daily, _)| daily.len()).max().unwrap_or(0))
+        .map(|day| day.to_string())
+        .collect();
+    let links = compared.into_iter()
+        .map(|(id, daily, conversions)| {
+            let clicks: i64 = daily.iter().map(|day| day.clicks).sum();
+            ComparedLink {
+                id,
+                clicks: daily.iter().map(|day| day.clicks).collect(),
+                visitors: daily.iter().map(|day| day.visitors).collect(),
+                total_clicks: clicks,
+                conversions,
+                conversion_rate: ratio(conversions, clicks),
+                share: ratio(clicks, total_clicks)
+            }
+        })
+        .collect();
+
+    Ok(Json(Comparison { from, to, tz: tz.name().to_owned(), days, total_clicks, links }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratios_are_undefined_without_a_whole() {
+        assert_eq!(ratio(1, 4), Some(0.25));
+        assert_eq!(ratio(0, 0), None);
+    }
+}
@@ -0,0 +1,598 @@
+use std::{env, fs, net::{IpAddr, SocketAddr}, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use axum::{
+    extract::{ConnectInfo, Request, State}, http::StatusCode, middleware::Next, response::Response, Extension
+};
+use chrono::NaiveDate;
+use deadpool_diesel::sqlite;
+use ipnet::IpNet;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::captcha::{self, Captcha};
+use crate::mail::Mailer;
+use crate::policy::RoutePolicy;
+use crate::roots::HostRoots;
+use crate::security_log::SecurityLog;
+use crate::settings;
+use crate::signing;
+use crate::snowflake;
+use crate::spam::SpamPolicy;
+use crate::well_known::StaticFiles;
+use crate::telemetry;
+
+/// How codes are minted for links created without an alias.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IdStrategy {
+    /// Random codes drawn from `ID_ALPHABET`; collisions are caught by the primary key.
+    Random,
+    /// Base-62 numbers counting up from 1: the shortest codes, but the easiest to enumerate.
+    Sequential,
+    /// Codes drawn from `ID_ALPHABET` by hashing the destination, so anyone
+    /// knowing a destination can find its code, but no one can guess others.
+    Hash,
+    /// Time-ordered ids unique per node, for instances sharing one database.
+    Snowflake { node_id: u16 }
+}
+
+impl IdStrategy {
+    /// The strategy called `name`, with snowflake ids minted as node `node_id`.
+    pub fn parse(name: &str, node_id: u16) -> Option<IdStrategy> {
+        match name {
+            "random" => Some(IdStrategy::Random),
+            "sequential" => Some(IdStrategy::Sequential),
+            "hash" => Some(IdStrategy::Hash),
+            "snowflake" => Some(IdStrategy::Snowflake { node_id }),
+            _ => None
+        }
+    }
+}
+
+const HEX_ALPHABET: &str = "1234567890abcdef";
+
+/// Leaves out characters easily confused with one another when read or retyped:
+/// 0/o, 1/l/i and the uppercase letters altogether.
+const SAFE_ALPHABET: &str = "23456789abcdefghjkmnpqrstuvwxyz";
+
+/// Well-known URL shorteners, whose links as destinations hide where a link really goes.
+const SHORTENER_DOMAINS: &str = "bit.ly,tinyurl.com,t.co,goo.gl,ow.ly,is.gd,buff.ly,rebrand.ly,cutt.ly,shorturl.at,tiny.cc,rb.gy";
+
+const REFERRER_POLICIES: [&str; 8] = [
+    "no-referrer", "no-referrer-when-downgrade", "origin", "origin-when-cross-origin",
+    "same-origin", "strict-origin", "strict-origin-when-cross-origin", "unsafe-url"
+];
+
+/// What to do with clicks that aren't logged like others, such as those from
+/// visitors sending `DNT: 1` or `Sec-GPC: 1`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrackingOptOut {
+    /// Log the click like any other.
+    Ignore,
+    /// Don't count the click at all.
+    Skip,
+    /// Only bump the link's total, leaving no per-click row behind.
+    Aggregate
+}
+
+impl TrackingOptOut {
+    fn parse(name: &str, default: TrackingOptOut) -> Result<TrackingOptOut, String> {
+        match env::var(name).as_deref() {
+            Err(_) => Ok(default),
+            Ok("ignore") => Ok(TrackingOptOut::Ignore),
+            Ok("skip") => Ok(TrackingOptOut::Skip),
+            Ok("aggregate") => Ok(TrackingOptOut::Aggregate),
+            Ok(other) => Err(format!("Unknown {name} {other}; expected ignore, skip or aggregate"))
+        }
+    }
+}
+
+pub struct AppConfig {
+    pub listen_addrs: Vec<SocketAddr>,
+    pub admin_listen_addrs: Vec<SocketAddr>,
+    pub addr: String,
+    /// Hosts the instance was reachable at before, redirected to `addr`.
+    pub legacy_hosts: Vec<String>,
+    /// What `/` answers with, per host the request was sent to.
+    pub host_roots: HostRoots,
+    pub nano_id_alphabet: Vec<char>,
+    pub id_length: usize,
+    pub id_checksum: bool,
+    pub code_signing_key: Option<String>,
+    pub signature_length: usize,
+    pub case_insensitive_codes: bool,
+    pub id_strategy: IdStrategy,
+    /// Which node of instances sharing a database this is, for snowflake ids.
+    pub node_id: u16,
+    pub admin_token: Option<String>,
+    pub create_requires_token: bool,
+    /// Links each API token may create a month; 0 is unlimited.
+    pub link_quota: i64,
+    /// Redirects the links of each API token may answer a month; 0 is unlimited.
+    pub redirect_quota: i64,
+    pub captcha: Option<Captcha>,
+    /// Shared with the creation transaction, which checks for bursts.
+    pub spam: Arc<SpamPolicy>,
+    pub blocked_domains: Vec<String>,
+    /// Address ranges, such as an office or VPN, exempt from CAPTCHA and throttling.
+    pub trusted_networks: Vec<IpNet>,
+    /// Announced to clients of the unversioned `/api` paths as the day they stop working.
+    pub api_sunset: Option<NaiveDate>,
+    /// Lets links point to, and the service fetch, loopback, private and metadata addresses.
+    pub allow_private_destinations: bool,
+    /// Refuses new links while set, e.g. during a migration.
+    pub maintenance_mode: bool,
+    pub idempotency_ttl: Duration,
+    /// How long a handler may take before the request is answered with `503`.
+    pub request_timeout: Duration,
+    /// The same for routes going through many rows, such as bulk changes.
+    pub slow_request_timeout: Duration,
+    pub reserved_aliases: Vec<String>,
+    pub slack_signing_secret: Option<String>,
+    pub telegram_bot_token: Option<String>,
+    pub redirect_status: StatusCode,
+    pub redirect_max_age: Duration,
+    pub redirect_cache_scope: &'static str,
+    pub compression: Vec<String>,
+    pub max_url_length: usize,
+    pub max_body_bytes: usize,
+    /// The most API tokens that metrics get an `actor` label of their own for.
+    pub metrics_max_actors: usize,
+    pub force_https: bool,
+    pub hsts_max_age: Duration,
+    pub tracking_opt_out: TrackingOptOut,
+    /// What to do with prefetches and link previews, which no visitor asked for.
+    pub automated_clicks: TrackingOptOut,
+    pub referrer_policy: Option<String>,
+    pub visitor_salt: Option<String>,
+    pub click_id_param: Option<String>,
+    /// Repeat clicks of a visitor on a link within this long of the last one aren't counted.
+    pub click_dedup_window: Duration,
+    /// Signs the tokens of preview links, which show stats that aren't public.
+    pub preview_signing_key: Option<String>,
+    /// Days raw clicks are kept once rolled up into daily counts; 0 keeps them forever.
+    pub click_retention_days: u32,
+    /// Months without a click after which links are archived; 0 keeps them all in place.
+    pub cold_link_months: u32,
+    pub interstitial_delay: Duration,
+    pub not_found_page: Option<String>,
+    pub disabled_page: Option<String>,
+    pub pending_page: Option<String>,
+    pub support_contact: Option<String>,
+    pub static_files: StaticFiles,
+    pub scan_max_misses: u32,
+    pub scan_window: Duration,
+    /// Added to the lookups of an address for each unknown code it asked for in the window.
+    pub scan_tarpit_step: Duration,
+    pub scan_tarpit_max: Duration,
+    /// Decoy codes never issued, which only scanners ask for.
+    pub honeypot_codes: Vec<String>,
+    pub honeypot_block: Duration,
+    pub redirect_min_latency: Duration,
+    pub security_log: SecurityLog,
+    pub route_policy: RoutePolicy,
+    pub mailer: Option<Mailer>,
+    pub admin_email: Option<String>,
+    pub archive_on_create: bool,
+    pub archive_interval: Duration,
+    pub screenshot_url: Option<String>,
+    pub screenshot_dir: PathBuf,
+    pub health_check_interval: Duration,
+    pub health_check_max_failures: i32,
+    pub anomaly_check_interval: Duration,
+    /// The UTC hour at which the database is vacuumed and analysed each day.
+    pub db_maintenance_hour: Option<u32>,
+    /// Only counts clicks on links flagged for abnormal clicks, without recording them.
+    pub throttle_anomalous_clicks: bool,
+    /// Serves `/sitemap.xml` with the links flagged as indexable.
+    pub sitemap: bool
+}
+
+/// The configuration currently in effect, swapped out wholesale on reload.
+pub type LiveConfig = Arc<ArcSwap<AppConfig>>;
+
+/// Reads a secret from the file named by `<name>_FILE` (as mounted by Docker and
+/// Kubernetes secrets), falling back to the `<name>` variable itself.
+pub fn secret(name: &str) -> Result<Option<String>, String> {
+    match env::var(format!("{name}_FILE")) {
+        Ok(path) => fs::read_to_string(&path)
+            .map(|value| Some(value.trim_end_matches(['\r', '\n']).to_owned()))
+            .map_err(|err| format!("Can't read {name}_FILE {path}: {err}")),
+        Err(_) => Ok(env::var(name).ok())
+    }
+}
+
+/// Reads the file named by the `name` variable, if set.
+fn file(name: &str) -> Result<Option<String>, String> {
+    env::var(name)
+        .ok()
+        .map(|path| fs::read_to_string(&path).map_err(|err| format!("Can't read {name} {path}: {err}")))
+        .transpose()
+}
+
+/// The redirect status codes links may answer with.
+pub fn redirect_status(code: u16) -> Result<StatusCode, String> {
+    match code {
+        301 | 302 | 303 | 307 | 308 => Ok(StatusCode::from_u16(code).expect("redirect codes are valid")),
+        _ => Err(format!("REDIRECT_STATUS must be 301, 302, 303, 307 or 308, not {code}"))
+    }
+}
+
+/// The number `name` is set to, or `default` if it's unset or blank.
+fn parsed<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value.trim()
+            .parse()
+            .map_err(|_| format!("{name} must be a number, not {value:?}")),
+        _ => Ok(default)
+    }
+}
+
+fn flag(name: &str) -> bool {
+    matches!(env::var(name).as_deref(), Ok("true" | "1"))
+}
+
+/// Parses address ranges in CIDR notation, e.g. `10.8.0.0/16`. A bare address is
+/// a range of one.
+pub fn networks(ranges: &[String]) -> Result<Vec<IpNet>, String> {
+    ranges.iter()
+        .map(|range| {
+            range.parse::<IpNet>()
+                .or_else(|_| range.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("{range} isn't an address range like 10.8.0.0/16"))
+        })
+        .collect()
+}
+
+fn list(name: &str, default: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_else(|_| default.into())
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl AppConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let addrs = |name, default| list(name, default)
+            .iter()
+            .map(|addr| addr.parse().map_err(|_| format!("Invalid listen address {addr}")))
+            .collect::<Result<Vec<SocketAddr>, _>>();
+        let listen_addrs = addrs("LISTEN_ADDRS", "127.0.0.1:3000")?;
+        let admin_listen_addrs = addrs("ADMIN_LISTEN_ADDRS", "")?;
+        if listen_addrs.is_empty() {
+            return Err("LISTEN_ADDRS must contain at least one address".into())
+        }
+
+        let node_id = parsed("NODE_ID", 0)?;
+        if node_id > snowflake::MAX_NODE_ID {
+            return Err(format!("NODE_ID must be at most {}", snowflake::MAX_NODE_ID))
+        }
+        let id_strategy = match env::var("ID_STRATEGY") {
+            Err(_) => IdStrategy::Random,
+            Ok(name) => IdStrategy::parse(&name, node_id)
+                .ok_or_else(|| format!("Unknown ID_STRATEGY {name}; expected random, sequential, hash or snowflake"))?
+        };
+
+        let id_checksum = flag("ID_CHECKSUM");
+        if id_checksum && !matches!(id_strategy, IdStrategy::Random) {
+            return Err("ID_CHECKSUM only works with the random ID_STRATEGY".into())
+        }
+
+        let code_signing_key = secret("CODE_SIGNING_KEY")?;
+        if code_signing_key.is_some() && !matches!(id_strategy, IdStrategy::Random) {
+            return Err("CODE_SIGNING_KEY only works with the random ID_STRATEGY".into())
+        }
+        let signature_length = parsed("SIGNATURE_LENGTH", 8)?;
+        if !(1..=signing::MAX_LENGTH).contains(&signature_length) {
+            return Err(format!("SIGNATURE_LENGTH must be between 1 and {}", signing::MAX_LENGTH))
+        }
+
+        let tracking_opt_out = TrackingOptOut::parse("TRACKING_OPT_OUT", TrackingOptOut::Ignore)?;
+        let automated_clicks = TrackingOptOut::parse("AUTOMATED_CLICKS", TrackingOptOut::Skip)?;
+
+        let referrer_policy = env::var("REFERRER_POLICY").ok();
+        if let Some(policy) = referrer_policy.as_deref().filter(|policy| !REFERRER_POLICIES.contains(policy)) {
+            return Err(format!("Unknown REFERRER_POLICY {policy}"))
+        }
+
+        let case_insensitive_codes = flag("CASE_INSENSITIVE_CODES");
+        if case_insensitive_codes && !matches!(id_strategy, IdStrategy::Random) {
+            return Err("CASE_INSENSITIVE_CODES only works with the random ID_STRATEGY".into())
+        }
+
+        let mailer = match (secret("SMTP_URL")?, env::var("MAIL_FROM")) {
+            (Some(url), Ok(from)) => Some(Mailer::new(&url, &from)?),
+            (Some(_), Err(_)) => return Err("MAIL_FROM must be set along with SMTP_URL".into()),
+            (None, _) => None
+        };
+
+        let captcha = match env::var("CAPTCHA_PROVIDER").as_deref() {
+            Err(_) => None,
+            Ok(name) => {
+                let Some(provider) = captcha::Provider::parse(name) else {
+                    return Err(format!("Unknown CAPTCHA_PROVIDER {name}; expected turnstile or hcaptcha"))
+                };
+                let (Ok(site_key), Some(secret)) = (env::var("CAPTCHA_SITE_KEY"), secret("CAPTCHA_SECRET")?) else {
+                    return Err("CAPTCHA_SITE_KEY and CAPTCHA_SECRET must be set along with CAPTCHA_PROVIDER".into())
+                };
+                Some(Captcha { provider, site_key, secret, verify_url: env::var("CAPTCHA_VERIFY_URL").ok() })
+            }
+        };
+
+        let spam = SpamPolicy {
+            quarantine_score: parsed("SPAM_QUARANTINE_SCORE", 0)?,
+            tlds: list("SPAM_TLDS", "")
+                .into_iter()
+                .map(|tld| tld.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            shorteners: list("SHORTENER_DOMAINS", SHORTENER_DOMAINS)
+                .into_iter()
+                .map(|domain| domain.to_ascii_lowercase())
+                .collect(),
+            new_domains: file("NEW_DOMAINS_FILE")?
+                .unwrap_or_default()
+                .lines()
+                .map(|line| line.trim().to_ascii_lowercase())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect(),
+            burst_limit: parsed("SPAM_BURST_LIMIT", 10)?,
+            burst_window: chrono::Duration::seconds(parsed("SPAM_BURST_WINDOW_SECS", 600)?)
+        };
+
+        let screenshot_url = secret("SCREENSHOT_URL")?;
+        if screenshot_url.as_deref().is_some_and(|url| !url.contains("{url}")) {
+            return Err("SCREENSHOT_URL must contain a {url} placeholder".into())
+        }
+
+        let api_sunset = env::var("API_SUNSET")
+            .ok()
+            .map(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d"))
+            .transpose()
+            .map_err(|_| "API_SUNSET must be a date like 2027-06-30".to_owned())?;
+
+        let db_maintenance_hour = env::var("DB_MAINTENANCE_HOUR")
+            .ok()
+            .map(|hour| hour.parse::<u32>().ok().filter(|hour| *hour < 24))
+            .map(|hour| hour.ok_or("DB_MAINTENANCE_HOUR must be an hour from 0 to 23"))
+            .transpose()?;
+
+        let alphabet = match env::var("ID_ALPHABET").as_deref() {
+            Err(_) | Ok("hex") => HEX_ALPHABET.into(),
+            Ok("safe") => SAFE_ALPHABET.into(),
+            Ok(custom) => custom.to_owned()
+        };
+        let alphabet: Vec<char> = if case_insensitive_codes { alphabet.to_lowercase() } else { alphabet }
+            .chars()
+            .collect();
+        if let Some(c) = alphabet.iter().find(|c| c.is_whitespace() || "/?#%".contains(**c)) {
+            return Err(format!("ID_ALPHABET can't contain {c:?}"))
+        }
+        if alphabet.len() < 2 || alphabet.len() > 255 {
+            return Err("ID_ALPHABET must have between 2 and 255 characters".into())
+        }
+        if alphabet.iter().enumerate().any(|(i, c)| alphabet[..i].contains(c)) {
+            return Err("ID_ALPHABET must not repeat characters, ignoring case if CASE_INSENSITIVE_CODES is set".into())
+        }
+
+        Ok(AppConfig {
+            addr: env::var("PUBLIC_ADDR").unwrap_or_else(|_| listen_addrs[0].to_string()),
+            legacy_hosts: list("LEGACY_HOSTS", "").into_iter().map(|host| host.to_ascii_lowercase()).collect(),
+            host_roots: HostRoots::parse(env::var("HOST_ROOTS").ok().as_deref())?,
+            listen_addrs,
+            admin_listen_addrs,
+            nano_id_alphabet: alphabet,
+            id_length: parsed("ID_LENGTH", 10)?,
+            id_checksum,
+            code_signing_key,
+            signature_length,
+            case_insensitive_codes,
+            id_strategy,
+            node_id,
+            admin_token: secret("ADMIN_TOKEN")?,
+            create_requires_token: flag("CREATE_REQUIRES_TOKEN"),
+            link_quota: parsed("QUOTA_LINKS_PER_MONTH", 0)?,
+            redirect_quota: parsed("QUOTA_REDIRECTS_PER_MONTH", 0)?,
+            captcha,
+            spam: Arc::new(spam),
+            blocked_domains: list("BLOCKED_DOMAINS", "")
+                .into_iter()
+                .map(|domain| domain.to_ascii_lowercase())
+                .collect(),
+            trusted_networks: networks(&list("TRUSTED_NETWORKS", ""))?,
+            api_sunset,
+            allow_private_destinations: flag("ALLOW_PRIVATE_DESTINATIONS"),
+            maintenance_mode: flag("MAINTENANCE_MODE"),
+            idempotency_ttl: Duration::from_secs(parsed("IDEMPOTENCY_KEY_TTL_SECS", 24 * 60 * 60)?),
+            request_timeout: Duration::from_secs(parsed("REQUEST_TIMEOUT_SECS", 30)?),
+            slow_request_timeout: Duration::from_secs(parsed("SLOW_REQUEST_TIMEOUT_SECS", 300)?),
+            reserved_aliases: list("RESERVED_ALIASES", ""),
+            slack_signing_secret: secret("SLACK_SIGNING_SECRET")?,
+            telegram_bot_token: secret("TELEGRAM_BOT_TOKEN")?,
+            redirect_status: redirect_status(parsed("REDIRECT_STATUS", 303)?)?,
+            redirect_max_age: Duration::from_secs(parsed("REDIRECT_MAX_AGE_SECS", 0)?),
+            // Shared caches answering redirects themselves means those clicks are never counted.
+            redirect_cache_scope: if flag("REDIRECT_CACHE_PUBLIC") { "public" } else { "private" },
+            compression: list("COMPRESSION", "gzip,br")
+                .into_iter()
+                .map(|algorithm| algorithm.to_ascii_lowercase())
+                .collect(),
+            max_url_length: parsed("MAX_URL_LENGTH", 2048)?,
+            max_body_bytes: parsed("MAX_BODY_BYTES", 64 * 1024)?,
+            metrics_max_actors: parsed("METRICS_MAX_ACTORS", 100)?,
+            force_https: flag("FORCE_HTTPS"),
+            hsts_max_age: Duration::from_secs(parsed("HSTS_MAX_AGE_SECS", 365 * 24 * 60 * 60)?),
+            tracking_opt_out,
+            automated_clicks,
+            referrer_policy,
+            visitor_salt: secret("VISITOR_SALT")?,
+            click_id_param: env::var("CLICK_ID_PARAM").ok().filter(|param| !param.is_empty()),
+            click_dedup_window: Duration::from_secs(parsed("CLICK_DEDUP_WINDOW_SECS", 0)?),
+            preview_signing_key: secret("PREVIEW_SIGNING_KEY")?,
+            click_retention_days: parsed("CLICK_RETENTION_DAYS", 0)?,
+            cold_link_months: parsed("COLD_LINK_MONTHS", 0)?,
+            interstitial_delay: Duration::from_secs(parsed("INTERSTITIAL_DELAY_SECS", 5)?),
+            not_found_page: file("NOT_FOUND_PAGE")?,
+            disabled_page: file("DISABLED_PAGE")?,
+            pending_page: file("PENDING_PAGE")?,
+            support_contact: env::var("SUPPORT_CONTACT").ok(),
+            static_files: StaticFiles::load(
+                env::var("FAVICON_PATH").ok().as_deref(),
+                env::var("ROBOTS_TXT_PATH").ok().as_deref(),
+                env::var("WELL_KNOWN_DIR").ok()
+            )?,
+            scan_max_misses: parsed("SCAN_MAX_NOT_FOUND", 0)?,
+            scan_window: Duration::from_secs(parsed("SCAN_WINDOW_SECS", 60)?),
+            scan_tarpit_step: Duration::from_millis(parsed("SCAN_TARPIT_STEP_MS", 0)?),
+            scan_tarpit_max: Duration::from_millis(parsed("SCAN_TARPIT_MAX_MS", 10_000)?),
+            honeypot_codes: list("HONEYPOT_CODES", ""),
+            honeypot_block: Duration::from_secs(parsed("HONEYPOT_BLOCK_SECS", 60 * 60)?),
+            redirect_min_latency: Duration::from_millis(parsed("REDIRECT_MIN_LATENCY_MS", 0)?),
+            security_log: SecurityLog::parse(env::var("SECURITY_LOG").ok().as_deref())?,
+            route_policy: RoutePolicy::parse(env::var("ROUTE_POLICY").ok().as_deref())?,
+            mailer,
+            admin_email: env::var("ADMIN_EMAIL").ok(),
+            archive_on_create: flag("ARCHIVE_ON_CREATE"),
+            archive_interval: Duration::from_secs(parsed("ARCHIVE_INTERVAL_SECS", 15)?),
+            screenshot_url,
+            screenshot_dir: env::var("SCREENSHOT_DIR").unwrap_or_else(|_| "screenshots".into()).into(),
+            health_check_interval: Duration::from_secs(parsed("HEALTH_CHECK_INTERVAL_SECS", 0)?),
+            health_check_max_failures: parsed("HEALTH_CHECK_MAX_FAILURES", 0)?,
+            anomaly_check_interval: Duration::from_secs(parsed("ANOMALY_CHECK_INTERVAL_SECS", 0)?),
+            db_maintenance_hour,
+            throttle_anomalous_clicks: flag("THROTTLE_ANOMALOUS_CLICKS"),
+            sitemap: flag("SITEMAP")
+        })
+    }
+
+    /// Whether `ip` is in one of the trusted networks.
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        self.trusted_networks.iter().any(|network| network.contains(&ip))
+    }
+
+    /// The setting that only works with random codes, if one is on, which rules
+    /// out minting codes any other way.
+    pub fn requires_random_codes(&self) -> Option<&'static str> {
+        if self.id_checksum {
+            Some("ID_CHECKSUM")
+        } else if self.code_signing_key.is_some() {
+            Some("CODE_SIGNING_KEY")
+        } else if self.case_insensitive_codes {
+            Some("CASE_INSENSITIVE_CODES")
+        } else {
+            None
+        }
+    }
+
+    /// Copies over the settings that are baked into listeners, layers and
+    /// background tasks at startup and so can't change without a restart.
+    fn keep_startup_settings(mut self, current: &AppConfig) -> Self {
+        self.listen_addrs.clone_from(&current.listen_addrs);
+        self.admin_listen_addrs.clone_from(&current.admin_listen_addrs);
+        self.compression.clone_from(&current.compression);
+        self.max_body_bytes = current.max_body_bytes;
+        // Changing how codes are minted or matched on a live node could hand out
+        // duplicate snowflakes or make existing codes unreachable.
+        self.id_strategy = current.id_strategy;
+        self.node_id = current.node_id;
+        self.nano_id_alphabet.clone_from(&current.nano_id_alphabet);
+        self.id_length = current.id_length;
+        self.id_checksum = current.id_checksum;
+        self.code_signing_key.clone_from(&current.code_signing_key);
+        self.signature_length = current.signature_length;
+        self.case_insensitive_codes = current.case_insensitive_codes;
+        self.telegram_bot_token.clone_from(&current.telegram_bot_token);
+        self.archive_on_create = current.archive_on_create;
+        self.archive_interval = current.archive_interval;
+        self.screenshot_url.clone_from(&current.screenshot_url);
+        self.screenshot_dir.clone_from(&current.screenshot_dir);
+        self.health_check_interval = current.health_check_interval;
+        self.anomaly_check_interval = current.anomaly_check_interval;
+        self.db_maintenance_hour = current.db_maintenance_hour;
+        // The background fetchers were set up with the policy in effect at startup.
+        self.allow_private_destinations = current.allow_private_destinations;
+        self
+    }
+}
+
+/// Re-reads `.env` and the environment and swaps in the resulting configuration.
+/// The current configuration stays in effect if the new one is invalid.
+pub fn reload(live: &LiveConfig) -> Result<(), String> {
+    dotenvy::dotenv_override().ok();
+    let current = live.load();
+    let fresh = settings::overlay(AppConfig::from_env()?.keep_startup_settings(&current));
+    live.store(Arc::new(fresh));
+    println!("Configuration reloaded");
+    Ok(())
+}
+
+/// Makes the configuration current at the start of each request available to
+/// handlers as an `Extension<Arc<AppConfig>>`.
+pub async fn inject(
+    Extension(live): Extension<LiveConfig>,
+    mut req: Request,
+    next: Next
+) -> Response {
+    req.extensions_mut().insert(live.load_full());
+    next.run(req).await
+}
+
+/// Reloads the configuration whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_sighup(live: LiveConfig) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup()).expect("SIGHUP handler can be installed");
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(err) = reload(&live) {
+                println!("Configuration reload failed: {err}");
+            }
+        }
+    });
+}
+
+pub async fn reload_config(
+    Extension(live): Extension<LiveConfig>,
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    reload(&live)
+        .map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err))?;
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    conn.interact(move |conn| {
+        audit::record(conn, NewAuditEntry {
+            actor: actor.name(),
+            action: "reload_config",
+            target: None,
+            ip: Some(&addr.ip().to_string())
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_numbers_name_the_variable() {
+        env::set_var("TEST_PARSED_LIMIT", " 12 ");
+        assert_eq!(parsed("TEST_PARSED_LIMIT", 5), Ok(12));
+        env::set_var("TEST_PARSED_LIMIT", "12k");
+        assert_eq!(parsed("TEST_PARSED_LIMIT", 5), Err::<u32, _>("TEST_PARSED_LIMIT must be a number, not \"12k\"".into()));
+        env::remove_var("TEST_PARSED_LIMIT");
+        assert_eq!(parsed("TEST_PARSED_LIMIT", 5), Ok(5));
+    }
+}
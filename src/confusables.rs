@@ -0,0 +1,73 @@
+/// Scripts whose letters are commonly mistaken for one another.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian
+}
+
+/// Greek, Cyrillic and Armenian letters that render like a Latin letter in most fonts.
+const LATIN_LOOKALIKES: &str = "αβεηικνορτυχΑΒΕΖΗΙΚΜΝΟΡΤΥΧ\
+    аеёіјкорсухѕԁԛԝһӏАВЕЅІЈКМНОРСТХУԌԜ\
+    օսոհզց";
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        '\u{0530}'..='\u{058F}' => Some(Script::Armenian),
+        _ => None
+    }
+}
+
+/// Whether `text` could pass for different Latin text: it mixes letters of
+/// several scripts, or spells a word entirely in non-Latin lookalikes.
+pub fn is_confusable(text: &str) -> bool {
+    let mut scripts = text.chars().filter_map(script);
+    let Some(first) = scripts.next() else {
+        return false
+    };
+    if scripts.any(|other| other != first) {
+        return true
+    }
+    first != Script::Latin
+        && text.chars()
+            .filter(|&c| script(c).is_some())
+            .all(|c| LATIN_LOOKALIKES.contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_aliases_are_not_confusable() {
+        assert!(!is_confusable("paypal"));
+        assert!(!is_confusable("launch-2024_v2"));
+        assert!(!is_confusable("café"));
+        assert!(!is_confusable("1234"));
+        assert!(!is_confusable(""));
+    }
+
+    #[test]
+    fn mixed_scripts_are_confusable() {
+        // Cyrillic "а" in an otherwise Latin word.
+        assert!(is_confusable("p\u{0430}ypal"));
+        assert!(is_confusable("αbc"));
+    }
+
+    #[test]
+    fn whole_script_lookalikes_are_confusable() {
+        // Entirely Cyrillic, but reads as "pac".
+        assert!(is_confusable("рас"));
+        assert!(is_confusable("ΑΒΕ"));
+    }
+
+    #[test]
+    fn genuine_non_latin_words_are_not_confusable() {
+        assert!(!is_confusable("привет"));
+        assert!(!is_confusable("λόγος"));
+    }
+}
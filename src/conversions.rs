@@ -0,0 +1,128 @@
+use axum::{
+    extract::{Query, State}, http::{header, Method, StatusCode}, response::IntoResponse, Json
+};
+use chrono::NaiveDateTime;
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, result::DatabaseErrorKind
+};
+use serde::Deserialize;
+use tower_http::cors::{Any, CorsLayer};
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::schema::{clicks, conversions};
+use crate::telemetry;
+
+const DEFAULT_EVENT: &str = "conversion";
+const MAX_EVENT_LENGTH: usize = 64;
+
+/// A transparent 1x1 GIF, for pages that report conversions with an `<img>` tag.
+const PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b
+];
+
+#[derive(Deserialize)]
+pub struct ConversionRequest {
+    /// The click token passed to the destination, not the click's row id.
+    click_id: String,
+    event: Option<String>
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = conversions)]
+struct NewConversion<'a> {
+    click_id: i32,
+    url_id: &'a str,
+    event: &'a str
+}
+
+pub fn total_conversions(conn: &mut SqliteConnection, url_id: &str) -> QueryResult<i64> {
+    conversions::table
+        .filter(conversions::url_id.eq(url_id))
+        .count()
+        .get_result(conn)
+}
+
+/// Conversions of a link recorded from `start` up to but excluding `end`.
+pub fn conversions_between(conn: &mut SqliteConnection, url_id: &str, start: NaiveDateTime, end: NaiveDateTime) -> QueryResult<i64> {
+    conversions::table
+        .filter(conversions::url_id.eq(url_id))
+        .filter(conversions::created_at.ge(start))
+        .filter(conversions::created_at.lt(end))
+        .count()
+        .get_result(conn)
+}
+
+/// Attributes a conversion to the link the click went through. Reporting the
+/// same event for a click again is accepted but counted once.
+async fn record(pool: &sqlite::Pool, request: ConversionRequest) -> Result<(), ApiError> {
+    let event = request.event.unwrap_or_else(|| DEFAULT_EVENT.into());
+    if event.is_empty() || event.len() > MAX_EVENT_LENGTH {
+        return Err(
+            (StatusCode::BAD_REQUEST, format!("Event names must be 1 to {MAX_EVENT_LENGTH} bytes long")).into()
+        )
+    }
+
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(unavailable)?;
+
+    let found = conn.interact(move |conn| {
+        let Some((click_id, url_id)) = clicks::table
+            .filter(clicks::token.eq(&request.click_id))
+            .select((clicks::id, clicks::url_id))
+            .get_result::<(i32, String)>(conn)
+            .optional()?
+        else {
+            return Ok(false)
+        };
+
+        let inserted = diesel::insert_into(conversions::table)
+            .values(NewConversion { click_id, url_id: &url_id, event: &event })
+            .execute(conn);
+        match inserted {
+            Ok(_) | Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Ok(true),
+            Err(err) => Err(err)
+        }
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if !found {
+        return Err(
+            (StatusCode::NOT_FOUND, "Click not found".into()).into()
+        )
+    }
+    Ok(())
+}
+
+/// Destination pages report conversions from their own origin, and a JSON body
+/// needs a CORS preflight. Clicks are identified by unguessable tokens, so any origin may report.
+pub fn cors() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([Method::POST])
+        .allow_headers([header::CONTENT_TYPE])
+}
+
+pub async fn report_conversion(
+    State(pool): State<sqlite::Pool>,
+    Json(request): Json<ConversionRequest>
+) -> Result<StatusCode, ApiError> {
+    record(&pool, request).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn conversion_pixel(
+    State(pool): State<sqlite::Pool>,
+    Query(request): Query<ConversionRequest>
+) -> Result<impl IntoResponse, ApiError> {
+    record(&pool, request).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "image/gif"), (header::CACHE_CONTROL, "no-store")],
+        PIXEL
+    ))
+}
@@ -0,0 +1,75 @@
+use crate::errors::ApiError;
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// How a listing's timestamps are spelled inside a cursor.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// One page of a listing, ordered newest first.
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `?after=` to continue after the last item, absent on the last page.
+    pub next: Option<String>
+}
+
+/// An opaque position in a listing ordered by a timestamp and then a unique key,
+/// the only order keyset pagination can continue without scanning skipped rows.
+pub fn encode(at: NaiveDateTime, key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}|{key}", at.format(TIMESTAMP_FORMAT)))
+}
+
+pub fn decode(cursor: &str) -> Result<(NaiveDateTime, String), ApiError> {
+    let invalid = || (StatusCode::BAD_REQUEST, "Invalid cursor".to_owned());
+    let decoded = URL_SAFE_NO_PAD.decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(invalid)?;
+    let (at, key) = decoded.split_once('|').ok_or_else(invalid)?;
+    let at = NaiveDateTime::parse_from_str(at, TIMESTAMP_FORMAT).map_err(|_| invalid())?;
+    Ok((at, key.to_owned()))
+}
+
+/// Turns one row more than the page holds into the page and the cursor after it.
+/// `position` gives the timestamp and key the listing is ordered by.
+pub fn paginate<T>(mut rows: Vec<T>, limit: i64, position: impl Fn(&T) -> (NaiveDateTime, String)) -> Page<T> {
+    let more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let next = rows.last()
+        .filter(|_| more)
+        .map(|last| {
+            let (at, key) = position(last);
+            encode(at, &key)
+        });
+    Page { items: rows, next }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_positions() {
+        let at = NaiveDateTime::parse_from_str("2024-05-01 12:30:00.250", "%Y-%m-%d %H:%M:%S%.f").unwrap();
+        assert_eq!(decode(&encode(at, "a|b")).unwrap(), (at, "a|b".to_owned()));
+    }
+
+    #[test]
+    fn rejects_tampered_cursors() {
+        assert!(decode("not a cursor").is_err());
+        assert!(decode(&URL_SAFE_NO_PAD.encode("yesterday|abc")).is_err());
+        assert!(decode(&URL_SAFE_NO_PAD.encode("2024-05-01T12:30:00")).is_err());
+    }
+
+    #[test]
+    fn only_full_pages_continue() {
+        let rows = vec![3, 2, 1];
+        let position = |row: &i32| (NaiveDateTime::default(), row.to_string());
+        let page = paginate(rows.clone(), 2, position);
+        assert_eq!(page.items, [3, 2]);
+        assert_eq!(decode(page.next.as_deref().unwrap()).unwrap().1, "2");
+        assert!(paginate(rows, 3, position).next.is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use deadpool::managed::HookError;
+use deadpool_diesel::sqlite::{self, Hook};
+use diesel::{migration::MigrationSource, prelude::*, sql_types::{BigInt, Text, Timestamp}, sqlite::Sqlite};
+use diesel_migrations::MigrationHarness;
+
+use crate::MIGRATIONS;
+
+/// How long a connection waits for another one's write lock before giving up
+/// with "database is locked". Background workers write alongside requests.
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// A pool hook that makes every new connection wait out short write locks.
+pub fn wait_for_locks() -> Hook {
+    Hook::async_fn(|conn, _| {
+        Box::pin(async move {
+            conn.interact(|conn| diesel::sql_query(format!("PRAGMA busy_timeout = {BUSY_TIMEOUT_MS}")).execute(conn))
+                .await
+                .map_err(|err| HookError::Message(err.to_string()))?
+                .map_err(|err| HookError::Backend(deadpool_diesel::Error::Ping(err)))?;
+            Ok(())
+        })
+    })
+}
+
+#[derive(QueryableByName)]
+struct DatabaseSize {
+    #[diesel(sql_type = BigInt)]
+    bytes: i64
+}
+
+/// The size of the database file in bytes, free pages included.
+pub fn size(conn: &mut SqliteConnection) -> QueryResult<i64> {
+    let size: DatabaseSize = diesel::sql_query(
+        "SELECT page_count * page_size AS bytes FROM pragma_page_count(), pragma_page_size()"
+    )
+    .get_result(conn)?;
+    Ok(size.bytes)
+}
+
+sql_function! {
+    /// The lowercased host of a URL, or NULL if it has none, e.g. for searching
+    /// destinations by domain however their URLs are spelled.
+    fn url_host(url: Text) -> Nullable<Text>;
+}
+
+sql_function! {
+    /// The day a UTC timestamp falls on in an IANA time zone, e.g. for counting
+    /// clicks per local day. Zones that don't exist count as UTC.
+    fn local_date(at: Timestamp, tz: Text) -> Text;
+}
+
+fn define_functions(conn: &mut SqliteConnection) -> QueryResult<()> {
+    url_host::register_impl(conn, |url: String| {
+        url::Url::parse(&url).ok().and_then(|url| url.host_str().map(str::to_owned))
+    })?;
+    local_date::register_impl(conn, |at: NaiveDateTime, tz: String| {
+        let tz = tz.parse::<Tz>().unwrap_or(Tz::UTC);
+        at.and_utc().with_timezone(&tz).date_naive().to_string()
+    })
+}
+
+/// A pool hook that defines the SQL functions queries rely on, like [`url_host`],
+/// on every new connection.
+pub fn register_functions() -> Hook {
+    Hook::async_fn(|conn, _| {
+        Box::pin(async move {
+            conn.interact(define_functions)
+                .await
+                .map_err(|err| HookError::Message(err.to_string()))?
+                .map_err(|err| HookError::Backend(deadpool_diesel::Error::Ping(err)))?;
+            Ok(())
+        })
+    })
+}
+
+/// Applies the embedded migrations the database is missing, returning their
+/// versions. Refuses to touch a database migrated by a newer build, since this
+/// one's queries wouldn't match its schema.
+pub async fn migrate(pool: &sqlite::Pool) -> Result<Vec<String>, String> {
+    let conn = pool.get()
+        .await
+        .map_err(|err| format!("Can't connect to the database: {err}; check DATABASE_URL"))?;
+    conn.interact(|conn| {
+        let known: Vec<String> = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)?
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .collect();
+        let unknown: Vec<String> = conn.applied_migrations()?
+            .iter()
+            .map(ToString::to_string)
+            .filter(|version| !known.contains(version))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(format!(
+                "The database has migrations this build doesn't know about ({}); run a newer shorturl or restore a matching backup",
+                unknown.join(", ")
+            ).into())
+        }
+        let applied = conn.run_pending_migrations(MIGRATIONS)?;
+        Ok(applied.iter().map(ToString::to_string).collect())
+    })
+    .await
+    .map_err(|err| format!("Migrating the database failed: {err}"))?
+    .map_err(|err: Box<dyn std::error::Error + Send + Sync>| format!("Migrating the database failed: {err}"))
+}
+
+/// A fresh database file in the temporary directory with every migration
+/// applied, for testing queries against the real schema and its triggers.
+#[cfg(test)]
+pub fn test_database() -> SqliteConnection {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CREATED: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "shorturl-test-{}-{}.sqlite3",
+        std::process::id(),
+        CREATED.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_file(&path);
+    let mut conn = SqliteConnection::establish(&path.to_string_lossy()).expect("test database opens");
+    conn.run_pending_migrations(MIGRATIONS).expect("migrations apply");
+    define_functions(&mut conn).expect("functions register");
+    conn
+}
@@ -0,0 +1,21 @@
+use deadpool::managed::HookError;
+use deadpool_diesel::sqlite::Hook;
+use diesel::prelude::*;
+
+/// A pool hook that unlocks every new connection with the SQLCipher `key`, and
+/// fails the connection right away if the key doesn't decrypt the database.
+pub fn unlock(key: String) -> Hook {
+    Hook::async_fn(move |conn, _| {
+        let pragma = format!("PRAGMA key = '{}'", key.replace('\'', "''"));
+        Box::pin(async move {
+            conn.interact(move |conn| {
+                diesel::sql_query(pragma).execute(conn)?;
+                diesel::sql_query("SELECT count(*) FROM sqlite_master").execute(conn)
+            })
+            .await
+            .map_err(|err| HookError::Message(err.to_string()))?
+            .map_err(|err| HookError::Backend(deadpool_diesel::Error::Ping(err)))?;
+            Ok(())
+        })
+    })
+}
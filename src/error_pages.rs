@@ -0,0 +1,64 @@
+use axum::{
+    http::{header, StatusCode}, response::{Html, IntoResponse, Response}
+};
+use chrono::{NaiveDateTime, Utc};
+
+use crate::html;
+use crate::i18n::Locale;
+use crate::negotiate::Format;
+use crate::AppConfig;
+
+/// Why a short code can't be followed.
+#[derive(Clone, Copy)]
+pub enum Unavailable {
+    NotFound,
+    Disabled,
+    /// Reserved but not live until `publish_at` (UTC).
+    Pending { publish_at: NaiveDateTime }
+}
+
+impl Unavailable {
+    fn status(self) -> StatusCode {
+        match self {
+            Unavailable::Disabled => StatusCode::GONE,
+            Unavailable::NotFound | Unavailable::Pending { .. } => StatusCode::NOT_FOUND
+        }
+    }
+}
+
+/// Answers a request for a short code that can't be followed, with the
+/// operator's branded page for browsers and a bare status line for everyone else.
+///
+/// Templates may use `{{code}}` and `{{support_contact}}`, plus `{{publish_at}}`
+/// on the pending page, which are filled in HTML-escaped.
+pub fn respond(config: &AppConfig, format: Format, locale: Locale, reason: Unavailable, code: &str) -> Response {
+    let messages = locale.messages();
+    let (template, message) = match reason {
+        Unavailable::NotFound => (config.not_found_page.as_deref(), messages.not_found),
+        Unavailable::Disabled => (config.disabled_page.as_deref(), messages.disabled),
+        Unavailable::Pending { .. } => (config.pending_page.as_deref(), messages.pending)
+    };
+    // Tells clients when a pending link goes live, and the scan guard that the code exists.
+    let retry_after = match reason {
+        Unavailable::Pending { publish_at } => {
+            let wait = (publish_at - Utc::now().naive_utc()).num_seconds().max(1);
+            Some([(header::RETRY_AFTER, wait.to_string())])
+        }
+        _ => None
+    };
+    let status = reason.status();
+    match template.filter(|_| format == Format::Html) {
+        Some(template) => {
+            let publish_at = match reason {
+                Unavailable::Pending { publish_at } => publish_at.and_utc().to_rfc3339(),
+                _ => String::new()
+            };
+            let page = template
+                .replace("{{code}}", &html::escape(code))
+                .replace("{{support_contact}}", &html::escape(config.support_contact.as_deref().unwrap_or_default()))
+                .replace("{{publish_at}}", &html::escape(&publish_at));
+            (status, retry_after, Html(page)).into_response()
+        }
+        None => (status, retry_after, [(header::CONTENT_LANGUAGE, locale.tag())], message).into_response()
+    }
+}
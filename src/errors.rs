@@ -1,8 +1,208 @@
-use axum::http::StatusCode;
+use std::any::Any;
 
-pub fn internal_error<E>(err: E) -> (StatusCode, String)
+use axum::{
+    body::{self, Body}, extract::Request, http::{header, HeaderMap, HeaderValue, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Json
+};
+use metrics::counter;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::negotiate::Format;
+
+/// Error messages are short; longer bodies are passed through as they are.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// An error a handler answers with: a status and a plain-text message, along
+/// with the stable code and offending fields [`describe`] tells JSON clients,
+/// which travel with the response as an extension.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    described: Described
+}
+
+/// The code and fields of an error, see [`describe`].
+#[derive(Clone, Debug, Default)]
+struct Described {
+    code: Option<&'static str>,
+    fields: Vec<FieldError>
+}
+
+/// A field of the request that failed validation.
+#[derive(Clone, Debug, Serialize)]
+struct FieldError {
+    field: String,
+    code: &'static str,
+    message: String
+}
+
+impl ApiError {
+    /// The same error about a field nested under `parent`, e.g. `links[2].url`
+    /// rather than `url` for one of a bundle's links.
+    pub fn within(mut self, parent: &str) -> Self {
+        for field in &mut self.described.fields {
+            field.field = format!("{parent}.{}", field.field);
+        }
+        self
+    }
+}
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        ApiError { status, message, described: Described::default() }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.message).into_response();
+        response.extensions_mut().insert(self.described);
+        response
+    }
+}
+
+/// Gives an error a stable code, e.g. `alias_taken`, that clients asking for
+/// JSON get along with the message, so they needn't parse the English.
+pub fn coded(code: &'static str, err: (StatusCode, String)) -> ApiError {
+    let mut err = ApiError::from(err);
+    err.described.code = Some(code);
+    err
+}
+
+/// Like [`coded`], for an error about one field of the request, which is also
+/// listed under `fields`.
+pub fn invalid(field: &str, code: &'static str, err: (StatusCode, String)) -> ApiError {
+    let mut err = coded(code, err);
+    err.described.fields.push(FieldError { field: field.to_owned(), code, message: err.message.clone() });
+    err
+}
+
+/// The code of errors no handler gave one, after their status, e.g. `not_found`.
+fn status_code(status: StatusCode) -> String {
+    match status {
+        StatusCode::INTERNAL_SERVER_ERROR => "internal_error".into(),
+        status => status.canonical_reason().unwrap_or("error").to_ascii_lowercase().replace([' ', '-'], "_")
+    }
+}
+
+/// Whether the client asked for JSON by name; plain-text error bodies are kept
+/// for everyone else, such as scripts that print them.
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers.get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.to_ascii_lowercase().contains("application/json") && Format::from_accept(accept) == Format::Json)
+}
+
+/// Answers plain-text errors of clients asking for JSON as
+/// `{"error": code, "message": text, "fields": [...]}`, with the code the
+/// handler gave the error or one after its status.
+pub async fn describe(req: Request, next: Next) -> Response {
+    if !wants_json(req.headers()) {
+        return next.run(req).await
+    }
+    let response = next.run(req).await;
+
+    let plain = response.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/plain"));
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) || !plain {
+        return response
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = body::to_bytes(body, MAX_MESSAGE_SIZE).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Can't read response body").into_response()
+    };
+    let message = String::from_utf8_lossy(&bytes).into_owned();
+    let described = parts.extensions.remove::<Described>().unwrap_or_default();
+    let code = described.code.map_or_else(|| status_code(status), String::from);
+
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let described = json!({ "error": code, "message": message, "fields": described.fields });
+    Response::from_parts(parts, Body::from(described.to_string()))
+}
+
+pub fn internal_error<E>(err: E) -> ApiError
+where
+    E: std::error::Error,
+{
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into()
+}
+
+/// Seconds clients are told to wait before retrying a request answered 503.
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+/// Answers a request that needed the database when no connection could be had,
+/// e.g. because the pool timed out or the file can't be opened. Unlike other
+/// failures this is likely to pass, so it answers 503 rather than 500.
+pub fn unavailable<E>(err: E) -> ApiError
 where
     E: std::error::Error,
 {
-    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
-}
\ No newline at end of file
+    println!("Database unavailable: {err}");
+    counter!("db_unavailable_total").increment(1);
+    coded("database_unavailable", (StatusCode::SERVICE_UNAVAILABLE, "The database is unavailable; try again shortly".into()))
+}
+
+/// Tells clients when to retry a request answered 503, unless the handler did.
+pub async fn retry_after(req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE && !response.headers().contains_key(header::RETRY_AFTER) {
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    }
+    response
+}
+
+/// Answers a request whose handler panicked, instead of dropping the connection.
+/// The panic message is logged but not sent, as it may carry internal details.
+pub fn panic_response(panic: Box<dyn Any + Send>) -> Response {
+    let message = panic.downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown cause");
+    println!("Handler panicked: {message}");
+    counter!("http_handler_panics_total").increment(1);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": "internal_error", "message": "The request failed unexpectedly" }))
+    ).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_untagged_errors_after_their_status() {
+        assert_eq!(status_code(StatusCode::NOT_FOUND), "not_found");
+        assert_eq!(status_code(StatusCode::TOO_MANY_REQUESTS), "too_many_requests");
+        assert_eq!(status_code(StatusCode::INTERNAL_SERVER_ERROR), "internal_error");
+    }
+
+    #[test]
+    fn responses_carry_the_code_and_fields() {
+        let err = invalid("url", "url_invalid", (StatusCode::BAD_REQUEST, "Not a valid URL".into())).within("links[2]");
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let described = response.extensions().get::<Described>().unwrap();
+        assert_eq!(described.code, Some("url_invalid"));
+        let fields: Vec<(&str, &str)> = described.fields.iter().map(|field| (field.field.as_str(), field.code)).collect();
+        assert_eq!(fields, [("links[2].url", "url_invalid")]);
+
+        let response = ApiError::from((StatusCode::NOT_FOUND, "Short URL not found".to_owned())).into_response();
+        assert!(response.extensions().get::<Described>().is_some_and(|described| described.code.is_none()));
+    }
+
+    #[test]
+    fn only_explicit_json_clients_get_json_errors() {
+        let accepting = |accept: &str| HeaderMap::from_iter([(header::ACCEPT, HeaderValue::from_str(accept).unwrap())]);
+        assert!(wants_json(&accepting("application/json")));
+        assert!(!wants_json(&accepting("*/*")));
+        assert!(!wants_json(&accepting("text/html, application/json;q=0.5")));
+        assert!(!wants_json(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,88 @@
+use axum::{
+    body::{self, Body, HttpBody}, extract::Request, http::{header, HeaderValue, Method, StatusCode}, middleware::Next, response::{IntoResponse, Response}
+};
+use sha2::{Digest, Sha256};
+
+/// Read responses are small; anything bigger, or of unknown size, is passed through without an ETag.
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Tags successful GET responses with a weak ETag and answers matching
+/// `If-None-Match` requests with `304 Not Modified`.
+pub async fn conditional_get(req: Request, next: Next) -> Response {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return next.run(req).await
+    }
+    let if_none_match = req.headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK {
+        return response
+    }
+
+    if response.body().size_hint().upper().is_none_or(|size| size > MAX_BODY_SIZE as u64) {
+        return response
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = body::to_bytes(body, MAX_BODY_SIZE).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Can't read response body").into_response()
+    };
+
+    let digest = Sha256::digest(&bytes);
+    let etag = format!("W/\"{}\"", hex::encode(&digest[..16]));
+    let etag_value = HeaderValue::from_str(&etag).expect("hex digests are valid header values");
+
+    if if_none_match.as_deref().is_some_and(|tags| matches(tags, &etag)) {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        for name in [header::CACHE_CONTROL, header::VARY, header::EXPIRES] {
+            if let Some(value) = parts.headers.remove(&name) {
+                not_modified.headers_mut().insert(name, value);
+            }
+        }
+        not_modified.headers_mut().insert(header::ETAG, etag_value);
+        return not_modified
+    }
+
+    parts.headers.insert(header::ETAG, etag_value);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Weak comparison as required for `If-None-Match`, ignoring the `W/` prefix on either side.
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    if_none_match.trim() == "*"
+        || if_none_match.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "W/\"0123abcd\"";
+
+    #[test]
+    fn matches_the_same_tag() {
+        assert!(matches(ETAG, ETAG));
+        assert!(matches("\"0123abcd\"", ETAG));
+    }
+
+    #[test]
+    fn matches_any_tag_in_a_list() {
+        assert!(matches("\"ffff\", W/\"0123abcd\"", ETAG));
+        assert!(!matches("\"ffff\", W/\"eeee\"", ETAG));
+    }
+
+    #[test]
+    fn wildcard_matches_everything() {
+        assert!(matches(" * ", ETAG));
+    }
+
+    #[test]
+    fn rejects_other_tags() {
+        assert!(!matches("\"0123abce\"", ETAG));
+        assert!(!matches("", ETAG));
+    }
+}
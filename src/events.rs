@@ -0,0 +1,135 @@
+use std::sync::LazyLock;
+
+use axum::{
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Query}, response::Response
+};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Events kept for subscribers that fall behind before they start missing some.
+const BUFFERED_EVENTS: usize = 1024;
+
+static EVENTS: LazyLock<broadcast::Sender<Event>> = LazyLock::new(|| broadcast::channel(BUFFERED_EVENTS).0);
+
+/// The names of the event types, as `type` spells them.
+pub const TYPES: [&str; 3] = ["created", "click", "not_found"];
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    Created { url: String, actor: Option<String>, quarantined: bool },
+    Click { referrer: Option<String> },
+    /// A lookup of an unknown code, as counted by the scan guard.
+    NotFound { path: String, ip: String }
+}
+
+impl EventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            EventKind::Created { .. } => "created",
+            EventKind::Click { .. } => "click",
+            EventKind::NotFound { .. } => "not_found"
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct Event {
+    /// The link the event is about, if it's about one.
+    id: Option<String>,
+    at: NaiveDateTime,
+    #[serde(flatten)]
+    kind: EventKind
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+}
+
+/// Tells live subscribers what happened. Nothing is kept when nobody listens.
+pub fn publish(id: Option<&str>, kind: EventKind) {
+    EVENTS.send(Event { id: id.map(String::from), at: Utc::now().naive_utc(), kind }).ok();
+}
+
+/// Receives the events published from now on, e.g. to deliver them elsewhere.
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    EVENTS.subscribe()
+}
+
+#[derive(Deserialize)]
+pub struct EventFilter {
+    /// Comma-separated event types to receive, all of them by default.
+    types: Option<String>,
+    /// Only events about this link.
+    id: Option<String>
+}
+
+impl EventFilter {
+    fn matches(&self, event: &Event) -> bool {
+        let wanted_type = self.types
+            .as_deref()
+            .is_none_or(|types| types.split(',').any(|name| name.trim() == event.kind.name()));
+        let wanted_link = self.id
+            .as_deref()
+            .is_none_or(|id| event.id.as_deref() == Some(id));
+        wanted_type && wanted_link
+    }
+}
+
+/// Streams events as JSON text messages, e.g. for a live dashboard.
+pub async fn live_events(ws: WebSocketUpgrade, Query(filter): Query<EventFilter>) -> Response {
+    ws.on_upgrade(move |socket| stream(socket, filter))
+}
+
+async fn stream(mut socket: WebSocket, filter: EventFilter) {
+    let mut events = subscribe();
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if filter.matches(&event) => serde_json::to_string(&event).expect("events serialize"),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => format!("{{\"type\":\"lagged\",\"missed\":{missed}}}"),
+                Err(RecvError::Closed) => return
+            },
+            received = socket.recv() => match received {
+                // Anything the client sends is ignored; pings are answered by axum.
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => continue
+            }
+        };
+        if socket.send(Message::Text(message)).await.is_err() {
+            return
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: &str, kind: EventKind) -> Event {
+        Event { id: Some(id.into()), at: NaiveDateTime::default(), kind }
+    }
+
+    #[test]
+    fn filters_by_type_and_link() {
+        let click = event("abc", EventKind::Click { referrer: None });
+        let everything = EventFilter { types: None, id: None };
+        assert!(everything.matches(&click));
+        assert!(EventFilter { types: Some("created, click".into()), id: None }.matches(&click));
+        assert!(!EventFilter { types: Some("not_found".into()), id: None }.matches(&click));
+        assert!(!EventFilter { types: None, id: Some("xyz".into()) }.matches(&click));
+    }
+
+    #[test]
+    fn serializes_flat() {
+        let created = event("abc", EventKind::Created { url: "https://example.com/".into(), actor: None, quarantined: false });
+        let json = serde_json::to_value(created).unwrap();
+        assert_eq!(json["type"], "created");
+        assert_eq!(json["id"], "abc");
+        assert_eq!(json["url"], "https://example.com/");
+    }
+}
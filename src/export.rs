@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body, extract::{Query, State}, http::{header, HeaderName}, response::{IntoResponse, Response}, Extension
+};
+use chrono::NaiveDateTime;
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::clicks;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// Clicks read per query. Each batch checks out a connection of its own, so a
+/// slow client never holds one for long.
+const BATCH_SIZE: i64 = 5000;
+
+/// The header telling how many rows the export holds.
+const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Ndjson
+}
+
+#[derive(Deserialize)]
+pub struct ClickExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    /// Only the clicks on this link.
+    id: Option<String>,
+    /// The `id` of the last click received, to resume an interrupted export.
+    after: Option<i32>
+}
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = clicks)]
+struct ExportedClick {
+    id: i32,
+    url_id: String,
+    clicked_at: NaiveDateTime,
+    referrer: Option<String>
+}
+
+/// Quotes a CSV field if it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson"
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson"
+        }
+    }
+
+    fn render(self, clicks: &[ExportedClick]) -> String {
+        clicks.iter()
+            .map(|click| match self {
+                ExportFormat::Csv => format!(
+                    "{},{},{},{}\n",
+                    click.id,
+                    csv_field(&click.url_id),
+                    click.clicked_at.format("%Y-%m-%dT%H:%M:%S"),
+                    csv_field(click.referrer.as_deref().unwrap_or_default())
+                ),
+                ExportFormat::Ndjson => format!("{}\n", serde_json::to_string(click).expect("clicks serialize"))
+            })
+            .collect()
+    }
+}
+
+fn batch(conn: &mut SqliteConnection, url_id: Option<&str>, after: i32, last: i32) -> QueryResult<Vec<ExportedClick>> {
+    let mut query = clicks::table
+        .filter(clicks::id.gt(after))
+        .filter(clicks::id.le(last))
+        .order(clicks::id)
+        .limit(BATCH_SIZE)
+        .select(ExportedClick::as_select())
+        .into_boxed();
+    if let Some(url_id) = url_id {
+        query = query.filter(clicks::url_id.eq(url_id));
+    }
+    query.load(conn)
+}
+
+/// Streams the raw clicks recorded so far, in the order they were recorded, as
+/// CSV or NDJSON of any size. The number of rows is sent up front in
+/// `X-Total-Count`. An interrupted export resumes with `?after=` set to the last
+/// `id` received; clicks recorded after the export started are left to the next.
+pub async fn export_clicks(
+    Query(query): Query<ClickExportQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Response, ApiError> {
+    let format = query.format;
+    let url_id = query.id.map(|id| links::normalize_code(&config, id));
+    let after = query.after.unwrap_or(0);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let counted_id = url_id.clone();
+    let (last, total) = conn.interact(move |conn| {
+        let last = clicks::table
+            .select(diesel::dsl::max(clicks::id))
+            .get_result::<Option<i32>>(conn)?
+            .unwrap_or(0);
+        let mut count = clicks::table
+            .filter(clicks::id.gt(after))
+            .filter(clicks::id.le(last))
+            .count()
+            .into_boxed();
+        if let Some(url_id) = counted_id.as_deref() {
+            count = count.filter(clicks::url_id.eq(url_id));
+        }
+        Ok::<_, diesel::result::Error>((last, count.get_result::<i64>(conn)?))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+    drop(conn);
+
+    let header = match format {
+        ExportFormat::Csv => Some(String::from("id,url_id,clicked_at,referrer\n")),
+        ExportFormat::Ndjson => None
+    };
+    let body = stream::unfold((Some(after), header), move |(position, header)| {
+        let pool = pool.clone();
+        let url_id = url_id.clone();
+        async move {
+            if let Some(header) = header {
+                return Some((Ok(header), (position, None)))
+            }
+            let after = position?;
+            let fetched = async {
+                let conn = telemetry::checkout(&pool).await.map_err(|err| err.to_string())?;
+                conn.interact(move |conn| batch(conn, url_id.as_deref(), after, last))
+                    .await
+                    .map_err(|err| err.to_string())?
+                    .map_err(|err| err.to_string())
+            };
+            match fetched.await {
+                // Ending the body early tells the client the export broke off.
+                Err(err) => Some((Err(err), (None, None))),
+                Ok(clicks) if clicks.is_empty() => None,
+                Ok(clicks) => {
+                    let next = (clicks.len() as i64 == BATCH_SIZE).then(|| clicks[clicks.len() - 1].id);
+                    Some((Ok(format.render(&clicks)), (next, None)))
+                }
+            }
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_owned()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"clicks.{}\"", format.extension())),
+            (TOTAL_COUNT, total.to_string())
+        ],
+        Body::from_stream(body)
+    ).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_csv_fields_only_when_needed() {
+        assert_eq!(csv_field("example.com"), "example.com");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(""), "");
+    }
+}
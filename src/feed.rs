@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State, http::header, response::IntoResponse, Extension
+};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html::escape;
+use crate::schema::urls;
+use crate::telemetry;
+use crate::{AppConfig, Url};
+
+const FEED_ENTRIES: i64 = 50;
+
+pub async fn recent_links_feed(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<impl IntoResponse, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let links = conn.interact(|conn| {
+        urls::table
+            .select(Url::as_select())
+            .order(urls::created_at.desc())
+            .limit(FEED_ENTRIES)
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let updated = links.first()
+        .map(|link| link.created_at)
+        .unwrap_or_else(|| Utc::now().naive_utc());
+
+    let entries: String = links.iter()
+        .map(|link| {
+            let short_url = escape(&format!("{}/{}", config.addr, link.id));
+            let destination = escape(&link.url);
+            let state = if link.disabled { " (disabled)" } else { "" };
+            format!(
+                "<entry>\n<id>urn:shorturl:{}</id>\n<title>{short_url}{state}</title>\n\
+                 <link href=\"{destination}\"/>\n<updated>{}</updated>\n\
+                 <summary>{short_url} → {destination}</summary>\n</entry>\n",
+                escape(&link.id),
+                rfc3339(link.created_at)
+            )
+        })
+        .collect();
+
+    let feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>urn:shorturl:feed:{}</id>\n<title>Recently shortened links</title>\n\
+         <updated>{}</updated>\n{entries}</feed>\n",
+        escape(&config.addr),
+        rfc3339(updated)
+    );
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        feed
+    ))
+}
+
+fn rfc3339(timestamp: NaiveDateTime) -> String {
+    timestamp.and_utc().to_rfc3339()
+}
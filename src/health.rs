@@ -0,0 +1,464 @@
+use std::{
+    net::SocketAddr, sync::Arc, time::{Duration, Instant}
+};
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State}, http::{header, StatusCode}, response::Html, Extension, Json
+};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::{BigInt, Text}
+};
+use metrics::counter;
+use reqwest::redirect;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::config::LiveConfig;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html;
+use crate::i18n::{self, Locale};
+use crate::links;
+use crate::mail;
+use crate::schema::{link_checks, urls};
+use crate::ssrf;
+use crate::telemetry;
+use crate::AppConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longer chains are given up on and reported as `too_many_redirects`.
+const MAX_REDIRECTS: i32 = 10;
+
+/// Checks kept per link; older ones are pruned as new ones come in.
+const HISTORY_SIZE: i64 = 50;
+
+/// Actor recorded in the audit log for links the checker marks broken or recovered.
+const ACTOR: &str = "health_check";
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+/// The outcome of requesting a link's destination once.
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = link_checks)]
+pub struct LinkCheck {
+    id: i32,
+    checked_at: NaiveDateTime,
+    /// Status of the last response, after following redirects.
+    status: Option<i32>,
+    /// `timeout`, `connection`, `too_many_redirects` or `blocked` when no final response
+    /// was received, the last for destinations or redirects to internal addresses.
+    failure: Option<String>,
+    redirects: i32,
+    duration_ms: i32,
+    /// The URL the redirects ended at, or were given up on.
+    final_url: Option<String>,
+    /// Whether a redirect went from `https` to plain `http`.
+    downgraded: bool
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = link_checks)]
+struct NewLinkCheck {
+    url_id: String,
+    status: Option<i32>,
+    failure: Option<String>,
+    redirects: i32,
+    duration_ms: i32,
+    final_url: Option<String>,
+    downgraded: bool
+}
+
+/// Where a link's destination led when it was last checked, shown with the link
+/// so double-shortened and downgraded destinations stand out.
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = link_checks)]
+pub struct RedirectChain {
+    checked_at: NaiveDateTime,
+    redirects: i32,
+    final_url: Option<String>,
+    downgraded: bool
+}
+
+/// The redirect chain of the latest check of a link, if it was checked.
+pub fn latest_chain(conn: &mut SqliteConnection, url_id: &str) -> QueryResult<Option<RedirectChain>> {
+    link_checks::table
+        .filter(link_checks::url_id.eq(url_id))
+        .filter(link_checks::latest.eq(true))
+        .select(RedirectChain::as_select())
+        .get_result(conn)
+        .optional()
+}
+
+#[derive(QueryableByName)]
+struct Due {
+    #[diesel(sql_type = Text)]
+    id: String,
+    #[diesel(sql_type = Text)]
+    url: String
+}
+
+/// Starts requesting the destination of one enabled link every `interval`, taking
+/// whichever link was checked longest ago, and recording each outcome. Internal
+/// addresses are refused, at every redirect, unless `allow_private` is set.
+pub fn spawn(pool: sqlite::Pool, live: LiveConfig, interval: Duration, allow_private: bool) {
+    let mut client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(redirect::Policy::none());
+    if !allow_private {
+        client = client.dns_resolver(Arc::new(ssrf::PublicResolver));
+    }
+    let client = client.build().expect("HTTP client can be built");
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(err) = check_next(&pool, &live.load(), &client, allow_private).await {
+                println!("Link health check failed: {err}");
+            }
+        }
+    });
+}
+
+async fn check_next(pool: &sqlite::Pool, config: &AppConfig, client: &reqwest::Client, allow_private: bool) -> Result<(), String> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    let due = conn.interact(|conn| {
+        // Links never checked sort first, as NULL is the smallest value.
+        diesel::sql_query(
+            "SELECT id, url FROM urls WHERE disabled = 0 \
+             ORDER BY (SELECT MAX(checked_at) FROM link_checks WHERE url_id = urls.id) LIMIT 1"
+        )
+        .get_result::<Due>(conn)
+        .optional()
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+    let Some(due) = due else {
+        return Ok(())
+    };
+    // Following a slow redirect chain can take a while; the pool needn't wait on it.
+    drop(conn);
+
+    let check = check(client, due.id.clone(), &due.url, allow_private).await;
+    counter!("link_checks_total", "outcome" => outcome(&check)).increment(1);
+    let failing = check.is_failure();
+    let max_failures = config.health_check_max_failures;
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    let broke = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let url_id = check.url_id.clone();
+            diesel::update(link_checks::table)
+                .filter(link_checks::url_id.eq(&url_id))
+                .set(link_checks::latest.eq(false))
+                .execute(conn)?;
+            diesel::insert_into(link_checks::table)
+                .values(check)
+                .execute(conn)?;
+            diesel::sql_query(
+                "DELETE FROM link_checks WHERE url_id = ? AND id NOT IN \
+                 (SELECT id FROM link_checks WHERE url_id = ? ORDER BY id DESC LIMIT ?)"
+            )
+            .bind::<Text, _>(&url_id)
+            .bind::<Text, _>(&url_id)
+            .bind::<BigInt, _>(HISTORY_SIZE)
+            .execute(conn)?;
+            track_failures(conn, &url_id, failing, max_failures)
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    if broke && config.mailer.is_some() && config.admin_email.is_some() {
+        let body = format!(
+            "The destination of {} failed {max_failures} health checks in a row:\n\n{}\n\n\
+             Visitors now get a warning page instead of being redirected. The link recovers by itself \
+             once its destination responds again, or can be restored right away with \
+             POST /api/v1/admin/urls/{}/restore.",
+            links::short_url(config, &due.id),
+            due.url,
+            due.id
+        );
+        mail::alert_admin(config, &format!("Short URL /{} looks broken", due.id), body).await?;
+    }
+    Ok(())
+}
+
+/// Counts consecutive failed checks, marking the link broken once there are
+/// `max_failures` of them and clearing the mark when a check succeeds again.
+/// Returns whether the link just broke.
+fn track_failures(conn: &mut SqliteConnection, url_id: &str, failing: bool, max_failures: i32) -> QueryResult<bool> {
+    let link = urls::table.find(url_id);
+    match failing {
+        true => diesel::update(link).set(urls::failed_checks.eq(urls::failed_checks + 1)).execute(conn)?,
+        false => diesel::update(link).set(urls::failed_checks.eq(0)).execute(conn)?
+    };
+    let (failed_checks, broken_at) = link
+        .select((urls::failed_checks, urls::broken_at))
+        .get_result::<(i32, Option<NaiveDateTime>)>(conn)?;
+
+    let (broken_at, action) = match broken_at {
+        Some(_) if !failing => (None, "recover"),
+        None if failing && max_failures > 0 && failed_checks >= max_failures => (Some(Utc::now().naive_utc()), "mark_broken"),
+        _ => return Ok(false)
+    };
+    diesel::update(link)
+        .set(urls::broken_at.eq(broken_at))
+        .execute(conn)?;
+    audit::record(conn, NewAuditEntry {
+        actor: Some(ACTOR),
+        action,
+        target: Some(url_id),
+        ip: None
+    })?;
+    Ok(broken_at.is_some())
+}
+
+/// Requests `url`, following redirects by hand so the hops can be counted.
+async fn check(client: &reqwest::Client, url_id: String, url: &str, allow_private: bool) -> NewLinkCheck {
+    let started = Instant::now();
+    let mut current = url::Url::parse(url).ok();
+    let mut redirects = 0;
+    let mut final_url = None;
+    let mut downgraded = false;
+    let (status, failure) = loop {
+        let Some(target) = current.take() else {
+            break (None, Some("connection"))
+        };
+        final_url = Some(target.to_string());
+        // Addresses aren't resolved, so the client's resolver never sees them.
+        if !allow_private && ssrf::names_internal_host(&target) {
+            break (None, Some("blocked"))
+        }
+        let response = match client.get(target.clone()).send().await {
+            Ok(response) => response,
+            Err(err) if ssrf::is_refused(&err) => break (None, Some("blocked")),
+            Err(err) if err.is_timeout() => break (None, Some("timeout")),
+            Err(_) => break (None, Some("connection"))
+        };
+        let status = i32::from(response.status().as_u16());
+        let location = response.headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| target.join(location).ok());
+        match location.filter(|_| response.status().is_redirection()) {
+            Some(_) if redirects == MAX_REDIRECTS => break (Some(status), Some("too_many_redirects")),
+            Some(next) => {
+                redirects += 1;
+                downgraded |= target.scheme() == "https" && next.scheme() == "http";
+                current = Some(next);
+            }
+            None => break (Some(status), None)
+        }
+    };
+
+    NewLinkCheck {
+        url_id,
+        status,
+        failure: failure.map(String::from),
+        redirects,
+        duration_ms: started.elapsed().as_millis().try_into().unwrap_or(i32::MAX),
+        final_url,
+        downgraded
+    }
+}
+
+impl NewLinkCheck {
+    fn is_failure(&self) -> bool {
+        self.failure.is_some() || self.status.is_none_or(|status| status >= 400)
+    }
+}
+
+fn outcome(check: &NewLinkCheck) -> String {
+    match (check.failure.as_deref(), check.status) {
+        (Some(failure), _) => failure.to_owned(),
+        (None, Some(status)) => format!("{}xx", status / 100),
+        (None, None) => "unknown".into()
+    }
+}
+
+/// Which kind of trouble to list.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    Timeout,
+    Connection,
+    TooManyRedirects,
+    Blocked,
+    #[serde(rename = "4xx")]
+    ClientError,
+    #[serde(rename = "5xx")]
+    ServerError
+}
+
+#[derive(Deserialize)]
+pub struct LinkHealthQuery {
+    problem: Option<Problem>,
+    /// Only links whose destination redirected at least this many times.
+    min_redirects: Option<i32>,
+    /// `check.id` to continue a listing from.
+    before: Option<i32>,
+    limit: Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct LinkHealth {
+    id: String,
+    url: String,
+    check: LinkCheck
+}
+
+/// The latest check of every link matching the filters, most recent first.
+pub async fn list_link_health(
+    Query(query): Query<LinkHealthQuery>,
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Vec<LinkHealth>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let found = conn.interact(move |conn| {
+        let mut statement = link_checks::table
+            .inner_join(urls::table)
+            .filter(link_checks::latest.eq(true))
+            .select((urls::id, urls::url, LinkCheck::as_select()))
+            .order(link_checks::id.desc())
+            .limit(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+            .into_boxed();
+
+        statement = match query.problem {
+            None => statement,
+            Some(Problem::Timeout) => statement.filter(link_checks::failure.eq("timeout")),
+            Some(Problem::Connection) => statement.filter(link_checks::failure.eq("connection")),
+            Some(Problem::TooManyRedirects) => statement.filter(link_checks::failure.eq("too_many_redirects")),
+            Some(Problem::Blocked) => statement.filter(link_checks::failure.eq("blocked")),
+            Some(Problem::ClientError) => statement
+                .filter(link_checks::failure.is_null())
+                .filter(link_checks::status.between(400, 499)),
+            Some(Problem::ServerError) => statement
+                .filter(link_checks::failure.is_null())
+                .filter(link_checks::status.between(500, 599))
+        };
+        if let Some(min_redirects) = query.min_redirects {
+            statement = statement.filter(link_checks::redirects.ge(min_redirects));
+        }
+        if let Some(before) = query.before {
+            statement = statement.filter(link_checks::id.lt(before));
+        }
+
+        statement.load::<(String, String, LinkCheck)>(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(
+        found.into_iter()
+            .map(|(id, url, check)| LinkHealth { id, url, check })
+            .collect()
+    ))
+}
+
+/// Every check kept for one link, newest first.
+pub async fn link_health_history(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<Vec<LinkCheck>>, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let history = conn.interact(move |conn| {
+        let exists = diesel::select(diesel::dsl::exists(urls::table.find(&id))).get_result::<bool>(conn)?;
+        if !exists {
+            return Ok(None)
+        }
+        link_checks::table
+            .filter(link_checks::url_id.eq(&id))
+            .select(LinkCheck::as_select())
+            .order(link_checks::id.desc())
+            .load(conn)
+            .map(Some)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(history) = history else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    };
+    Ok(Json(history))
+}
+
+/// Served instead of redirecting while a link is marked broken. Visitors can still
+/// try the destination, or its Wayback Machine snapshot if one was taken.
+pub fn broken_page(locale: Locale, destination: &str, archive_url: Option<&str>) -> Html<String> {
+    let messages = locale.messages();
+    let mut body = format!(
+        "<h1>{}</h1>\n<p>{}</p>\n<p>{}</p>\n",
+        html::escape(messages.broken_title),
+        html::escape(messages.broken),
+        i18n::fill(&html::escape(messages.continue_anyway), &html::link(destination))
+    );
+    if let Some(archive_url) = archive_url.filter(|archive_url| links::is_web_url(archive_url)) {
+        body.push_str(&format!(
+            "<p><a href=\"{}\">{}</a></p>\n",
+            html::escape(archive_url),
+            html::escape(messages.archived_copy)
+        ));
+    }
+    Html(html::page_with_head(locale.tag(), messages.broken_title, "", &body))
+}
+
+/// Clears a link's broken mark, so visitors are redirected again right away.
+pub async fn restore_link(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set((urls::failed_checks.eq(0), urls::broken_at.eq(None::<NaiveDateTime>)))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "restore",
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State}, http::StatusCode, Extension, Json
+};
+use chrono::NaiveDateTime;
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cursor::{self, Page};
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::link_events;
+use crate::telemetry;
+use crate::AppConfig;
+
+const DEFAULT_HISTORY_LIMIT: i64 = 100;
+const MAX_HISTORY_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Only the events up to this time (UTC), the first of which tells the state then.
+    at: Option<NaiveDateTime>,
+    /// The `next` cursor of the previous page.
+    after: Option<String>,
+    limit: Option<i64>
+}
+
+/// A change to a link, written by triggers on `urls` whichever way it was
+/// made: `created`, `edited`, `destination_changed`, `disabled`, `enabled`,
+/// `quarantined`, `approved`, `broken`, `repaired`, `archived`, `restored` or
+/// `deleted`, along with where the link pointed afterwards.
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = link_events)]
+pub struct LinkEvent {
+    id: i32,
+    event: String,
+    url: String,
+    title: Option<String>,
+    disabled: bool,
+    at: NaiveDateTime
+}
+
+/// The changes to a link, most recent first, including those from before it
+/// was deleted or its code was given to another link.
+pub async fn link_history(
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<Page<LinkEvent>>, ApiError> {
+    let after = query.after.as_deref()
+        .map(|after| cursor::decode(after)
+            .and_then(|(at, id)| id.parse::<i32>()
+                .map(|id| (at, id))
+                .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_owned()).into())))
+        .transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let events = conn.interact(move |conn| {
+        // One row beyond the page tells whether there's another one.
+        let mut statement = link_events::table
+            .filter(link_events::url_id.eq(&id))
+            .select(LinkEvent::as_select())
+            .order((link_events::at.desc(), link_events::id.desc()))
+            .limit(limit + 1)
+            .into_boxed();
+        if let Some(at) = query.at {
+            statement = statement.filter(link_events::at.le(at));
+        }
+        if let Some((at, event_id)) = after {
+            statement = statement.filter(
+                link_events::at.lt(at)
+                    .or(link_events::at.eq(at).and(link_events::id.lt(event_id)))
+            );
+        }
+        statement.load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if events.is_empty() && after.is_none() && query.at.is_none() {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(Json(cursor::paginate(events, limit, |event| (event.at, event.id.to_string()))))
+}
+
+/// Deletes the history of `ids`, for erasing links on request.
+pub fn delete(conn: &mut SqliteConnection, ids: &[String]) -> QueryResult<usize> {
+    diesel::delete(link_events::table)
+        .filter(link_events::url_id.eq_any(ids))
+        .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::schema::{archived_links, urls};
+
+    #[test]
+    fn triggers_record_each_change_once() {
+        let mut conn = database::test_database();
+        diesel::insert_into(urls::table)
+            .values((urls::id.eq("logged"), urls::url.eq("https://example.com/")))
+            .execute(&mut conn)
+            .unwrap();
+        let link = urls::table.find("logged");
+        diesel::update(link).set(urls::title.eq("Launch")).execute(&mut conn).unwrap();
+        diesel::update(link).set(urls::url.eq("https://example.org/")).execute(&mut conn).unwrap();
+        diesel::update(link).set(urls::disabled.eq(true)).execute(&mut conn).unwrap();
+        // Setting a column to what it was isn't a change.
+        diesel::update(link).set(urls::title.eq("Launch")).execute(&mut conn).unwrap();
+        // Archiving moves the link out of `urls` only once it's in `archived_links`.
+        diesel::insert_into(archived_links::table)
+            .values((archived_links::id.eq("logged"), archived_links::link.eq("{}"), archived_links::daily_stats.eq("[]")))
+            .execute(&mut conn)
+            .unwrap();
+        links::delete(&mut conn, &["logged".to_owned()]).unwrap();
+        diesel::insert_into(urls::table)
+            .values((urls::id.eq("logged"), urls::url.eq("https://example.org/"), urls::title.eq("Launch"), urls::disabled.eq(true)))
+            .execute(&mut conn)
+            .unwrap();
+        diesel::delete(archived_links::table.find("logged")).execute(&mut conn).unwrap();
+        links::delete(&mut conn, &["logged".to_owned()]).unwrap();
+
+        let events: Vec<(String, String, Option<String>, bool)> = link_events::table
+            .filter(link_events::url_id.eq("logged"))
+            .order(link_events::id)
+            .select((link_events::event, link_events::url, link_events::title, link_events::disabled))
+            .load(&mut conn)
+            .unwrap();
+        let launch = || Some("Launch".to_owned());
+        assert_eq!(events, [
+            ("created".into(), "https://example.com/".into(), None, false),
+            ("edited".into(), "https://example.com/".into(), launch(), false),
+            ("destination_changed".into(), "https://example.org/".into(), launch(), false),
+            ("disabled".into(), "https://example.org/".into(), launch(), true),
+            ("archived".into(), "https://example.org/".into(), launch(), true),
+            ("restored".into(), "https://example.org/".into(), launch(), true),
+            ("deleted".into(), "https://example.org/".into(), launch(), true)
+        ]);
+
+        let rewritten = diesel::update(link_events::table)
+            .set(link_events::url.eq("https://example.net/"))
+            .execute(&mut conn);
+        assert!(rewritten.is_err_and(|err| err.to_string().contains("append-only")));
+    }
+}
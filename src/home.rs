@@ -0,0 +1,90 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, State}, http::StatusCode, response::Html, Extension, Form
+};
+use deadpool_diesel::sqlite;
+use serde::Deserialize;
+
+use crate::errors::ApiError;
+use crate::html::{self, escape};
+use crate::links::{self, CreateLink};
+use crate::AppConfig;
+
+#[derive(Deserialize)]
+pub struct ShortenForm {
+    url: String,
+    #[serde(default)]
+    alias: String,
+    /// Filled in by the CAPTCHA widget, under a name depending on the provider.
+    #[serde(default, alias = "cf-turnstile-response", alias = "h-captcha-response")]
+    captcha_response: String
+}
+
+fn render(config: &AppConfig, url: &str, alias: &str, result: &str) -> Html<String> {
+    let body = format!(
+        "<h1>ShortURL</h1>\n\
+         <form method=\"post\" action=\"/\">\n\
+         <p><label>URL <input type=\"url\" name=\"url\" value=\"{}\" required autofocus size=\"50\"></label></p>\n\
+         <p><label>Alias (optional) <input type=\"text\" name=\"alias\" value=\"{}\" pattern=\"[A-Za-z0-9_-]+\"></label></p>\n\
+         {}\n\
+         <p><button type=\"submit\">Shorten</button></p>\n\
+         </form>\n{result}",
+        escape(url),
+        escape(alias),
+        config.captcha.as_ref().map(|captcha| captcha.widget()).unwrap_or_default()
+    );
+    Html(html::page("ShortURL", &body))
+}
+
+/// A minimal page for shortening links from a browser, without any external frontend.
+pub async fn homepage(Extension(config): Extension<Arc<AppConfig>>) -> Html<String> {
+    render(&config, "", "", "")
+}
+
+pub async fn shorten_form(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(form): Form<ShortenForm>
+) -> (StatusCode, Html<String>) {
+    let alias = form.alias.trim();
+    // Browsers posting the form have no way to present a token.
+    if config.create_requires_token {
+        let message = "<p>Shortening links requires an API token on this instance.</p>";
+        return (StatusCode::UNAUTHORIZED, render(&config, &form.url, alias, message))
+    }
+    if let Some(captcha) = config.captcha.as_ref() {
+        if let Err(ApiError { status, message, .. }) = captcha.verify(Some(&form.captcha_response), addr.ip()).await {
+            return (status, render(&config, &form.url, alias, &format!("<p>{}</p>", escape(&message))))
+        }
+    }
+    let created = links::create(&pool, &config, CreateLink {
+        url: form.url.clone(),
+        alias: Some(alias.to_owned()).filter(|alias| !alias.is_empty()),
+        public_stats: false,
+        publish_at: None,
+        title: None,
+        notes: None,
+        id_strategy: None,
+        idempotency_key: None,
+        actor: None,
+        ip: Some(addr.ip().to_string())
+    })
+    .await;
+
+    match created {
+        Ok(created) => {
+            // PUBLIC_ADDR may lack a scheme, so the link itself stays relative.
+            let result = format!(
+                "<p>Short URL: <a href=\"/{}\"><code>{}</code></a> → {}</p>",
+                escape(&created.id),
+                escape(&links::short_url(&config, &created.id)),
+                escape(&created.url)
+            );
+            (StatusCode::OK, render(&config, "", "", &result))
+        }
+        // Keep the input so it can be corrected.
+        Err(ApiError { status, message, .. }) => (status, render(&config, &form.url, alias, &format!("<p>{}</p>", escape(&message))))
+    }
+}
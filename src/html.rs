@@ -0,0 +1,60 @@
+use crate::links;
+
+/// Escapes text for use in HTML element content and quoted attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// A link to `url`, or just its text unless it's an `http` or `https` URL,
+/// so stored `javascript:` URLs can't run on the service's origin.
+pub fn link(url: &str) -> String {
+    if links::is_web_url(url) {
+        format!("<a href=\"{}\">{}</a>", escape(url), escape(url))
+    } else {
+        escape(url)
+    }
+}
+
+/// Wraps `body` in the minimal document shared by every page the service renders.
+pub fn page(title: &str, body: &str) -> String {
+    page_with_head("en", title, "", body)
+}
+
+/// [`page`] in the language `lang`, with extra markup such as `<meta>` tags added to the `<head>`.
+pub fn page_with_head(lang: &str, title: &str, head: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{}</title>
+{}<style>
+body {{ font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; color: #222; }}
+table {{ border-collapse: collapse; }}
+td, th {{ padding: 0.25rem 0.75rem; text-align: left; }}
+.bar {{ background: #4a7bd0; height: 0.8rem; }}
+</style>
+</head>
+<body>
+{}
+</body>
+</html>
+"#,
+        escape(lang),
+        escape(title),
+        if head.is_empty() { String::new() } else { format!("{head}\n") },
+        body
+    )
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Request, http::{header, HeaderValue, StatusCode}, middleware::Next, response::{IntoResponse, Redirect, Response}, Extension
+};
+
+use crate::AppConfig;
+
+/// Redirects plain-HTTP requests to HTTPS and marks HTTPS responses with
+/// `Strict-Transport-Security` when `force_https` is enabled.
+///
+/// TLS is expected to terminate at a reverse proxy, which reports the original
+/// scheme through `X-Forwarded-Proto`.
+pub async fn enforce(
+    Extension(config): Extension<Arc<AppConfig>>,
+    req: Request,
+    next: Next
+) -> Response {
+    if !config.force_https {
+        return next.run(req).await
+    }
+
+    let secure = req.headers()
+        .get("X-Forwarded-Proto")
+        .and_then(|proto| proto.to_str().ok())
+        .and_then(|proto| proto.split(',').next())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+    if !secure {
+        let Some(host) = req.headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+        else {
+            return (StatusCode::BAD_REQUEST, "HTTPS is required").into_response()
+        };
+        let path = req.uri()
+            .path_and_query()
+            .map_or("/", |path| path.as_str());
+        return Redirect::permanent(&format!("https://{host}{path}")).into_response()
+    }
+
+    let mut response = next.run(req).await;
+    let hsts = format!("max-age={}; includeSubDomains", config.hsts_max_age.as_secs());
+    response.headers_mut().insert(
+        header::STRICT_TRANSPORT_SECURITY,
+        HeaderValue::from_str(&hsts).expect("HSTS value is ASCII")
+    );
+    response
+}
@@ -0,0 +1,187 @@
+use axum::http::{header, HeaderMap};
+
+/// Languages visitor-facing messages are translated into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Locale {
+    En,
+    De,
+    Fr,
+    Es
+}
+
+/// The text of every message shown to visitors following a short link.
+/// `{}` marks where a value is filled in.
+pub struct Messages {
+    pub not_found: &'static str,
+    pub disabled: &'static str,
+    pub pending: &'static str,
+    pub redirecting: &'static str,
+    pub redirecting_to: &'static str,
+    pub leaving: &'static str,
+    pub forwarded_in: &'static str,
+    pub continue_now: &'static str,
+    pub broken_title: &'static str,
+    pub broken: &'static str,
+    pub continue_anyway: &'static str,
+    pub archived_copy: &'static str
+}
+
+const EN: Messages = Messages {
+    not_found: "Short URL not found",
+    disabled: "Short URL has been disabled",
+    pending: "Short URL is not live yet",
+    redirecting: "Redirecting",
+    redirecting_to: "Redirecting to {}…",
+    leaving: "You are leaving {}",
+    forwarded_in: "You will be forwarded in {} seconds.",
+    continue_now: "Continue now",
+    broken_title: "Link may be broken",
+    broken: "The page this short link points to seems to be unavailable.",
+    continue_anyway: "Try {} anyway",
+    archived_copy: "View an archived copy"
+};
+
+const DE: Messages = Messages {
+    not_found: "Kurzlink nicht gefunden",
+    disabled: "Kurzlink wurde deaktiviert",
+    pending: "Kurzlink ist noch nicht freigeschaltet",
+    redirecting: "Weiterleitung",
+    redirecting_to: "Weiterleitung zu {}…",
+    leaving: "Sie verlassen {}",
+    forwarded_in: "Sie werden in {} Sekunden weitergeleitet.",
+    continue_now: "Jetzt fortfahren",
+    broken_title: "Link möglicherweise defekt",
+    broken: "Die Seite, auf die dieser Kurzlink verweist, scheint nicht erreichbar zu sein.",
+    continue_anyway: "{} trotzdem öffnen",
+    archived_copy: "Archivierte Kopie ansehen"
+};
+
+const FR: Messages = Messages {
+    not_found: "Lien court introuvable",
+    disabled: "Le lien court a été désactivé",
+    pending: "Le lien court n'est pas encore actif",
+    redirecting: "Redirection",
+    redirecting_to: "Redirection vers {}…",
+    leaving: "Vous quittez {}",
+    forwarded_in: "Vous serez redirigé dans {} secondes.",
+    continue_now: "Continuer maintenant",
+    broken_title: "Lien peut-être cassé",
+    broken: "La page vers laquelle pointe ce lien court semble indisponible.",
+    continue_anyway: "Ouvrir {} quand même",
+    archived_copy: "Voir une copie archivée"
+};
+
+const ES: Messages = Messages {
+    not_found: "No se encontró el enlace corto",
+    disabled: "El enlace corto ha sido desactivado",
+    pending: "El enlace corto aún no está activo",
+    redirecting: "Redirigiendo",
+    redirecting_to: "Redirigiendo a {}…",
+    leaving: "Está saliendo de {}",
+    forwarded_in: "Será redirigido en {} segundos.",
+    continue_now: "Continuar ahora",
+    broken_title: "Enlace posiblemente roto",
+    broken: "La página a la que apunta este enlace corto parece no estar disponible.",
+    continue_anyway: "Abrir {} de todos modos",
+    archived_copy: "Ver una copia archivada"
+};
+
+impl Locale {
+    fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split('-').next().unwrap_or_default();
+        match language {
+            "en" | "*" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "fr" => Some(Locale::Fr),
+            "es" => Some(Locale::Es),
+            _ => None
+        }
+    }
+
+    /// Picks the preferred supported language from an `Accept-Language` header, falling back to English.
+    pub fn from_accept_language(accept_language: &str) -> Self {
+        let mut best = None;
+        for range in accept_language.split(',') {
+            let mut params = range.split(';');
+            let tag = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let Some(locale) = Locale::from_tag(&tag) else {
+                continue
+            };
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((locale, quality));
+            }
+        }
+        best.map_or(Locale::En, |(locale, _)| locale)
+    }
+
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|accept_language| accept_language.to_str().ok())
+            .map_or(Locale::En, Locale::from_accept_language)
+    }
+
+    /// The language tag for `lang` attributes and `Content-Language`.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Es => "es"
+        }
+    }
+
+    pub fn messages(self) -> &'static Messages {
+        match self {
+            Locale::En => &EN,
+            Locale::De => &DE,
+            Locale::Fr => &FR,
+            Locale::Es => &ES
+        }
+    }
+}
+
+/// Fills the `{}` in a message with `value`.
+pub fn fill(message: &str, value: &str) -> String {
+    message.replacen("{}", value, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_preferred_supported_language() {
+        assert_eq!(Locale::from_accept_language("de"), Locale::De);
+        assert_eq!(Locale::from_accept_language("fr-CH, fr;q=0.9, en;q=0.8"), Locale::Fr);
+        assert_eq!(Locale::from_accept_language("en;q=0.5, es;q=0.7"), Locale::Es);
+    }
+
+    #[test]
+    fn skips_unsupported_and_refused_languages() {
+        assert_eq!(Locale::from_accept_language("ja, de;q=0.3"), Locale::De);
+        assert_eq!(Locale::from_accept_language("de;q=0, fr;q=0.1"), Locale::Fr);
+    }
+
+    #[test]
+    fn tags_are_case_insensitive() {
+        assert_eq!(Locale::from_accept_language("DE-AT"), Locale::De);
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        assert_eq!(Locale::from_accept_language(""), Locale::En);
+        assert_eq!(Locale::from_accept_language("ja, zh-CN"), Locale::En);
+        assert_eq!(Locale::from_accept_language("*"), Locale::En);
+    }
+
+    #[test]
+    fn fill_replaces_the_placeholder() {
+        assert_eq!(fill(EN.leaving, "example.com"), "You are leaving example.com");
+    }
+}
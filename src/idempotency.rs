@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use axum::http::{HeaderMap, StatusCode};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::errors::ApiError;
+use crate::schema::idempotency_keys;
+
+pub const HEADER: &str = "Idempotency-Key";
+
+const MAX_KEY_LENGTH: usize = 255;
+
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = idempotency_keys)]
+pub struct IdempotencyRecord {
+    pub request_url: String,
+    pub url_id: String
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = idempotency_keys)]
+struct NewIdempotencyRecord<'a> {
+    scope: &'a str,
+    key: &'a str,
+    request_url: &'a str,
+    url_id: &'a str
+}
+
+pub fn key_from_headers(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(HEADER) else {
+        return Ok(None)
+    };
+
+    value.to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .map(|key| Some(key.to_owned()))
+        .ok_or((StatusCode::BAD_REQUEST, format!("Invalid {HEADER} header")).into())
+}
+
+/// Whose keys a key is looked up among: the actor creating the link, or the
+/// address of an anonymous creator, so one caller can't replay another's key.
+pub fn scope(actor: Option<&str>, ip: Option<&str>) -> String {
+    match (actor, ip) {
+        (Some(actor), _) => actor.to_owned(),
+        (None, Some(ip)) => format!("ip:{ip}"),
+        (None, None) => String::new()
+    }
+}
+
+/// Finds a live record for `key` in `scope`, discarding records older than `ttl` first.
+pub fn find(conn: &mut SqliteConnection, scope: &str, key: &str, ttl: Duration) -> QueryResult<Option<IdempotencyRecord>> {
+    let expires_before = Utc::now().naive_utc() - ttl;
+    diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::created_at.lt(expires_before))
+        .execute(conn)?;
+
+    idempotency_keys::table
+        .find((scope, key))
+        .select(IdempotencyRecord::as_select())
+        .first(conn)
+        .optional()
+}
+
+pub fn save(conn: &mut SqliteConnection, scope: &str, key: &str, request_url: &str, url_id: &str) -> QueryResult<()> {
+    diesel::insert_into(idempotency_keys::table)
+        .values(NewIdempotencyRecord { scope, key, request_url, url_id })
+        .execute(conn)
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_keys_by_actor_then_address() {
+        assert_eq!(scope(Some("token:3"), Some("203.0.113.7")), "token:3");
+        assert_eq!(scope(None, Some("203.0.113.7")), "ip:203.0.113.7");
+        assert_ne!(scope(Some("token:3"), None), scope(Some("token:4"), None));
+    }
+}
@@ -0,0 +1,308 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::StatusCode, response::Html, Extension, Json
+};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html;
+use crate::i18n::{self, Locale};
+use crate::links;
+use crate::schema::urls;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// Long enough for typical pixels to fire, short enough not to annoy visitors.
+const PIXEL_DELAY_SECS: u64 = 1;
+
+/// How a link forwards visitors when no interstitial is shown.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectMode {
+    /// A plain HTTP redirect.
+    Http,
+    /// An HTML page with `<meta http-equiv="refresh">`.
+    MetaRefresh,
+    /// An HTML page that forwards with JavaScript, falling back to a meta refresh.
+    Javascript
+}
+
+impl RedirectMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            RedirectMode::Http => "http",
+            RedirectMode::MetaRefresh => "meta_refresh",
+            RedirectMode::Javascript => "javascript"
+        }
+    }
+
+    fn from_column(value: &str) -> Self {
+        match value {
+            "meta_refresh" => RedirectMode::MetaRefresh,
+            "javascript" => RedirectMode::Javascript,
+            _ => RedirectMode::Http
+        }
+    }
+}
+
+/// The per-link settings deciding what a visitor is served instead of a bare redirect.
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = urls)]
+pub struct Presentation {
+    pixels: Option<String>,
+    interstitial: bool,
+    redirect_mode: String
+}
+
+/// The page to serve for a redirect to `destination`, or `None` when a plain
+/// HTTP redirect will do.
+pub fn render(
+    config: &AppConfig,
+    locale: Locale,
+    destination: &str,
+    presentation: &Presentation
+) -> Result<Option<Html<String>>, ApiError> {
+    // Links stored before other schemes were rejected fall back to a plain redirect,
+    // which browsers won't follow into script.
+    if !links::is_web_url(destination) {
+        return Ok(None)
+    }
+    let pixels = presentation.pixels.as_deref();
+    if presentation.interstitial {
+        return leaving_page(config, locale, destination, pixels).map(Some)
+    }
+    // Pixels need a moment to load before the page navigates away.
+    let delay = if pixels.is_some() { PIXEL_DELAY_SECS } else { 0 };
+    Ok(match (RedirectMode::from_column(&presentation.redirect_mode), pixels) {
+        (RedirectMode::Http, None) => None,
+        (RedirectMode::Http | RedirectMode::MetaRefresh, pixels) => Some(forwarding_page(locale, destination, delay, false, pixels)),
+        (RedirectMode::Javascript, pixels) => Some(forwarding_page(locale, destination, delay, true, pixels))
+    })
+}
+
+/// A minimal page that forwards to `destination` after `delay` seconds, loading
+/// the link's tracking pixels, if any, in the meantime.
+fn forwarding_page(locale: Locale, destination: &str, delay: u64, javascript: bool, pixels: Option<&str>) -> Html<String> {
+    let messages = locale.messages();
+    let escaped = html::escape(destination);
+    let refresh = format!("<meta http-equiv=\"refresh\" content=\"{delay}; url={escaped}\">");
+    // With JavaScript available the script forwards; the refresh is only a fallback.
+    let head = if javascript { format!("<noscript>{refresh}</noscript>") } else { refresh };
+    let link = format!("<a href=\"{escaped}\">{escaped}</a>");
+    let mut body = format!("<p>{}</p>\n", i18n::fill(&html::escape(messages.redirecting_to), &link));
+    if let Some(pixels) = pixels {
+        body.push_str(pixels);
+        body.push('\n');
+    }
+    if javascript {
+        // A JSON string literal is a valid JavaScript one; `<` is escaped so the
+        // destination can't close the script element.
+        let target = serde_json::to_string(destination)
+            .expect("strings serialize")
+            .replace('<', "\\u003c");
+        body.push_str(&format!("<script>setTimeout(() => location.replace({target}), {});</script>", delay * 1000));
+    }
+    Html(html::page_with_head(locale.tag(), messages.redirecting, &head, &body))
+}
+
+/// The "you are leaving" page. Its markup lives in `templates/interstitial.html`,
+/// which instances can restyle before building.
+#[derive(Template)]
+#[template(path = "interstitial.html")]
+struct LeavingPage<'a> {
+    lang: &'a str,
+    leaving: &'a str,
+    forwarded_in: &'a str,
+    continue_now: &'a str,
+    short_host: &'a str,
+    destination: &'a str,
+    destination_host: &'a str,
+    seconds: u64,
+    pixels: Option<&'a str>
+}
+
+/// Warns that the visitor is about to leave for `destination` and forwards them
+/// after a countdown. Any tracking pixels of the link are loaded on the same page.
+pub fn leaving_page(
+    config: &AppConfig,
+    locale: Locale,
+    destination: &str,
+    pixels: Option<&str>
+) -> Result<Html<String>, ApiError> {
+    let short_host = config.addr
+        .split_once("://")
+        .map_or(config.addr.as_str(), |(_, host)| host);
+    let destination_host = url::Url::parse(destination)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_else(|| destination.to_owned());
+
+    let messages = locale.messages();
+    let seconds = config.interstitial_delay.as_secs();
+    let countdown = format!("<span id=\"countdown\">{seconds}</span>");
+
+    LeavingPage {
+        lang: locale.tag(),
+        leaving: &i18n::fill(messages.leaving, short_host),
+        forwarded_in: &i18n::fill(&html::escape(messages.forwarded_in), &countdown),
+        continue_now: messages.continue_now,
+        short_host,
+        destination,
+        destination_host: &destination_host,
+        seconds,
+        pixels
+    }
+    .render()
+    .map(Html)
+    .map_err(internal_error)
+}
+
+#[derive(Deserialize)]
+pub struct InterstitialRequest {
+    enabled: bool
+}
+
+/// Turns the "you are leaving" page on or off for a link.
+pub async fn set_interstitial(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<InterstitialRequest>
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set(urls::interstitial.eq(request.enabled))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: if request.enabled { "enable_interstitial" } else { "disable_interstitial" },
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct RedirectModeRequest {
+    mode: RedirectMode
+}
+
+pub async fn set_redirect_mode(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<RedirectModeRequest>
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let mode = request.mode.as_str();
+            let updated = diesel::update(urls::table.find(&id))
+                .set(urls::redirect_mode.eq(mode))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "set_redirect_mode",
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct PixelsRequest {
+    html: Option<String>
+}
+
+/// Sets or, given `{"html": null}`, removes the HTML snippet of tracking pixels
+/// served on the link's interstitial page.
+pub async fn set_pixels(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<PixelsRequest>
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let pixels = request.html.filter(|html| !html.trim().is_empty());
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set(urls::pixels.eq(&pixels))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: if pixels.is_some() { "set_pixels" } else { "clear_pixels" },
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,942 @@
+use std::{
+    net::SocketAddr, sync::Arc
+};
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State}, http::StatusCode, Extension, Json
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    dsl::sql, prelude::*, result::DatabaseErrorKind, sql_types::{Bool, Text}, sqlite::Sqlite
+};
+use metrics::counter;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::alias::{self, Availability};
+use crate::archive;
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::bundles;
+use crate::checksum;
+use crate::config::IdStrategy;
+use crate::cursor::{self, Page};
+use crate::database::url_host;
+use crate::errors::{self, ApiError, internal_error, unavailable};
+use crate::events::{self, EventKind};
+use crate::health;
+use crate::idempotency::{self, IdempotencyRecord};
+use crate::scan;
+use crate::screenshot;
+use crate::sequential;
+use crate::signing;
+use crate::schema::{click_stats_daily, clicks, conversions, idempotency_keys, link_checks, urls};
+use crate::snowflake;
+use crate::spam;
+use crate::telemetry;
+use crate::usage::{self, Meter};
+use crate::validate;
+use crate::{AppConfig, NewUrl};
+
+/// Everything needed to mint a short link, whichever frontend the request came through.
+pub struct CreateLink {
+    pub url: String,
+    pub alias: Option<String>,
+    pub public_stats: bool,
+    /// Until then the code is reserved but answers as pending.
+    pub publish_at: Option<NaiveDateTime>,
+    /// Free text for people managing links, never shown to visitors being redirected.
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    /// Overrides `ID_STRATEGY` for this link, for callers with an API token.
+    pub id_strategy: Option<IdStrategy>,
+    pub idempotency_key: Option<String>,
+    pub actor: Option<String>,
+    pub ip: Option<String>
+}
+
+/// How many generated codes are tried before giving up on finding a free one.
+const GENERATE_ATTEMPTS: usize = 5;
+
+/// Longest title and notes, in characters.
+pub const MAX_TITLE_LENGTH: usize = 200;
+const MAX_NOTES_LENGTH: usize = 4000;
+
+enum Insertion {
+    /// The idempotency key was seen before, with this outcome.
+    Replayed(IdempotencyRecord),
+    /// The creator's link quota is used up.
+    OverQuota(ApiError),
+    /// A hash code led to a link the creator made before to the same destination.
+    Existing(String),
+    Inserted { id: String, quarantined: bool }
+}
+
+pub struct CreatedLink {
+    pub id: String,
+    pub url: String
+}
+
+/// The codes a link created without an alias may get.
+pub enum Candidates {
+    /// Drawn up front, to be tried in order.
+    Drawn(Vec<String>),
+    /// Hashed from `input` one attempt after another. Codes taken by links to
+    /// other destinations count against `GENERATE_ATTEMPTS`, but those taken by
+    /// links to `input` itself don't, so shortening it again never runs out.
+    Hashed {
+        input: String,
+        alphabet: Vec<char>,
+        length: usize,
+        honeypots: Vec<String>,
+        /// Whose live links to `input` are handed back rather than passed over.
+        reusable_by: Option<Option<String>>
+    },
+    /// The next free number of the sequence, skipping these reserved codes.
+    Sequential { reserved: Vec<String> }
+}
+
+impl Candidates {
+    /// Codes for a new link under `strategy`, with `input`, such as the
+    /// destination, being what hash codes are drawn from.
+    pub fn new(config: &AppConfig, strategy: IdStrategy, input: &str) -> Self {
+        match strategy {
+            IdStrategy::Sequential => Candidates::Sequential {
+                reserved: alias::BUILTIN_RESERVED.iter()
+                    .map(|reserved| reserved.to_string())
+                    .chain(config.reserved_aliases.iter().cloned())
+                    .chain(config.honeypot_codes.iter().cloned())
+                    .collect()
+            },
+            IdStrategy::Hash => Candidates::Hashed {
+                input: input.to_owned(),
+                alphabet: config.nano_id_alphabet.clone(),
+                length: config.id_length,
+                honeypots: config.honeypot_codes.clone(),
+                reusable_by: None
+            },
+            strategy => Candidates::Drawn(
+                (0..GENERATE_ATTEMPTS)
+                    .map(|attempt| generate_id(config, strategy, input, attempt))
+                    .filter(|code| !scan::is_honeypot(config, code))
+                    .collect()
+            )
+        }
+    }
+
+    /// Lets hash codes lead to a live link `creator` made before from the same
+    /// input, which is then picked instead of a free code.
+    pub fn reusable_by(mut self, creator: Option<String>) -> Self {
+        if let Candidates::Hashed { reusable_by, .. } = &mut self {
+            *reusable_by = Some(creator);
+        }
+        self
+    }
+
+    /// The first of the codes that's free, if any. Must run in a transaction
+    /// holding the write lock, so a free code stays free until inserted.
+    pub fn pick(self, conn: &mut SqliteConnection) -> QueryResult<Option<Pick>> {
+        match self {
+            Candidates::Drawn(codes) => {
+                for code in codes {
+                    if !bundles::code_taken(conn, &code)? {
+                        return Ok(Some(Pick::Free(code)))
+                    }
+                }
+                Ok(None)
+            }
+            Candidates::Hashed { input, alphabet, length, honeypots, reusable_by } => {
+                let mut collisions = 0;
+                for attempt in 0.. {
+                    let code = hash_code(&alphabet, length, &input, attempt);
+                    if honeypots.iter().any(|honeypot| honeypot.eq_ignore_ascii_case(&code)) {
+                        continue
+                    }
+                    if !bundles::code_taken(conn, &code)? {
+                        return Ok(Some(Pick::Free(code)))
+                    }
+                    let taken = urls::table
+                        .find(&code)
+                        .select(HashedLink::as_select())
+                        .get_result(conn)
+                        .optional()?;
+                    match taken {
+                        Some(link) if link.url == input => {
+                            if link.is_live() && reusable_by.as_ref() == Some(&link.created_by) {
+                                return Ok(Some(Pick::Existing(code)))
+                            }
+                        }
+                        _ => {
+                            collisions += 1;
+                            if collisions == GENERATE_ATTEMPTS {
+                                return Ok(None)
+                            }
+                        }
+                    }
+                }
+                unreachable!("attempts go on until a code is picked or collisions run out")
+            }
+            Candidates::Sequential { reserved } => sequential::next_code(conn, &reserved).map(|code| Some(Pick::Free(code)))
+        }
+    }
+}
+
+/// A link holding a code hashed for a new one.
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = urls)]
+struct HashedLink {
+    url: String,
+    disabled: bool,
+    quarantined: bool,
+    publish_at: Option<NaiveDateTime>,
+    created_by: Option<String>
+}
+
+impl HashedLink {
+    fn is_live(&self) -> bool {
+        !self.disabled && !self.quarantined && self.publish_at.is_none_or(|publish_at| publish_at <= Utc::now().naive_utc())
+    }
+}
+
+/// What [`Candidates::pick`] found.
+pub enum Pick {
+    /// A code no link or bundle has.
+    Free(String),
+    /// The code of a link made before from the same input by the same creator.
+    Existing(String)
+}
+
+/// The strategy a caller asked for by name, which only API token holders may.
+pub fn requested_strategy(config: &AppConfig, name: Option<String>) -> Result<Option<IdStrategy>, ApiError> {
+    let Some(name) = name else {
+        return Ok(None)
+    };
+    let Some(strategy) = IdStrategy::parse(&name, config.node_id) else {
+        return Err(
+            errors::invalid("id_strategy", "id_strategy_unknown", (StatusCode::UNPROCESSABLE_ENTITY, "id_strategy must be random, sequential, hash or snowflake".into()))
+        )
+    };
+    if let Some(setting) = config.requires_random_codes().filter(|_| strategy != IdStrategy::Random) {
+        return Err(
+            errors::invalid("id_strategy", "id_strategy_forbidden", (StatusCode::UNPROCESSABLE_ENTITY, format!("id_strategy must be random while {setting} is set")))
+        )
+    }
+    Ok(Some(strategy))
+}
+
+/// Draws code number `attempt` for `input` under `strategy`. Sequential codes
+/// need the database and come from [`Candidates`]; here they're drawn at random.
+pub fn generate_id(config: &AppConfig, strategy: IdStrategy, input: &str, attempt: usize) -> String {
+    match strategy {
+        IdStrategy::Hash => hash_code(&config.nano_id_alphabet, config.id_length, input, attempt),
+        IdStrategy::Random | IdStrategy::Sequential => {
+            let alphabet = &config.nano_id_alphabet;
+            let mut code = random_code(alphabet, config.id_length);
+            if config.id_checksum {
+                code.push(checksum::check_char(alphabet, &code));
+            }
+            if let Some(key) = config.code_signing_key.as_deref() {
+                code.push_str(&signing::sign(key.as_bytes(), alphabet, &code, config.signature_length));
+            }
+            code
+        }
+        IdStrategy::Snowflake { node_id } => snowflake::next_code(node_id)
+    }
+}
+
+/// The spelling codes are stored and looked up in.
+pub fn normalize_code(config: &AppConfig, code: String) -> String {
+    if config.case_insensitive_codes { code.to_lowercase() } else { code }
+}
+
+/// Rejects codes shaped like generated ones whose check character or signature
+/// doesn't match, which can't exist and so needn't be looked up. Anything else may
+/// be an alias, or a code minted before signing was turned on.
+pub fn passes_checks(config: &AppConfig, code: &str) -> bool {
+    let alphabet = &config.nano_id_alphabet;
+    let signature_length = if config.code_signing_key.is_some() { config.signature_length } else { 0 };
+    let unsigned_length = config.id_length + usize::from(config.id_checksum);
+    let generated_shape = code.chars().count() == unsigned_length + signature_length
+        && code.chars().all(|c| alphabet.contains(&c));
+    if !generated_shape {
+        return true
+    }
+    let signed = config.code_signing_key
+        .as_deref()
+        .is_none_or(|key| signing::verify(key.as_bytes(), alphabet, code, signature_length));
+    let unsigned: String = code.chars().take(unsigned_length).collect();
+    signed && (!config.id_checksum || checksum::is_valid(alphabet, &unsigned))
+}
+
+/// A code of `length` characters of `alphabet` derived from `input`, the same
+/// for the same input and attempt. Later attempts stand in when the first is taken.
+fn hash_code(alphabet: &[char], length: usize, input: &str, attempt: usize) -> String {
+    let base = alphabet.len() as u128;
+    let mut code = String::new();
+    for block in 0u32.. {
+        let digest = Sha256::new()
+            .chain_update(input)
+            .chain_update([0])
+            .chain_update(attempt.to_be_bytes())
+            .chain_update(block.to_be_bytes())
+            .finalize();
+        // Eight characters of an alphabet of up to 65536 symbols take 128 bits.
+        let mut value = u128::from_be_bytes(digest[..16].try_into().expect("digest is 32 bytes"));
+        for _ in 0..8 {
+            if code.chars().count() == length {
+                return code
+            }
+            code.push(alphabet[(value % base) as usize]);
+            value /= base;
+        }
+    }
+    unreachable!("blocks go on until the code is long enough")
+}
+
+/// Like `nanoid!`, but counting characters rather than bytes, so the alphabet may contain
+/// multi-byte characters such as emoji.
+fn random_code(alphabet: &[char], length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..length)
+        .map(|_| *alphabet.choose(&mut rng).expect("alphabet is not empty"))
+        .collect()
+}
+
+pub fn short_url(config: &AppConfig, id: &str) -> String {
+    format!("{}/{}", config.addr, id)
+}
+
+/// Whether `url` is an absolute `http` or `https` URL. Anything else, `javascript:`
+/// in particular, must never end up in a page served from the short domain.
+pub fn is_web_url(url: &str) -> bool {
+    url::Url::parse(url.trim()).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Canonical spelling of a destination, so lookups match however the URL was written.
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    url::Url::parse(url)
+        .map(String::from)
+        .unwrap_or_else(|_| url.to_owned())
+}
+
+/// Trims a title or notes, treating blank ones as absent. `label` names it in
+/// the error, whose field is its last word, e.g. `title` for `Link title`.
+pub fn annotation(text: Option<String>, label: &str, max_length: usize) -> Result<Option<String>, ApiError> {
+    let Some(text) = text.map(|text| text.trim().to_owned()).filter(|text| !text.is_empty()) else {
+        return Ok(None)
+    };
+    if text.chars().count() > max_length {
+        let field = label.rsplit(' ').next().unwrap_or(label).to_ascii_lowercase();
+        return Err(
+            errors::invalid(&field, "too_long", (StatusCode::UNPROCESSABLE_ENTITY, format!("{label} is longer than {max_length} characters")))
+        )
+    }
+    Ok(Some(text))
+}
+
+pub async fn create(
+    pool: &sqlite::Pool,
+    config: &AppConfig,
+    request: CreateLink
+) -> Result<CreatedLink, ApiError> {
+    if config.maintenance_mode {
+        return Err(
+            errors::coded("maintenance", (StatusCode::SERVICE_UNAVAILABLE, "Link creation is paused for maintenance; try again later".into()))
+        )
+    }
+    let origin_url = validate::pipeline().run(config, request.url)?;
+    let title = annotation(request.title, "Title", MAX_TITLE_LENGTH)?;
+    let notes = annotation(request.notes, "Notes", MAX_NOTES_LENGTH)?;
+
+    let alias = request.alias.map(|alias| normalize_code(config, alias));
+    if let Some(alias) = alias.as_deref() {
+        match alias::precheck(config, alias) {
+            Some(Availability::Invalid) => return Err(
+                errors::invalid("alias", "alias_invalid", (StatusCode::BAD_REQUEST, "Alias may only contain letters, digits, '-', '_' and characters of the id alphabet, in up to 5 segments separated by '/'".into()))
+            ),
+            Some(Availability::Confusable) => return Err(
+                errors::invalid("alias", "alias_confusable", (StatusCode::BAD_REQUEST, "Alias mixes scripts or imitates Latin letters".into()))
+            ),
+            Some(_) => return Err(
+                errors::invalid("alias", "alias_reserved", (StatusCode::CONFLICT, "Alias is reserved".into()))
+            ),
+            None => {}
+        }
+    }
+
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(unavailable)?;
+
+    let strategy = match request.id_strategy {
+        Some(_) if request.actor.is_none() => return Err(
+            (StatusCode::FORBIDDEN, "Choosing id_strategy requires an API token".into()).into()
+        ),
+        Some(strategy) => strategy,
+        None => config.id_strategy
+    };
+    // Generated codes can collide with existing ones, so a few spares are drawn up front.
+    let generated = alias.is_none();
+    let candidates = generated.then(|| Candidates::new(config, strategy, &origin_url).reusable_by(request.actor.clone()));
+    let mut new_url = NewUrl {
+        id: String::new(),
+        url: origin_url.clone(),
+        public_stats: request.public_stats,
+        publish_at: request.publish_at,
+        spam_score: 0,
+        quarantined: false,
+        created_by: request.actor.clone(),
+        creator_ip: request.ip.clone(),
+        title,
+        notes
+    };
+    // Links created with a token are vouched for by whoever it was issued to.
+    let spam_policy = config.spam.clone();
+    let mut assessment = (request.actor.is_none() && spam_policy.enabled())
+        .then(|| spam_policy.assess(&origin_url));
+    let publish_at = request.publish_at;
+    let idempotency_key = request.idempotency_key;
+    let idempotency_scope = idempotency::scope(request.actor.as_deref(), request.ip.as_deref());
+    let idempotency_ttl = config.idempotency_ttl;
+    let link_quota = usage::configured(config, Meter::LinksCreated);
+    let actor = request.actor;
+    let created_by = actor.clone();
+    let ip = request.ip;
+    // Offices and VPNs behind one address create links in bursts of their own.
+    let burst_ip = ip.clone().filter(|ip| !ip.parse().is_ok_and(|ip| config.trusts(ip)));
+
+    let inserted = conn.interact(move |conn| {
+        conn.immediate_transaction::<_, diesel::result::Error, _>(|conn| {
+            if let Some(key) = idempotency_key.as_deref() {
+                if let Some(record) = idempotency::find(conn, &idempotency_scope, key, idempotency_ttl)? {
+                    return Ok(Insertion::Replayed(record));
+                }
+            }
+
+            // The transaction holds the write lock, so a free code stays free until inserted.
+            let picked = match candidates {
+                Some(candidates) => candidates.pick(conn)?.ok_or(diesel::result::Error::NotFound)?,
+                None => {
+                    let alias = alias.expect("links without candidates have an alias");
+                    bundles::ensure_free_for_link(conn, &alias)?;
+                    Pick::Free(alias)
+                }
+            };
+            new_url.id = match picked {
+                Pick::Free(id) => id,
+                Pick::Existing(id) => {
+                    if let Some(key) = idempotency_key.as_deref() {
+                        idempotency::save(conn, &idempotency_scope, key, &new_url.url, &id)?;
+                    }
+                    return Ok(Insertion::Existing(id))
+                }
+            };
+            if let Some(actor) = actor.as_deref() {
+                if let Err(err) = usage::count(conn, actor, Meter::LinksCreated, link_quota)? {
+                    return Ok(Insertion::OverQuota(err));
+                }
+            }
+            if let Some(assessment) = assessment.as_mut() {
+                spam_policy.check_burst(conn, assessment, burst_ip.as_deref())?;
+                new_url.spam_score = assessment.score;
+                new_url.quarantined = spam_policy.quarantines(assessment);
+            }
+            diesel::insert_into(urls::table)
+                .values(&new_url)
+                .execute(conn)?;
+
+            audit::record(conn, NewAuditEntry {
+                actor: actor.as_deref(),
+                action: "create",
+                target: Some(&new_url.id),
+                ip: ip.as_deref()
+            })?;
+            if new_url.quarantined {
+                audit::record(conn, NewAuditEntry {
+                    actor: Some(spam::ACTOR),
+                    action: "quarantine",
+                    target: Some(&new_url.id),
+                    ip: ip.as_deref()
+                })?;
+                let reasons = assessment.as_ref().map(|assessment| assessment.reasons.join(", ")).unwrap_or_default();
+                println!("Quarantined {} scoring {} ({reasons})", new_url.id, new_url.spam_score);
+            }
+
+            if let Some(key) = idempotency_key.as_deref() {
+                idempotency::save(conn, &idempotency_scope, key, &new_url.url, &new_url.id)?;
+            }
+            Ok(Insertion::Inserted { id: new_url.id, quarantined: new_url.quarantined })
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(|err| match err {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) if !generated => {
+            errors::invalid("alias", "alias_taken", (StatusCode::CONFLICT, "Alias is already taken".into()))
+        }
+        diesel::result::Error::NotFound => {
+            errors::coded("codes_exhausted", (StatusCode::SERVICE_UNAVAILABLE, "No free short code found; try again or use a longer ID_LENGTH".into()))
+        }
+        err => internal_error(err)
+    })?;
+
+    let id = match inserted {
+        Insertion::Replayed(record) if record.request_url != origin_url => return Err(
+            errors::coded("idempotency_key_reused", (StatusCode::UNPROCESSABLE_ENTITY, format!("{} was already used for a different URL", idempotency::HEADER)))
+        ),
+        Insertion::Replayed(record) => record.url_id,
+        Insertion::OverQuota(err) => return Err(err),
+        Insertion::Existing(id) => id,
+        Insertion::Inserted { id, quarantined } => {
+            counter!("links_created_total", "actor" => telemetry::actor_label(config.metrics_max_actors, created_by.as_deref())).increment(1);
+            // Archiving is public, and screenshot providers are third parties,
+            // so links that aren't live yet would be announced early.
+            if quarantined {
+                counter!("links_quarantined_total").increment(1);
+            } else if publish_at.is_none_or(|publish_at| publish_at <= Utc::now().naive_utc()) {
+                archive::enqueue(&id, &origin_url);
+                screenshot::enqueue(&id, &origin_url);
+            }
+            events::publish(Some(&id), EventKind::Created { url: origin_url.clone(), actor: created_by, quarantined });
+            id
+        }
+    };
+
+    Ok(CreatedLink { id, url: origin_url })
+}
+
+/// Everything known about a link, for admins. Unlike public listings this
+/// includes who created it.
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = urls)]
+pub struct LinkDetails {
+    id: String,
+    url: String,
+    disabled: bool,
+    public_stats: bool,
+    title: Option<String>,
+    notes: Option<String>,
+    /// The preview shown to social crawlers, if overridden.
+    og_title: Option<String>,
+    og_description: Option<String>,
+    og_image: Option<String>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    /// The API token or integration user that created the link, if any.
+    created_by: Option<String>,
+    creator_ip: Option<String>,
+    publish_at: Option<NaiveDateTime>,
+    archive_url: Option<String>,
+    broken_at: Option<NaiveDateTime>,
+    spam_score: i32,
+    quarantined: bool,
+    /// Why the link's clicks were flagged as abnormal, and when that was last seen.
+    anomaly: Option<String>,
+    anomaly_at: Option<NaiveDateTime>
+}
+
+/// A link's details with what its latest health check found.
+#[derive(Serialize)]
+pub struct CheckedLinkDetails {
+    #[serde(flatten)]
+    details: LinkDetails,
+    redirect_chain: Option<health::RedirectChain>
+}
+
+pub async fn link_details(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<CheckedLinkDetails>, ApiError> {
+    let id = normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let details = conn.interact(move |conn| {
+        let Some(details) = urls::table
+            .find(&id)
+            .select(LinkDetails::as_select())
+            .get_result(conn)
+            .optional()? else {
+            return Ok(None)
+        };
+        let redirect_chain = health::latest_chain(conn, &id)?;
+        Ok::<_, diesel::result::Error>(Some(CheckedLinkDetails { details, redirect_chain }))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(details) = details else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    };
+    Ok(Json(details))
+}
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct LinkListQuery {
+    /// Words to look for in the destination, title and notes.
+    q: Option<String>,
+    /// The `next` cursor of the previous page.
+    after: Option<String>,
+    /// Links to skip instead, for jumping to a page. Gets slower the further in it goes.
+    offset: Option<i64>,
+    limit: Option<i64>
+}
+
+/// An FTS5 query matching links containing every word of `q`, or words starting
+/// with it. Quoting each word keeps FTS5 operators in what people type from being
+/// interpreted.
+fn search_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q.split_whitespace()
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Matches the links whose destination, title or notes contain what `q` looks for,
+/// or `None` when it looks for nothing.
+pub fn search(q: &str) -> Option<Box<dyn BoxableExpression<urls::table, Sqlite, SqlType = Bool>>> {
+    let query = search_query(q)?;
+    Some(Box::new(
+        sql::<Bool>("urls.rowid IN (SELECT rowid FROM urls_search WHERE urls_search MATCH ")
+            .bind::<Text, _>(query)
+            .sql(")")
+    ))
+}
+
+/// Criteria for investigating abuse across all links, e.g. a campaign pointing
+/// at one domain or created from one address. Every given criterion must match.
+#[derive(Deserialize, Clone, Default)]
+pub struct LinkFilter {
+    /// Destinations on this domain or its subdomains.
+    domain: Option<String>,
+    creator_ip: Option<String>,
+    created_from: Option<DateTime<Utc>>,
+    created_until: Option<DateTime<Utc>>
+}
+
+impl LinkFilter {
+    /// Matches the links meeting every criterion, or `None` when none is given.
+    pub fn condition(&self) -> Option<Box<dyn BoxableExpression<urls::table, Sqlite, SqlType = Bool>>> {
+        let mut conditions: Vec<Box<dyn BoxableExpression<urls::table, Sqlite, SqlType = Bool>>> = Vec::new();
+        if let Some(domain) = &self.domain {
+            let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+            let host = url_host(urls::url);
+            conditions.push(Box::new(
+                host.eq(domain.clone()).or(host.like(format!("%.{domain}"))).assume_not_null()
+            ));
+        }
+        if let Some(ip) = &self.creator_ip {
+            conditions.push(Box::new(urls::creator_ip.eq(ip.trim().to_owned()).assume_not_null()));
+        }
+        if let Some(from) = self.created_from {
+            conditions.push(Box::new(urls::created_at.ge(from.naive_utc())));
+        }
+        if let Some(until) = self.created_until {
+            conditions.push(Box::new(urls::created_at.le(until.naive_utc())));
+        }
+        conditions.into_iter().reduce(|all, condition| Box::new(all.and(condition)))
+    }
+}
+
+/// Every link, or those matching `?q=` and the [`LinkFilter`] criteria, newest first.
+pub async fn list_links(
+    Query(query): Query<LinkListQuery>,
+    Query(filter): Query<LinkFilter>,
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Page<LinkDetails>>, ApiError> {
+    if query.after.is_some() && query.offset.is_some() {
+        return Err(
+            (StatusCode::BAD_REQUEST, "Use either after or offset, not both".into()).into()
+        )
+    }
+    let after = query.after.as_deref().map(cursor::decode).transpose()?;
+    let search = query.q.as_deref().and_then(search);
+    let filter = filter.condition();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let links = conn.interact(move |conn| {
+        // One row beyond the page tells whether there's another one.
+        let mut statement = urls::table
+            .select(LinkDetails::as_select())
+            .order((urls::created_at.desc(), urls::id.desc()))
+            .limit(limit + 1)
+            .into_boxed();
+
+        if let Some(search) = search {
+            statement = statement.filter(search);
+        }
+        if let Some(filter) = filter {
+            statement = statement.filter(filter);
+        }
+        if let Some((created_at, id)) = after {
+            statement = statement.filter(
+                urls::created_at.lt(created_at)
+                    .or(urls::created_at.eq(created_at).and(urls::id.lt(id)))
+            );
+        }
+        if let Some(offset) = query.offset {
+            statement = statement.offset(offset.max(0));
+        }
+
+        statement.load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(cursor::paginate(links, limit, |link| (link.created_at, link.id.clone()))))
+}
+
+#[derive(Deserialize)]
+pub struct BurstQuery {
+    /// How far back to look, 7 days by default.
+    days: Option<i64>,
+    /// The longest gap between two links of one burst, 60 seconds by default.
+    gap_secs: Option<i64>,
+    /// The fewest links that make a burst, 10 by default.
+    min_links: Option<usize>
+}
+
+/// Links created from one address in quick succession, which scripted abuse
+/// campaigns leave behind. List or disable them with `creator_ip`, `created_from`
+/// and `created_until`.
+#[derive(Serialize)]
+pub struct CreationBurst {
+    creator_ip: String,
+    first_created_at: NaiveDateTime,
+    last_created_at: NaiveDateTime,
+    links: usize
+}
+
+/// Bursts with the most links sorted first, up to this many.
+const MAX_BURSTS: usize = 100;
+
+/// Groups each address's creation times, sorted, into runs without a gap longer
+/// than `gap`, and keeps those of at least `min_links` links.
+fn find_bursts(creations: Vec<(String, NaiveDateTime)>, gap: chrono::Duration, min_links: usize) -> Vec<CreationBurst> {
+    let mut bursts = Vec::new();
+    let mut current: Option<CreationBurst> = None;
+    for (ip, created_at) in creations {
+        if let Some(burst) = current.as_mut() {
+            if burst.creator_ip == ip && created_at - burst.last_created_at <= gap {
+                burst.last_created_at = created_at;
+                burst.links += 1;
+                continue
+            }
+        }
+        bursts.extend(current.replace(CreationBurst { creator_ip: ip, first_created_at: created_at, last_created_at: created_at, links: 1 }));
+    }
+    bursts.extend(current);
+    bursts.retain(|burst| burst.links >= min_links);
+    bursts.sort_by(|a, b| b.links.cmp(&a.links).then(b.last_created_at.cmp(&a.last_created_at)));
+    bursts.truncate(MAX_BURSTS);
+    bursts
+}
+
+pub async fn creation_bursts(
+    Query(query): Query<BurstQuery>,
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Vec<CreationBurst>>, ApiError> {
+    let since = Utc::now().naive_utc() - chrono::Duration::days(query.days.unwrap_or(7).clamp(1, 365));
+    let gap = chrono::Duration::seconds(query.gap_secs.unwrap_or(60).max(0));
+    let min_links = query.min_links.unwrap_or(10).max(2);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let creations = conn.interact(move |conn| {
+        urls::table
+            .filter(urls::created_at.ge(since))
+            .filter(urls::creator_ip.is_not_null())
+            .order((urls::creator_ip, urls::created_at))
+            .select((urls::creator_ip.assume_not_null(), urls::created_at))
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(find_bursts(creations, gap, min_links)))
+}
+
+/// Tells a field set to `null` apart from one left out.
+pub fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Fields of a link that can be edited in place. Absent ones are left alone,
+/// `null` clears them.
+#[derive(Deserialize, AsChangeset)]
+#[diesel(table_name = urls)]
+pub struct LinkUpdate {
+    #[serde(default, deserialize_with = "present")]
+    title: Option<Option<String>>,
+    #[serde(default, deserialize_with = "present")]
+    notes: Option<Option<String>>
+}
+
+pub async fn update_link(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(update): Json<LinkUpdate>
+) -> Result<Json<LinkDetails>, ApiError> {
+    if update.title.is_none() && update.notes.is_none() {
+        return Err(
+            errors::coded("nothing_to_update", (StatusCode::UNPROCESSABLE_ENTITY, "Nothing to update".into()))
+        )
+    }
+    let update = LinkUpdate {
+        title: update.title.map(|title| annotation(title, "Title", MAX_TITLE_LENGTH)).transpose()?,
+        notes: update.notes.map(|notes| annotation(notes, "Notes", MAX_NOTES_LENGTH)).transpose()?
+    };
+    let id = normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let details = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set(&update)
+                .execute(conn)?;
+            if updated == 0 {
+                return Ok(None)
+            }
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "update",
+                target: Some(&id),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            urls::table
+                .find(&id)
+                .select(LinkDetails::as_select())
+                .get_result(conn)
+                .map(Some)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(details) = details else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    };
+    Ok(Json(details))
+}
+
+/// Deletes links along with their click history, health checks and idempotency keys.
+/// `ids` must fit in SQLite's bind parameter limit.
+pub fn delete(conn: &mut SqliteConnection, ids: &[String]) -> QueryResult<usize> {
+    diesel::delete(conversions::table)
+        .filter(conversions::url_id.eq_any(ids))
+        .execute(conn)?;
+    diesel::delete(clicks::table)
+        .filter(clicks::url_id.eq_any(ids))
+        .execute(conn)?;
+    diesel::delete(click_stats_daily::table)
+        .filter(click_stats_daily::url_id.eq_any(ids))
+        .execute(conn)?;
+    diesel::delete(link_checks::table)
+        .filter(link_checks::url_id.eq_any(ids))
+        .execute(conn)?;
+    diesel::delete(idempotency_keys::table)
+        .filter(idempotency_keys::url_id.eq_any(ids))
+        .execute(conn)?;
+    diesel::delete(urls::table)
+        .filter(urls::id.eq_any(ids))
+        .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_quick_creations_into_bursts() {
+        let at = |secs| NaiveDateTime::default() + chrono::Duration::seconds(secs);
+        let creations = vec![
+            ("192.0.2.1".to_owned(), at(0)),
+            ("192.0.2.1".to_owned(), at(30)),
+            ("192.0.2.1".to_owned(), at(80)),
+            ("192.0.2.1".to_owned(), at(1000)),
+            ("192.0.2.2".to_owned(), at(1010)),
+            ("192.0.2.2".to_owned(), at(1020))
+        ];
+        let bursts = find_bursts(creations, chrono::Duration::seconds(60), 2);
+        assert_eq!(bursts.len(), 2);
+        assert_eq!((bursts[0].creator_ip.as_str(), bursts[0].links), ("192.0.2.1", 3));
+        assert_eq!(bursts[0].last_created_at, at(80));
+        assert_eq!((bursts[1].creator_ip.as_str(), bursts[1].links), ("192.0.2.2", 2));
+    }
+
+    #[test]
+    fn hash_codes_follow_their_input() {
+        let alphabet: Vec<char> = "0123456789abcdef".chars().collect();
+        let code = hash_code(&alphabet, 20, "https://example.com", 0);
+        assert_eq!(code.chars().count(), 20);
+        assert!(code.chars().all(|c| alphabet.contains(&c)));
+        assert_eq!(code, hash_code(&alphabet, 20, "https://example.com", 0));
+        assert_ne!(code, hash_code(&alphabet, 20, "https://example.com", 1));
+        assert_ne!(code, hash_code(&alphabet, 20, "https://example.org", 0));
+        assert!(code.starts_with(&hash_code(&alphabet, 6, "https://example.com", 0)));
+    }
+
+    #[test]
+    fn hashing_the_same_destination_again_never_runs_out() {
+        let mut conn = crate::database::test_database();
+        let candidates = |creator: &str| Candidates::Hashed {
+            input: "https://example.com/".into(),
+            alphabet: "0123456789abcdef".chars().collect(),
+            length: 8,
+            honeypots: Vec::new(),
+            reusable_by: Some(Some(creator.to_owned()))
+        };
+        let mut create = |creator: &str| match candidates(creator).pick(&mut conn).unwrap() {
+            Some(Pick::Free(id)) => {
+                diesel::insert_into(urls::table)
+                    .values((urls::id.eq(&id), urls::url.eq("https://example.com/"), urls::created_by.eq(creator)))
+                    .execute(&mut conn)
+                    .unwrap();
+                Ok(id)
+            }
+            Some(Pick::Existing(id)) => Err(id),
+            None => panic!("codes ran out for {creator}")
+        };
+
+        // Each creator gets a link of their own, beyond the spare attempts.
+        let created: Vec<String> = (0..8)
+            .map(|n| create(&format!("token:{n}")).unwrap())
+            .collect();
+        assert_eq!(created.iter().collect::<std::collections::HashSet<_>>().len(), 8);
+        // A creator shortening it again gets their link back.
+        for _ in 0..6 {
+            assert_eq!(create("token:5"), Err(created[5].clone()));
+        }
+    }
+
+    #[test]
+    fn search_terms_are_quoted_prefixes() {
+        assert_eq!(search_query("launch  Q4").as_deref(), Some("\"launch\"* \"Q4\"*"));
+        assert_eq!(search_query("say \"hi\" OR NOT").as_deref(), Some("\"say\"* \"\"\"hi\"\"\"* \"OR\"* \"NOT\"*"));
+        assert_eq!(search_query("   "), None);
+    }
+}
@@ -1,134 +1,842 @@
-use std::{env, sync::Arc};
+use crate::errors::ApiError;
+use std::{net::SocketAddr, sync::Arc};
 
+use arc_swap::ArcSwap;
 use axum::{
-    extract::{Path, State}, http::{StatusCode, Uri}, response::Redirect, routing::get, Extension, Json, Router
+    extract::{ConnectInfo, DefaultBodyLimit, Query, State}, http::{header, HeaderMap, HeaderName, Method, StatusCode, Uri}, middleware, response::IntoResponse, routing::{delete, get, post, put}, Extension, Router
 };
 
+use chrono::{DateTime, NaiveDateTime, Utc};
+use auth::Creator;
+use config::{AppConfig, LiveConfig, TrackingOptOut};
 use deadpool_diesel::{sqlite, Runtime};
 use diesel::prelude::*;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations};
 use dotenvy::dotenv;
-use errors::internal_error;
-use nanoid::nanoid;
+use error_pages::Unavailable;
+use errors::{internal_error, unavailable};
+use events::EventKind;
+use i18n::Locale;
+use metrics::counter;
+use links::CreateLink;
+use negotiate::{Format, Negotiated, Representation};
+use percent_encoding::percent_decode_str;
 use schema::urls::{self};
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{net::TcpListener, task::JoinSet};
+use tower_http::{catch_panic::CatchPanicLayer, compression::CompressionLayer};
+use usage::Meter;
 
 mod schema;
+mod config;
+mod cursor;
 mod errors;
-
-struct AppConfig {
-    addr: String,
-    nano_id_alphabet: [char; 16]
-}
+mod audit;
+mod auth;
+mod captcha;
+mod tokens;
+mod idempotency;
+mod alias;
+mod bulk;
+mod html;
+mod stats;
+mod compare;
+mod badge;
+mod bitly;
+mod events;
+#[cfg(feature = "shlink")]
+mod shlink;
+mod feed;
+mod sitemap;
+mod links;
+mod slack;
+mod telegram;
+mod negotiate;
+mod etag;
+mod https;
+mod version;
+mod overview;
+mod telemetry;
+mod seed;
+mod bench;
+mod snowflake;
+mod sequential;
+mod encryption;
+mod database;
+mod privacy;
+mod conversions;
+mod interstitial;
+mod preview;
+mod error_pages;
+mod home;
+mod well_known;
+mod i18n;
+mod checksum;
+mod signing;
+mod spam;
+mod settings;
+mod validate;
+mod confusables;
+mod scan;
+mod security_log;
+mod mail;
+mod archive;
+mod screenshot;
+mod health;
+mod ssrf;
+mod versioning;
+mod timeouts;
+mod qr;
+mod systemd;
+mod retention;
+mod cold;
+mod history;
+mod anomaly;
+mod policy;
+mod bundles;
+mod share;
+mod vacuum;
+mod export;
+mod webhooks;
+mod startup;
+mod rebase;
+mod roots;
+mod usage;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/");
 
 #[derive(Serialize, Selectable, Queryable)]
 struct Url {
     id: String,
-    url: String
+    url: String,
+    disabled: bool,
+    public_stats: bool,
+    created_at: NaiveDateTime,
+    /// Last change to where or how the link redirects, or to how it's labeled and listed.
+    updated_at: NaiveDateTime,
+    title: Option<String>,
+    publish_at: Option<NaiveDateTime>,
+    /// Wayback Machine snapshot of the destination, once archived.
+    archive_url: Option<String>,
+    /// When the destination was found broken by health checks, answering with a warning page since.
+    broken_at: Option<NaiveDateTime>,
+    /// Held back by the spam filter until an admin approves it.
+    quarantined: bool,
+    /// Why its clicks were flagged as abnormal, for admins only.
+    #[serde(skip)]
+    anomaly: Option<String>,
+    /// The actor that created it, which labels its redirects in the metrics.
+    #[serde(skip)]
+    created_by: Option<String>
 }
 
 #[derive(Deserialize, Insertable, Clone)]
 #[diesel(table_name = urls)]
 struct NewUrl {
     id: String,
-    url: String
+    url: String,
+    public_stats: bool,
+    publish_at: Option<NaiveDateTime>,
+    spam_score: i32,
+    quarantined: bool,
+    created_by: Option<String>,
+    creator_ip: Option<String>,
+    title: Option<String>,
+    notes: Option<String>
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    let db_url = env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set");
+    let db_url = config::secret("DATABASE_URL")
+        .unwrap_or_else(|err| startup::exit(&err))
+        .unwrap_or_else(|| startup::exit("DATABASE_URL must be set, e.g. to database.sqlite3"));
 
     let manager = 
         sqlite::Manager::new(db_url, Runtime::Tokio1);
-    let pool = sqlite::Pool::builder(manager)
+    let mut pool = sqlite::Pool::builder(manager);
+    if let Some(key) = config::secret("DATABASE_KEY").unwrap_or_else(|err| startup::exit(&err)) {
+        if !cfg!(feature = "sqlcipher") {
+            startup::exit("DATABASE_KEY is set but this build lacks the sqlcipher feature; build with --features sqlcipher")
+        }
+        pool = pool.post_create(encryption::unlock(key));
+    }
+    let pool = pool
+        .post_create(database::wait_for_locks())
+        .post_create(database::register_functions())
         .build()
-        .unwrap();
+        .unwrap_or_else(|err| startup::exit(&format!("Can't set up the connection pool: {err}")));
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // For deployments that migrate in a step of their own, with `shorturl migrate`.
+    let skip_migrations = args.iter().any(|arg| arg == "--skip-migrations");
+    args.retain(|arg| arg != "--skip-migrations");
+    let migrate_only = args.first().map(String::as_str) == Some("migrate");
+    if migrate_only || !skip_migrations {
+        let applied = database::migrate(&pool)
+            .await
+            .unwrap_or_else(|err| startup::exit(&err));
+        if applied.is_empty() {
+            println!("Database schema is up to date");
+        } else {
+            println!("Applied migrations {}", applied.join(", "));
+        }
+    }
+    if migrate_only {
+        return
+    }
+
+    settings::load(&pool)
+        .await
+        .unwrap_or_else(|err| startup::exit(&err));
+    let config = AppConfig::from_env()
+        .map(settings::overlay)
+        .unwrap_or_else(|err| startup::exit(&err));
+    let listen_addrs = config.listen_addrs.clone();
+    let admin_listen_addrs = config.admin_listen_addrs.clone();
+    let max_body_bytes = config.max_body_bytes;
+    let enabled = |algorithm| config.compression.iter().any(|enabled| enabled == algorithm);
+    let compression = CompressionLayer::new()
+        .gzip(enabled("gzip"))
+        .br(enabled("br"))
+        .no_deflate()
+        .no_zstd();
+    let telegram_bot_token = config.telegram_bot_token.clone();
+    let archive = config.archive_on_create.then_some(config.archive_interval);
+    let allow_private_destinations = config.allow_private_destinations;
+    let health_check_interval = config.health_check_interval;
+    let anomaly_check_interval = config.anomaly_check_interval;
+    let db_maintenance_hour = config.db_maintenance_hour;
+    let screenshots = config.screenshot_url.clone().map(|provider| (provider, config.screenshot_dir.clone()));
 
-    let alphabet: [char; 16] = [
-        '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b', 'c', 'd', 'e', 'f'
-    ];
+    validate::install(validate::Pipeline::standard().with(validate::Blocklist).with(validate::PublicHosts));
 
-    let config = AppConfig {
-        addr: "127.0.0.1:3000".into(),
-        nano_id_alphabet: alphabet
+    let command = match args.first().map(String::as_str) {
+        Some("seed") => Some(seed::run(pool.clone(), &config, &args[1..]).await),
+        Some("bench") => Some(bench::run(pool.clone(), &args[1..]).await),
+        Some("privacy") => Some(privacy::run(pool.clone(), &args[1..]).await),
+        Some("webhook") => Some(webhooks::run(&args[1..])),
+        Some("rebase") => Some(rebase::run(pool.clone(), &args[1..]).await),
+        _ => None
     };
+    if let Some(result) = command {
+        result.unwrap_or_else(|err| startup::exit(&err));
+        return
+    }
 
-    let app = Router::new()
-        .route("/url/add/:origin_url", get(add_url))
-        .route("/:id", get(redirect_to))
-        .layer(Extension(Arc::new(config)))
-        .with_state(pool);
+    let mut report = startup::Report::default();
+    startup::check_database(&pool, &mut report).await;
+    startup::check_paths(&config, &mut report);
+    startup::check_codes(&config, &mut report);
+    startup::check_public_addr(&config, &mut report).await;
+    // Sockets passed by systemd take the place of the addresses they stand in for.
+    let (activated, activated_admin) = systemd::activated_listeners()
+        .unwrap_or_else(|err| startup::exit(&format!("Can't take over the sockets passed by systemd: {err}")));
+    let mut bind_all = |activated: Vec<std::net::TcpListener>, addrs: Vec<SocketAddr>, setting: &str| {
+        let bound: Vec<(String, std::io::Result<TcpListener>)> = if activated.is_empty() {
+            addrs.into_iter().map(|addr| (format!("listen {addr}"), bind(addr))).collect()
+        } else {
+            activated.into_iter().map(|listener| ("systemd socket".to_owned(), TcpListener::from_std(listener))).collect()
+        };
+        bound.into_iter()
+            .filter_map(|(check, bound)| match bound {
+                Ok(listener) => {
+                    report.ok(check, "bound");
+                    Some(listener)
+                }
+                Err(err) => {
+                    report.fail(check, format!("{err}; stop whatever uses the port or change {setting}"));
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let public_listeners = bind_all(activated, listen_addrs, "LISTEN_ADDRS");
+    let admin_listeners = bind_all(activated_admin, admin_listen_addrs, "ADMIN_LISTEN_ADDRS");
+    report.conclude();
 
-    let listener = TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-    println!("ShortURL service has been run on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    let metrics = telemetry::install();
+
+    let live: LiveConfig = Arc::new(ArcSwap::from_pointee(config));
+    #[cfg(unix)]
+    config::reload_on_sighup(live.clone());
+
+    if let Some(token) = telegram_bot_token {
+        telegram::spawn(token, pool.clone(), live.clone());
+    }
+    if let Some(interval) = archive {
+        archive::spawn(pool.clone(), interval, allow_private_destinations);
+    }
+    if let Some((provider, dir)) = screenshots {
+        screenshot::spawn(pool.clone(), provider, dir, allow_private_destinations);
+    }
+    retention::spawn(pool.clone(), live.clone());
+    cold::spawn(pool.clone(), live.clone());
+    webhooks::spawn(pool.clone(), allow_private_destinations);
+    if !health_check_interval.is_zero() {
+        health::spawn(pool.clone(), live.clone(), health_check_interval, allow_private_destinations);
+    }
+    if !anomaly_check_interval.is_zero() {
+        anomaly::spawn(pool.clone(), anomaly_check_interval);
+    }
+    if let Some(hour) = db_maintenance_hour {
+        vacuum::spawn(pool.clone(), hour);
+    }
+
+    let admin = Router::new()
+        .route("/audit-log", get(audit::list_audit_log))
+        .route("/urls", get(links::list_links))
+        .route("/creation-bursts", get(links::creation_bursts))
+        .route("/urls/:id", get(links::link_details).patch(links::update_link))
+        .route("/urls/:id/clicks", get(stats::click_history))
+        .route("/urls/:id/history", get(history::link_history))
+        .route("/clicks/export", get(export::export_clicks))
+        .route("/urls/:id/public-stats", put(stats::set_public_stats))
+        .route("/urls/:id/preview-links", post(share::create_preview_link))
+        .route("/urls/:id/pixels", put(interstitial::set_pixels))
+        .route("/urls/:id/interstitial", put(interstitial::set_interstitial))
+        .route("/urls/:id/redirect-mode", put(interstitial::set_redirect_mode))
+        .route("/urls/:id/open-graph", put(preview::set_open_graph))
+        .route("/urls/:id/indexable", put(sitemap::set_indexable))
+        .route("/urls/:id/restore", post(health::restore_link))
+        .route("/urls/:id/approve", post(spam::approve_link))
+        .route("/bundles", get(bundles::list_bundles).post(bundles::create_bundle))
+        .route("/bundles/:id", get(bundles::bundle_details).put(bundles::update_bundle).delete(bundles::delete_bundle))
+        .route("/quarantine", get(spam::list_quarantine))
+        .route("/archived-links", get(cold::list_archived_links))
+        .route("/archived-links/:id/restore", post(cold::restore_archived_link))
+        .route("/reload", post(config::reload_config))
+        .route("/settings", get(settings::list_settings).patch(settings::update_settings))
+        .route("/overview", get(overview::instance_overview))
+        .route("/database/maintenance", post(vacuum::run_maintenance))
+        .route("/link-health", get(health::list_link_health))
+        .route("/link-health/:id", get(health::link_health_history))
+        .route("/privacy", delete(privacy::erase_subject))
+        .route("/privacy/export", get(privacy::export_subject))
+        .route("/metrics", get(telemetry::render_metrics))
+        .route("/mail/test", post(mail::send_test_email))
+        .route("/tokens", get(tokens::list_tokens).post(tokens::create_token))
+        .route("/webhooks", get(webhooks::list_webhooks).post(webhooks::create_webhook))
+        .route("/webhooks/:id", delete(webhooks::delete_webhook))
+        .route("/tokens/:id", delete(tokens::revoke_token))
+        .route("/tokens/:id/rotate", post(tokens::rotate_token))
+        .route_layer(middleware::from_fn(etag::conditional_get));
+
+    let bulk = Router::new()
+        .route("/bulk-delete", post(bulk::bulk_delete))
+        .route("/bulk-disable", post(bulk::bulk_disable))
+        .route("/bulk-enable", post(bulk::bulk_enable));
+
+    let qr_codes = Router::new()
+        .route("/qr/batch", post(qr::batch));
+
+    let feed = Router::new()
+        .route("/feed.xml", get(feed::recent_links_feed))
+        .route_layer(middleware::from_fn(etag::conditional_get));
+
+    let api_reads = Router::new()
+        .route("/urls", get(find_urls))
+        .route("/aliases/:alias/availability", get(alias::check_availability).layer(middleware::from_fn(scan::guard)))
+        .route_layer(middleware::from_fn(etag::conditional_get));
+
+    let reads = Router::new()
+        .route("/badge/:file", get(badge::click_badge).layer(middleware::from_fn(scan::guard)))
+        .route("/:id/stats", get(stats::public_stats_page).layer(middleware::from_fn(scan::guard)))
+        .route("/:id/screenshot", get(screenshot::link_screenshot).layer(middleware::from_fn(scan::guard)))
+        .route("/sitemap.xml", get(sitemap::sitemap_xml))
+        .route_layer(middleware::from_fn(etag::conditional_get));
+
+    let event_stream = Router::new()
+        .route("/ws", get(events::live_events));
+
+    let bitly_reads = Router::new()
+        .route("/v4/bitlinks/*bitlink", get(bitly::bitlink));
+
+    // Paths within the JSON API, which is mounted under `/api/v1` and its old `/api`.
+    let internal_v1 = Router::new()
+        .nest("/admin", admin)
+        .nest("/urls", bulk)
+        .route("/urls/:id/history", get(history::link_history).layer(middleware::from_fn(etag::conditional_get)))
+        .route("/compare", get(compare::compare_links))
+        .merge(qr_codes)
+        .merge(event_stream);
+
+    let public_v1 = Router::new()
+        .route("/shorten", get(shorten_plain))
+        .route("/version", get(version::version))
+        .route("/me/usage", get(usage::my_usage))
+        .route("/conversions", post(conversions::report_conversion).layer(conversions::cors()))
+        .route("/conversions/pixel.gif", get(conversions::conversion_pixel))
+        .route("/integrations/slack", post(slack::slash_command))
+        .merge(api_reads);
+
+    let internal = Router::new()
+        .merge(versioning::mount(internal_v1))
+        .merge(feed)
+        .merge(bitly_reads);
+    #[cfg(feature = "shlink")]
+    let internal = internal.merge(shlink::router());
+
+    let public = Router::new()
+        .route("/", get(roots::root).post(home::shorten_form))
+        .route("/favicon.ico", get(well_known::favicon))
+        .route("/robots.txt", get(well_known::robots_txt))
+        .route("/.well-known/*path", get(well_known::well_known))
+        .route("/url/add/*origin_url", get(add_url))
+        .route("/v4/shorten", post(bitly::shorten))
+        .route("/v4/bitlinks", post(bitly::shorten))
+        .merge(versioning::mount(public_v1))
+        .route("/:id", get(redirect_to).layer(middleware::from_fn(scan::guard)))
+        .merge(reads)
+        // Paths no route matched, which is where vanity paths like `/go/product/launch`
+        // end up after single-segment codes and the pages below them.
+        .fallback(get(redirect_to).layer(middleware::from_fn(scan::guard)));
+
+    let serve = |router: Router<sqlite::Pool>| {
+        let router = router
+            .layer(CatchPanicLayer::custom(errors::panic_response))
+            .layer(middleware::from_fn(errors::retry_after))
+            .layer(middleware::from_fn_with_state(pool.clone(), policy::enforce));
+        #[cfg(feature = "shlink")]
+        let router = router.layer(middleware::from_fn(shlink::api_key));
+        router
+            .layer(middleware::from_fn(timeouts::enforce))
+            .layer(middleware::from_fn(telemetry::track))
+            .layer(middleware::from_fn(errors::describe))
+            .layer(DefaultBodyLimit::max(max_body_bytes))
+            .layer(compression.clone())
+            .layer(middleware::from_fn(https::enforce))
+            .layer(middleware::from_fn(rebase::bridge))
+            .layer(middleware::from_fn(config::inject))
+            .layer(Extension(live.clone()))
+            .layer(Extension(metrics.clone()))
+            .with_state(pool.clone())
+    };
+
+    // With separate admin addresses, e.g. on a port that isn't exposed publicly,
+    // the admin API is only served there.
+    let listeners: Vec<(TcpListener, Router)> = if admin_listeners.is_empty() {
+        let app = serve(public.merge(internal));
+        public_listeners.into_iter().map(|listener| (listener, app.clone())).collect()
+    } else {
+        let (public, internal) = (serve(public), serve(internal));
+        public_listeners.into_iter()
+            .map(|listener| (listener, public.clone()))
+            .chain(admin_listeners.into_iter().map(|listener| (listener, internal.clone())))
+            .collect()
+    };
+
+    let mut servers = JoinSet::new();
+    for (listener, app) in listeners {
+        println!("ShortURL service has been run on {}", listener.local_addr().unwrap());
+        servers.spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+        });
+    }
+    systemd::notify_ready();
+    while let Some(served) = servers.join_next().await {
+        served.unwrap().unwrap();
+    }
 }
 
-async fn add_url(
-    Path(origin_url): Path<String>,
-    State(pool): State<sqlite::Pool>,
-    Extension(config): Extension<Arc<AppConfig>>
-) -> Result<Json<AddUrlResponse>, (StatusCode, String)> {
-    if origin_url.parse::<Uri>().is_err() {
-        return Err(
-            (StatusCode::BAD_REQUEST, "Not a valid URL".into())
-        )
+/// Binds `addr` for listening. IPv6 sockets are made v6-only so that `[::]` and
+/// `0.0.0.0` on the same port can be listened on side by side.
+fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
     }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
 
-    let conn = pool.get()
-        .await
-        .map_err(internal_error)?;
+/// Query parameters [`add_url`] reads itself; any others belong to the URL being shortened.
+const ADD_URL_OPTIONS: [&str; 7] = ["alias", "public_stats", "publish_at", "title", "notes", "id_strategy", "include_qr"];
 
-    let new_url = NewUrl {
-        id: nanoid!(10, &config.nano_id_alphabet),
-        url: origin_url.clone()
+#[derive(Deserialize)]
+struct AddUrlOptions {
+    alias: Option<String>,
+    #[serde(default)]
+    public_stats: bool,
+    publish_at: Option<DateTime<Utc>>,
+    title: Option<String>,
+    notes: Option<String>,
+    id_strategy: Option<String>,
+    /// Adds the short URL's QR code to the response, saving kiosks and print
+    /// pipelines a second request.
+    include_qr: Option<qr::Embedding>
+}
+
+/// Gives back the query string a URL passed unencoded lost to the request, e.g. the
+/// `?q=rust&page=2` of `/url/add/https://example.com/search?q=rust&page=2`, leaving out
+/// the options meant for [`add_url`].
+fn with_own_query(origin_url: String, query: Option<&str>) -> String {
+    let own: Vec<&str> = query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let name = pair.split_once('=').map_or(*pair, |(name, _)| name);
+            !ADD_URL_OPTIONS.contains(&name)
+        })
+        .collect();
+    if own.is_empty() {
+        return origin_url
+    }
+    // A fragment can only have come percent-encoded, and has to stay last.
+    let (url, fragment) = match origin_url.split_once('#') {
+        Some((url, fragment)) => (url, format!("#{fragment}")),
+        None => (origin_url.as_str(), String::new())
     };
-    let _new_url = new_url.clone();
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{url}{separator}{}{fragment}", own.join("&"))
+}
+
+/// The URL to shorten from the path below `/url/add/`. It's decoded when it was sent
+/// percent-encoded as a whole; one sent as it is, recognizable by its `://`, keeps
+/// escapes such as `%20` that belong to it.
+fn origin_url(uri: &Uri) -> Result<String, ApiError> {
+    let raw = uri.path().strip_prefix("/url/add/").unwrap_or_default();
+    if raw.contains("://") {
+        return Ok(raw.to_owned())
+    }
+    percent_decode_str(raw)
+        .decode_utf8()
+        .map(String::from)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "URL is not valid UTF-8".to_owned()).into())
+}
 
-    conn.interact(move |conn| {
-        let _ = diesel::insert_into(urls::table)
-            .values(new_url.clone())
-            .execute(conn);
+async fn add_url(
+    uri: Uri,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    creator: Creator,
+    headers: HeaderMap,
+    format: Format
+) -> Result<Negotiated<AddUrlResponse>, ApiError> {
+    let Query(options) = Query::<AddUrlOptions>::try_from_uri(&uri)
+        .map_err(|rejection| (rejection.status(), rejection.body_text()))?;
+    let created = links::create(&pool, &config, CreateLink {
+        url: with_own_query(origin_url(&uri)?, uri.query()),
+        alias: options.alias,
+        public_stats: options.public_stats,
+        publish_at: options.publish_at.map(|publish_at| publish_at.naive_utc()),
+        title: options.title,
+        notes: options.notes,
+        id_strategy: links::requested_strategy(&config, options.id_strategy)?,
+        idempotency_key: idempotency::key_from_headers(&headers)?,
+        actor: creator.actor,
+        ip: Some(creator.ip.to_string())
     })
-    .await
-    .map_err(internal_error)?;
+    .await?;
 
+    let gen_url = links::short_url(&config, &created.id);
     Ok(
-        Json(AddUrlResponse {
-            gen_url: format!("{}/{}", config.addr, _new_url.id),
-            origin_url
+        format.respond(AddUrlResponse {
+            qr: options.include_qr.map(|embedding| qr::embedded(&gen_url, embedding)).transpose()?,
+            gen_url,
+            origin_url: created.url
         })
     )
 }
 
+#[derive(Deserialize)]
+struct ShortenQuery {
+    url: String,
+    alias: Option<String>,
+    id_strategy: Option<String>
+}
+
+/// Plain-text variant of [`add_url`] for bookmarklets and shell scripts.
+async fn shorten_plain(
+    Query(query): Query<ShortenQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    creator: Creator,
+    headers: HeaderMap
+) -> Result<String, ApiError> {
+    let created = links::create(&pool, &config, CreateLink {
+        url: query.url,
+        alias: query.alias,
+        public_stats: false,
+        publish_at: None,
+        title: None,
+        notes: None,
+        id_strategy: links::requested_strategy(&config, query.id_strategy)?,
+        idempotency_key: idempotency::key_from_headers(&headers)?,
+        actor: creator.actor,
+        ip: Some(creator.ip.to_string())
+    })
+    .await?;
+
+    Ok(links::short_url(&config, &created.id))
+}
+
+#[derive(Deserialize)]
+struct FindUrlsQuery {
+    url: String
+}
+
+/// A live link found for a destination, without the details only admins see.
+#[derive(Serialize)]
+struct ExistingLink {
+    id: String,
+    short_url: String,
+    created_at: NaiveDateTime
+}
+
+/// The live links pointing at a destination, for reusing one instead of
+/// creating another. Disabled, quarantined and unpublished links aren't told
+/// about, as they're not for the public to find.
+async fn find_urls(
+    Query(query): Query<FindUrlsQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    format: Format
+) -> Result<Negotiated<Vec<ExistingLink>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let destination = links::normalize_url(&query.url);
+    let found: Vec<(String, NaiveDateTime)> = conn.interact(move |conn| {
+        let now = Utc::now().naive_utc();
+        urls::table
+            .filter(urls::url.eq(destination))
+            .filter(urls::disabled.eq(false))
+            .filter(urls::quarantined.eq(false))
+            .filter(urls::publish_at.is_null().or(urls::publish_at.le(now)))
+            .order(urls::created_at)
+            .select((urls::id, urls::created_at))
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let found = found.into_iter()
+        .map(|(id, created_at)| ExistingLink { short_url: links::short_url(&config, &id), id, created_at })
+        .collect();
+    Ok(format.respond(found))
+}
+
 async fn redirect_to(
-    Path(id): Path<String>,
-    State(pool): State<sqlite::Pool>
-) -> Result<Redirect, (StatusCode, String)> {
-    let conn = pool.get()
+    uri: Uri,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    method: Method,
+    headers: HeaderMap,
+    format: Format
+) -> Result<impl IntoResponse, ApiError> {
+    let Ok(id) = percent_decode_str(uri.path().trim_start_matches('/')).decode_utf8() else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    };
+    let id = links::normalize_code(&config, id.into_owned());
+    let code = id.clone();
+    let locale = Locale::from_headers(&headers);
+    if !links::passes_checks(&config, &code) || alias::is_reserved_prefix(&config, &code) {
+        return Ok(error_pages::respond(&config, format, locale, Unavailable::NotFound, &code))
+    }
+    let conn = telemetry::checkout(&pool)
         .await
-        .map_err(internal_error)?;
-    conn.interact(|conn| {
-        let _ = urls::table
-            .filter(urls::id.eq(id))
-            .select(Url::as_select())
-            .get_result(conn);
+        .map_err(unavailable)?;
+    // HEAD requests come from uptime checkers and CDNs, not visitors.
+    let count_click = method != Method::HEAD;
+    let opt_out = if stats::opted_out(&headers) { config.tracking_opt_out } else { TrackingOptOut::Ignore };
+    let automated = stats::automated(&headers).filter(|_| count_click);
+    if let Some(automated) = automated {
+        counter!("automated_clicks_total", "kind" => automated.name()).increment(1);
+    }
+    let opt_out = match automated {
+        Some(_) if config.automated_clicks != TrackingOptOut::Ignore => config.automated_clicks,
+        _ => opt_out
+    };
+    let throttle_anomalous = config.throttle_anomalous_clicks;
+    let dedup_window = config.click_dedup_window;
+    let referrer = stats::referrer_host(&headers);
+    let user_agent = headers.get(header::USER_AGENT).and_then(|agent| agent.to_str().ok());
+    let visitor = stats::visitor_hash(&config, addr.ip(), user_agent);
+    let now = Utc::now().naive_utc();
+    // Sequential click ids would let anyone report conversions for every click.
+    let token = config.click_id_param.as_ref().map(|_| nanoid::nanoid!());
+    let redirect_quota = usage::configured(&config, Meter::Redirects);
+    let (url, presentation, click_id, archived, over_quota) = conn.interact(move |conn| {
+        let found = urls::table
+            .filter(urls::id.eq(&id))
+            .select((Url::as_select(), (interstitial::Presentation::as_select(), preview::OpenGraph::as_select())))
+            .get_result::<(Url, (interstitial::Presentation, preview::OpenGraph))>(conn)
+            .optional()?;
+        let (url, presentation) = found.unzip();
+        let mut click_id = None;
+        let live = |url: &&Url| !url.disabled && !url.quarantined && url.publish_at.is_none_or(|publish_at| publish_at <= now);
+        if let Some(url) = url.as_ref().filter(|url| count_click && live(url)) {
+            // Redirects count against the quota of whoever created the link.
+            if let Some(owner) = url.created_by.as_deref() {
+                if let Err(err) = usage::count(conn, owner, Meter::Redirects, redirect_quota)? {
+                    return Ok((None, None, None, false, Some(err)))
+                }
+            }
+            // Flagged links may be under attack; their clicks shouldn't fill the table.
+            let opt_out = match opt_out {
+                TrackingOptOut::Ignore if throttle_anomalous && url.anomaly.is_some() => TrackingOptOut::Aggregate,
+                opt_out => opt_out
+            };
+            match opt_out {
+                TrackingOptOut::Ignore if stats::is_repeat_click(dedup_window, &url.id, &visitor) => {}
+                TrackingOptOut::Ignore => {
+                    stats::record_click(conn, &url.id, referrer.as_deref(), Some(&visitor), token.as_deref())?;
+                    events::publish(Some(&url.id), EventKind::Click { referrer });
+                    click_id = token;
+                }
+                TrackingOptOut::Aggregate => {
+                    stats::record_untracked_click(conn, &url.id)?;
+                    events::publish(Some(&url.id), EventKind::Click { referrer: None });
+                }
+                TrackingOptOut::Skip => {}
+            }
+        }
+        // Only unknown codes pay for the lookup in the archive.
+        let archived = url.is_none() && cold::is_archived(conn, &id)?;
+        Ok::<_, diesel::result::Error>((url, presentation, click_id, archived, None))
     })
     .await
+    .map_err(internal_error)?
     .map_err(internal_error)?;
+    if let Some(err) = over_quota {
+        return Err(err)
+    }
 
-    Ok(Redirect::to(""))
+    match url {
+        // Until restored, archived links answer like disabled ones.
+        None if archived => Ok(
+            error_pages::respond(&config, format, locale, Unavailable::Disabled, &code)
+        ),
+        None => match bundles::page(&pool, config.clone(), code.clone()).await? {
+            Some(page) => Ok(page.into_response()),
+            None => Ok(
+                error_pages::respond(&config, format, locale, Unavailable::NotFound, &code)
+            )
+        },
+        Some(url) if url.disabled => Ok(
+            error_pages::respond(&config, format, locale, Unavailable::Disabled, &code)
+        ),
+        // Answering like an unknown code keeps spam from getting anywhere while it awaits review.
+        Some(url) if url.quarantined => Ok(
+            error_pages::respond(&config, format, locale, Unavailable::NotFound, &code)
+        ),
+        Some(Url { publish_at: Some(publish_at), .. }) if publish_at > now => Ok(
+            error_pages::respond(&config, format, locale, Unavailable::Pending { publish_at }, &code)
+        ),
+        Some(Url { url, broken_at: Some(_), archive_url, .. }) => Ok(
+            ([(header::CACHE_CONTROL, "no-store")], health::broken_page(locale, &url, archive_url.as_deref())).into_response()
+        ),
+        Some(url) => {
+            counter!("redirects_total", "actor" => telemetry::actor_label(config.metrics_max_actors, url.created_by.as_deref())).increment(1);
+            // Passing the click id on lets the destination report conversions for it.
+            let tagged = config.click_id_param
+                .as_deref()
+                .zip(click_id)
+                .and_then(|(param, click_id)| {
+                    let mut destination = url::Url::parse(&url.url).ok()?;
+                    destination.query_pairs_mut().append_pair(param, &click_id);
+                    Some(String::from(destination))
+                });
+            let destination = tagged.as_deref().unwrap_or(&url.url);
+            let crawler = preview::is_crawler(&headers);
+            let open_graph = presentation.as_ref().map(|(_, open_graph)| open_graph).filter(|open_graph| open_graph.is_set());
+            let headers = (
+                // A prefetch stored for later would stand in for the visit it precedes.
+                redirect_cache_headers(&config, tagged.is_none() && automated != Some(stats::Automated::Prefetch)),
+                config.referrer_policy.clone().map(|policy| [(header::REFERRER_POLICY, policy)]),
+                // Crawlers get a different answer for links with a custom preview.
+                open_graph.map(|_| [(header::VARY, "user-agent")])
+            );
+            let page = match (presentation.as_ref(), open_graph) {
+                (_, Some(open_graph)) if crawler && links::is_web_url(&url.url) => {
+                    Some(preview::page(&links::short_url(&config, &url.id), &url.url, open_graph))
+                }
+                (Some((presentation, _)), _) => interstitial::render(&config, locale, destination, presentation)?,
+                (None, _) => None
+            };
+            Ok(match page {
+                Some(page) => (headers, page).into_response(),
+                None => (config.redirect_status, headers, [(header::LOCATION, destination)]).into_response()
+            })
+        }
+    }
+}
+
+/// Redirects tagged with a click id are unique to one click and must not be reused.
+fn redirect_cache_headers(config: &AppConfig, cacheable: bool) -> [(HeaderName, String); 2] {
+    if !cacheable {
+        return [
+            (header::CACHE_CONTROL, "no-store".into()),
+            (header::EXPIRES, "0".into())
+        ]
+    }
+    let max_age = config.redirect_max_age;
+    let expires = Utc::now() + max_age;
+    [
+        (header::CACHE_CONTROL, format!("{}, max-age={}", config.redirect_cache_scope, max_age.as_secs())),
+        (header::EXPIRES, expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+    ]
 }
 
 #[derive(Serialize)]
 struct AddUrlResponse {
     gen_url: String,
-    origin_url: String
-}
\ No newline at end of file
+    origin_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qr: Option<String>
+}
+
+impl Representation for AddUrlResponse {
+    fn to_text(&self) -> String {
+        self.gen_url.clone()
+    }
+
+    fn to_html(&self) -> String {
+        format!(
+            "<p><code>{}</code> → {}</p>",
+            html::escape(&self.gen_url),
+            html::link(&self.origin_url)
+        )
+    }
+}
+
+impl Representation for Vec<ExistingLink> {
+    fn to_text(&self) -> String {
+        self.iter()
+            .map(|link| format!("{} {}\n", link.id, link.short_url))
+            .collect()
+    }
+
+    fn to_html(&self) -> String {
+        let items: String = self.iter()
+            .map(|link| format!("<li><code>{}</code> {}</li>", html::escape(&link.id), html::link(&link.short_url)))
+            .collect();
+        format!("<ul>{items}</ul>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_destinations_own_query() {
+        let url = |origin_url: &str, query| with_own_query(origin_url.into(), query);
+        assert_eq!(url("https://example.com/", None), "https://example.com/");
+        assert_eq!(url("https://example.com/s", Some("q=rust&alias=rs&page=2")), "https://example.com/s?q=rust&page=2");
+        assert_eq!(url("https://example.com/s?q=rust", Some("page=2")), "https://example.com/s?q=rust&page=2");
+        assert_eq!(url("https://example.com/s#top", Some("q=a%26b")), "https://example.com/s?q=a%26b#top");
+        assert_eq!(url("https://example.com/s", Some("title=Docs&public_stats=true")), "https://example.com/s");
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    async_trait, extract::FromRequestParts, http::{header, request::Parts, HeaderValue}, response::{Html, IntoResponse, Response}, Json
+};
+use serde::Serialize;
+
+/// A response body that can be rendered in every format the API negotiates.
+pub trait Representation: Serialize {
+    fn to_text(&self) -> String;
+
+    /// An HTML fragment meant to be embedded in another page, not a full document.
+    fn to_html(&self) -> String;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Json,
+    Text,
+    Html
+}
+
+impl Format {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Format::Json),
+            "text/plain" => Some(Format::Text),
+            "text/html" | "text/*" => Some(Format::Html),
+            _ => None
+        }
+    }
+
+    /// Picks the best supported format from an `Accept` header, falling back to JSON.
+    pub fn from_accept(accept: &str) -> Self {
+        let mut best = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let Some(format) = Format::from_media_type(&media_type) else {
+                continue
+            };
+            if quality <= 0.0 {
+                continue
+            }
+            // Concrete types beat wildcards of the same quality; otherwise earlier entries win.
+            let rank = (quality, !media_type.ends_with('*'));
+            if best.is_none_or(|(_, best_rank)| rank > best_rank) {
+                best = Some((format, rank));
+            }
+        }
+        best.map_or(Format::Json, |(format, _)| format)
+    }
+
+    pub fn respond<T: Representation>(self, value: T) -> Negotiated<T> {
+        Negotiated { format: self, value }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .map_or(Format::Json, Format::from_accept))
+    }
+}
+
+pub struct Negotiated<T> {
+    format: Format,
+    value: T
+}
+
+impl<T: Representation> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let mut response = match self.format {
+            Format::Json => Json(self.value).into_response(),
+            Format::Text => self.value.to_text().into_response(),
+            Format::Html => Html(self.value.to_html()).into_response()
+        };
+        response.headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concrete_types_are_picked() {
+        assert_eq!(Format::from_accept("text/plain"), Format::Text);
+        assert_eq!(Format::from_accept("text/html,application/xhtml+xml"), Format::Html);
+        assert_eq!(Format::from_accept("application/json"), Format::Json);
+    }
+
+    #[test]
+    fn quality_decides_between_types() {
+        assert_eq!(Format::from_accept("text/html;q=0.5, text/plain"), Format::Text);
+        assert_eq!(Format::from_accept("text/plain; q=0.2, application/json; q=0.9"), Format::Json);
+        assert_eq!(Format::from_accept("text/plain;q=0, text/html;q=0.1"), Format::Html);
+    }
+
+    #[test]
+    fn concrete_types_beat_wildcards_of_the_same_quality() {
+        assert_eq!(Format::from_accept("*/*, text/plain"), Format::Text);
+        assert_eq!(Format::from_accept("text/*;q=0.8, text/plain;q=0.8"), Format::Text);
+    }
+
+    #[test]
+    fn falls_back_to_json() {
+        assert_eq!(Format::from_accept(""), Format::Json);
+        assert_eq!(Format::from_accept("image/png"), Format::Json);
+        assert_eq!(Format::from_accept("text/plain;q=0"), Format::Json);
+    }
+}
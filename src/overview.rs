@@ -0,0 +1,111 @@
+use axum::{
+    extract::State, Json
+};
+use chrono::{Duration, NaiveDate, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::{BigInt, Text, Timestamp}
+};
+use serde::Serialize;
+
+use crate::anomaly::{self, FlaggedLink};
+use crate::database;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::schema::urls;
+use crate::stats;
+use crate::telemetry;
+
+const OVERVIEW_DAYS: i64 = 30;
+
+#[derive(Serialize, QueryableByName)]
+pub struct DailyLinks {
+    #[diesel(sql_type = Text)]
+    pub day: String,
+    #[diesel(sql_type = BigInt)]
+    pub links: i64
+}
+
+#[derive(Serialize)]
+pub struct PoolHealth {
+    max_size: usize,
+    size: usize,
+    available: usize,
+    waiting: usize,
+    acquire_ms: u128
+}
+
+#[derive(Serialize)]
+pub struct Overview {
+    total_links: i64,
+    total_clicks: i64,
+    links_per_day: Vec<DailyLinks>,
+    database_bytes: i64,
+    pool: PoolHealth,
+    /// Links whose clicks currently look abnormal.
+    anomalies: Vec<FlaggedLink>
+}
+
+/// Links created per UTC day from `since` onwards, one entry for every day including empty ones.
+fn daily_links(conn: &mut SqliteConnection, since: NaiveDate) -> QueryResult<Vec<DailyLinks>> {
+    let counted: Vec<DailyLinks> = diesel::sql_query(
+        "SELECT date(created_at) AS day, COUNT(*) AS links FROM urls \
+         WHERE created_at >= ? GROUP BY day ORDER BY day"
+    )
+    .bind::<Timestamp, _>(since.and_time(Default::default()))
+    .load(conn)?;
+
+    let today = Utc::now().date_naive();
+    let mut counted = counted.into_iter().peekable();
+    Ok(since.iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| {
+            let day = day.to_string();
+            let links = counted.next_if(|counted| counted.day == day)
+                .map_or(0, |counted| counted.links);
+            DailyLinks { day, links }
+        })
+        .collect())
+}
+
+/// The numbers an operator checks first: sizes, recent growth and whether the pool keeps up.
+pub async fn instance_overview(
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Overview>, ApiError> {
+    let status = pool.status();
+    let requested = std::time::Instant::now();
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let acquire_ms = requested.elapsed().as_millis();
+
+    let (total_links, total_clicks, links_per_day, database_bytes, anomalies) = conn.interact(|conn| {
+        let total_links = urls::table.count().get_result::<i64>(conn)?;
+        let untracked_clicks = urls::table
+            .select(diesel::dsl::sum(urls::untracked_clicks))
+            .get_result::<Option<i64>>(conn)?;
+        let total_clicks = stats::all_tracked_clicks(conn)? + untracked_clicks.unwrap_or(0);
+        let since = Utc::now().date_naive() - Duration::days(OVERVIEW_DAYS - 1);
+        let links_per_day = daily_links(conn, since)?;
+        let database_bytes = database::size(conn)?;
+        let anomalies = anomaly::flagged_links(conn)?;
+        Ok::<_, diesel::result::Error>((total_links, total_clicks, links_per_day, database_bytes, anomalies))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(Overview {
+        total_links,
+        total_clicks,
+        links_per_day,
+        database_bytes,
+        pool: PoolHealth {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+            waiting: status.waiting,
+            acquire_ms
+        },
+        anomalies
+    }))
+}
@@ -0,0 +1,156 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State}, http::Method, middleware::Next, response::Response, Extension
+};
+use deadpool_diesel::sqlite;
+
+use crate::auth::{self, Actor};
+use crate::AppConfig;
+use crate::errors::ApiError;
+
+/// Who may use a route.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Public,
+    /// Anyone with a valid API token, whatever its scopes, or `ADMIN_TOKEN`.
+    Key,
+    /// `ADMIN_TOKEN`, or an API token with the `admin` scope, or `read` for `GET`.
+    Admin
+}
+
+impl Access {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "public" => Some(Access::Public),
+            "key" => Some(Access::Key),
+            "admin" => Some(Access::Admin),
+            _ => None
+        }
+    }
+}
+
+/// Routes as axum spells them, in every API version, where `*` stands for any
+/// run of characters. Routes not listed are public.
+const DEFAULT_RULES: [(&str, Access); 10] = [
+    ("*/admin/*", Access::Admin),
+    ("*/compare", Access::Admin),
+    ("*/me/*", Access::Key),
+    ("*/urls/bulk-*", Access::Admin),
+    ("*/urls/:id/history", Access::Admin),
+    ("*/qr/batch", Access::Admin),
+    ("*/ws", Access::Admin),
+    ("/feed.xml", Access::Admin),
+    ("/v4/bitlinks/*bitlink", Access::Admin),
+    ("/rest/:version/*", Access::Admin)
+];
+
+/// What single-segment codes are routed as; vanity paths, which no route
+/// matches, are looked up under it too.
+const REDIRECT_ROUTE: &str = "/:id";
+
+/// `ROUTE_POLICY` rules, which are checked in order before the built-in ones.
+pub struct RoutePolicy(Vec<(String, Access)>);
+
+impl RoutePolicy {
+    /// Parses comma-separated `route=access` rules, e.g.
+    /// `/:id/stats=public,/url/add/*=key`.
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        value.unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let (route, access) = rule.split_once('=')
+                    .ok_or_else(|| format!("ROUTE_POLICY rule {rule} lacks an access level"))?;
+                let access = Access::parse(access.trim())
+                    .ok_or_else(|| format!("Unknown access level in ROUTE_POLICY rule {rule}; expected public, key or admin"))?;
+                Ok((route.trim().to_owned(), access))
+            })
+            .collect::<Result<_, String>>()
+            .map(RoutePolicy)
+    }
+
+    fn access(&self, route: &str) -> Access {
+        self.0.iter()
+            .map(|(pattern, access)| (pattern.as_str(), *access))
+            .chain(DEFAULT_RULES)
+            .find(|(pattern, _)| matches(pattern, route))
+            .map_or(Access::Public, |(_, access)| access)
+    }
+}
+
+/// Whether `route` fits `pattern`, in which each `*` matches any characters.
+fn matches(pattern: &str, route: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = route.strip_prefix(first) else {
+        return false
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty()
+    };
+    for part in parts {
+        let Some(index) = rest.find(part) else {
+            return false
+        };
+        rest = &rest[index + part.len()..];
+    }
+    rest.ends_with(last)
+}
+
+/// Enforces the access level of the route each request was routed to, as set
+/// by `ROUTE_POLICY` and the built-in rules. Preflight requests carry no
+/// credentials, so `OPTIONS` is always let through.
+pub async fn enforce(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next
+) -> Result<Response, ApiError> {
+    let route = req.extensions()
+        .get::<MatchedPath>()
+        .map_or(REDIRECT_ROUTE, MatchedPath::as_str);
+    let access = config.route_policy.access(route);
+    // The body can't be held across the token lookup.
+    let (mut parts, body) = req.into_parts();
+    let actor = match access {
+        _ if parts.method == Method::OPTIONS => Actor::default(),
+        Access::Public => Actor::default(),
+        Access::Key => auth::check_key(&pool, &config, addr.ip(), &parts).await?,
+        Access::Admin => auth::check_admin(&pool, &config, addr.ip(), &parts).await?
+    };
+    // Handlers record changes under it in the audit log.
+    parts.extensions.insert(actor);
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_routes_with_wildcards() {
+        assert!(matches("*/admin/*", "/api/v1/admin/urls/:id"));
+        assert!(matches("*/admin/*", "/api/admin/overview"));
+        assert!(!matches("*/admin/*", "/:id/stats"));
+        assert!(matches("/:id/stats", "/:id/stats"));
+        assert!(!matches("/:id", "/:id/stats"));
+        assert!(matches("/url/add/*", "/url/add/*origin_url"));
+        assert!(matches("*/urls/bulk-*", "/api/v1/urls/bulk-delete"));
+    }
+
+    #[test]
+    fn configured_rules_come_first() {
+        let policy = RoutePolicy::parse(Some("/:id/stats=key, /api/v1/admin/metrics=public")).unwrap();
+        assert_eq!(policy.access("/:id/stats"), Access::Key);
+        assert_eq!(policy.access("/api/v1/admin/metrics"), Access::Public);
+        assert_eq!(policy.access("/api/v1/admin/overview"), Access::Admin);
+        assert_eq!(policy.access("/api/urls/:id/history"), Access::Admin);
+        assert_eq!(policy.access("/"), Access::Public);
+        assert!(RoutePolicy::parse(Some("/:id/stats=private")).is_err());
+        assert!(RoutePolicy::parse(Some("/:id/stats")).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::{header, HeaderMap, StatusCode}, response::Html, Extension, Json
+};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html;
+use crate::links;
+use crate::schema::urls;
+use crate::telemetry;
+use crate::AppConfig;
+
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+/// User agents of the bots that fetch a link to render its preview card in chats
+/// and social feeds, matched case-insensitively.
+const CRAWLERS: [&str; 12] = [
+    "facebookexternalhit",
+    "facebot",
+    "twitterbot",
+    "linkedinbot",
+    "slackbot",
+    "discordbot",
+    "telegrambot",
+    "whatsapp",
+    "skypeuripreview",
+    "pinterest",
+    "redditbot",
+    "mastodon"
+];
+
+/// The preview a link's owner chose to show on social sites instead of the
+/// destination's own metadata.
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = urls)]
+pub struct OpenGraph {
+    og_title: Option<String>,
+    og_description: Option<String>,
+    og_image: Option<String>
+}
+
+impl OpenGraph {
+    pub fn is_set(&self) -> bool {
+        self.og_title.is_some() || self.og_description.is_some() || self.og_image.is_some()
+    }
+}
+
+pub fn is_crawler(headers: &HeaderMap) -> bool {
+    let user_agent = headers.get(header::USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    CRAWLERS.iter().any(|crawler| user_agent.contains(crawler))
+}
+
+/// A page carrying the link's Open Graph tags, which also forwards anyone who
+/// isn't a crawler to `destination`.
+pub fn page(short_url: &str, destination: &str, open_graph: &OpenGraph) -> Html<String> {
+    let meta = |property: &str, content: &str| {
+        format!("<meta property=\"{property}\" content=\"{}\">\n", html::escape(content))
+    };
+    let title = open_graph.og_title.as_deref().unwrap_or(destination);
+    let mut head = meta("og:type", "website");
+    head.push_str(&meta("og:url", short_url));
+    head.push_str(&meta("og:title", title));
+    if let Some(description) = &open_graph.og_description {
+        head.push_str(&meta("og:description", description));
+    }
+    if let Some(image) = &open_graph.og_image {
+        head.push_str(&meta("og:image", image));
+        head.push_str(&meta("twitter:card", "summary_large_image"));
+    }
+    let escaped = html::escape(destination);
+    head.push_str(&format!("<meta http-equiv=\"refresh\" content=\"0; url={escaped}\">"));
+    let body = format!("<p><a href=\"{escaped}\">{escaped}</a></p>");
+    Html(html::page_with_head("en", title, &head, &body))
+}
+
+#[derive(Deserialize)]
+pub struct OpenGraphRequest {
+    title: Option<String>,
+    description: Option<String>,
+    image: Option<String>
+}
+
+/// Trims a value, treating blank ones as absent.
+fn text(value: Option<String>, field: &str, max_length: usize) -> Result<Option<String>, ApiError> {
+    let Some(value) = value.map(|value| value.trim().to_owned()).filter(|value| !value.is_empty()) else {
+        return Ok(None)
+    };
+    if value.chars().count() > max_length {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("{field} is longer than {max_length} characters")).into()
+        )
+    }
+    Ok(Some(value))
+}
+
+/// Replaces a link's preview. Fields left out or `null` fall back to the
+/// destination's own metadata; with none set, crawlers are redirected too.
+pub async fn set_open_graph(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<OpenGraphRequest>
+) -> Result<StatusCode, ApiError> {
+    let title = text(request.title, "Title", MAX_TITLE_LENGTH)?;
+    let description = text(request.description, "Description", MAX_DESCRIPTION_LENGTH)?;
+    let image = text(request.image, "Image", config.max_url_length)?;
+    if image.as_deref().is_some_and(|image| !links::is_web_url(image)) {
+        return Err(
+            (StatusCode::BAD_REQUEST, "Image must be an http or https URL".into()).into()
+        )
+    }
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set((urls::og_title.eq(&title), urls::og_description.eq(&description), urls::og_image.eq(&image)))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "set_open_graph",
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_preview_crawlers() {
+        let with_agent = |agent: &'static str| HeaderMap::from_iter([(header::USER_AGENT, agent.parse().unwrap())]);
+        assert!(is_crawler(&with_agent("facebookexternalhit/1.1 (+http://www.facebook.com/externalhit_uatext.php)")));
+        assert!(is_crawler(&with_agent("Mozilla/5.0 (compatible; Discordbot/2.0; +https://discordapp.com)")));
+        assert!(!is_crawler(&with_agent("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0")));
+        assert!(!is_crawler(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn escapes_overrides() {
+        let open_graph = OpenGraph {
+            og_title: Some("\"><script>".into()),
+            og_description: None,
+            og_image: Some("https://cdn.example/card.png".into())
+        };
+        let Html(page) = page("https://sho.rt/abc", "https://example.com/", &open_graph);
+        assert!(!page.contains("\"><script>"));
+        assert!(page.contains("<meta property=\"og:image\" content=\"https://cdn.example/card.png\">"));
+        assert!(!page.contains("og:description"));
+    }
+}
@@ -0,0 +1,311 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Query, State}, http::StatusCode, Json
+};
+use deadpool_diesel::sqlite;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditEntry, NewAuditEntry};
+use crate::auth::{Actor, ADMIN_ACTOR};
+use crate::cold;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::history;
+use crate::links;
+use crate::schema::{audit_log, clicks, urls};
+use crate::telemetry;
+use crate::usage;
+use crate::Url;
+
+/// Chunk size for `IN (...)` lists, comfortably below SQLite's bind parameter limit.
+const CHUNK_SIZE: usize = 500;
+
+/// Whose data a request is about: an integration actor such as `slack:U123`, or
+/// the IP address anonymous links were created from.
+pub struct Subject {
+    actor: Option<String>,
+    ip: Option<String>
+}
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = clicks)]
+pub struct ClickRecord {
+    url_id: String,
+    clicked_at: NaiveDateTime,
+    referrer: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct SubjectExport {
+    links: Vec<Url>,
+    /// Links archived for going unclicked, as they were stored.
+    archived_links: Vec<serde_json::Value>,
+    clicks: Vec<ClickRecord>,
+    audit_log: Vec<AuditEntry>
+}
+
+#[derive(Serialize)]
+pub struct ErasureResult {
+    links_deleted: usize,
+    audit_entries_anonymized: usize
+}
+
+impl Subject {
+    pub fn new(actor: Option<String>, ip: Option<String>) -> Result<Self, String> {
+        if actor.is_some() == ip.is_some() {
+            return Err("Exactly one of actor and ip must be given".into())
+        }
+        Ok(Subject { actor, ip })
+    }
+
+    fn audit_entries(&self) -> audit_log::BoxedQuery<'_, diesel::sqlite::Sqlite> {
+        match (&self.actor, &self.ip) {
+            (Some(actor), _) => audit_log::table.filter(audit_log::actor.eq(actor)).into_boxed(),
+            (None, ip) => audit_log::table.filter(audit_log::ip.eq(ip)).into_boxed()
+        }
+    }
+
+    /// Codes of the links the subject created, as recorded in the audit log.
+    fn created_links(&self, conn: &mut SqliteConnection) -> QueryResult<Vec<String>> {
+        self.audit_entries()
+            .filter(audit_log::action.eq("create"))
+            .select(audit_log::target.assume_not_null())
+            .filter(audit_log::target.is_not_null())
+            .distinct()
+            .load(conn)
+    }
+}
+
+/// Everything stored about the subject: their links, the clicks on them and their audit trail.
+pub fn export(conn: &mut SqliteConnection, subject: &Subject) -> QueryResult<SubjectExport> {
+    let ids = subject.created_links(conn)?;
+    let mut export = SubjectExport {
+        links: Vec::new(),
+        archived_links: Vec::new(),
+        clicks: Vec::new(),
+        audit_log: subject.audit_entries()
+            .select(AuditEntry::as_select())
+            .order(audit_log::id)
+            .load(conn)?
+    };
+    for chunk in ids.chunks(CHUNK_SIZE) {
+        export.links.extend(urls::table
+            .filter(urls::id.eq_any(chunk))
+            .select(Url::as_select())
+            .load(conn)?);
+        export.archived_links.extend(cold::links(conn, chunk)?);
+        export.clicks.extend(clicks::table
+            .filter(clicks::url_id.eq_any(chunk))
+            .select(ClickRecord::as_select())
+            .order(clicks::clicked_at)
+            .load(conn)?);
+    }
+    Ok(export)
+}
+
+/// Strips the subject's identity and IP address from the audit log and their
+/// links, or deletes their links and click history when `delete_links` is set.
+/// The erasure is audited under `actor`.
+pub fn erase(
+    conn: &mut SqliteConnection,
+    subject: &Subject,
+    delete_links: bool,
+    actor: Option<&str>,
+    ip: Option<&str>
+) -> QueryResult<ErasureResult> {
+    conn.immediate_transaction(|conn| {
+        let mut links_deleted = 0;
+        let ids = subject.created_links(conn)?;
+        for chunk in ids.chunks(CHUNK_SIZE) {
+            if delete_links {
+                links_deleted += links::delete(conn, chunk)? + cold::delete(conn, chunk)?;
+                // Deleting a link writes an event, so its history goes after it.
+                history::delete(conn, chunk)?;
+            } else {
+                cold::anonymize(conn, chunk)?;
+                diesel::update(urls::table)
+                    .filter(urls::id.eq_any(chunk))
+                    .set((urls::created_by.eq(None::<String>), urls::creator_ip.eq(None::<String>)))
+                    .execute(conn)?;
+            }
+        }
+
+        let matching: Vec<i32> = subject.audit_entries()
+            .select(audit_log::id)
+            .load(conn)?;
+        let mut audit_entries_anonymized = 0;
+        for chunk in matching.chunks(CHUNK_SIZE) {
+            audit_entries_anonymized += diesel::update(audit_log::table)
+                .filter(audit_log::id.eq_any(chunk))
+                .set((audit_log::actor.eq(None::<String>), audit_log::ip.eq(None::<String>)))
+                .execute(conn)?;
+        }
+
+        if let Some(actor) = subject.actor.as_deref() {
+            usage::delete(conn, actor)?;
+        }
+
+        // The erasure itself is audited, without naming whose data it was.
+        audit::record(conn, NewAuditEntry {
+            actor,
+            action: if delete_links { "erase_subject_links" } else { "anonymize_subject" },
+            target: None,
+            ip
+        })?;
+
+        Ok(ErasureResult { links_deleted, audit_entries_anonymized })
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SubjectQuery {
+    actor: Option<String>,
+    ip: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct ErasureQuery {
+    actor: Option<String>,
+    ip: Option<String>,
+    #[serde(default)]
+    delete_links: bool
+}
+
+pub async fn export_subject(
+    Query(query): Query<SubjectQuery>,
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<SubjectExport>, ApiError> {
+    let subject = Subject::new(query.actor, query.ip)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let export = conn.interact(move |conn| export(conn, &subject))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    Ok(Json(export))
+}
+
+pub async fn erase_subject(
+    Query(query): Query<ErasureQuery>,
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<Json<ErasureResult>, ApiError> {
+    let subject = Subject::new(query.actor, query.ip)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let result = conn.interact(move |conn| {
+        erase(conn, &subject, query.delete_links, actor.name(), Some(&addr.ip().to_string()))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(result))
+}
+
+/// `shorturl privacy export|erase --actor <actor> | --ip <ip> [--delete-links]`,
+/// for handling requests without exposing the admin API.
+pub async fn run(pool: sqlite::Pool, args: &[String]) -> Result<(), String> {
+    let usage = "Usage: privacy export|erase --actor <actor> | --ip <ip> [--delete-links]";
+    let (command, flags) = args.split_first().ok_or(usage)?;
+
+    let (mut actor, mut ip, mut delete_links) = (None, None, false);
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--actor" => actor = flags.next().cloned(),
+            "--ip" => ip = flags.next().cloned(),
+            "--delete-links" => delete_links = true,
+            _ => return Err(usage.into())
+        }
+    }
+    let subject = Subject::new(actor, ip)?;
+    let exporting = match command.as_str() {
+        "export" => true,
+        "erase" => false,
+        _ => return Err(usage.into())
+    };
+
+    let conn = pool.get()
+        .await
+        .map_err(|err| err.to_string())?;
+    let json = conn.interact(move |conn| {
+        let json = if exporting {
+            serde_json::to_string_pretty(&export(conn, &subject)?)
+        } else {
+            serde_json::to_string_pretty(&erase(conn, &subject, delete_links, Some(ADMIN_ACTOR), None)?)
+        };
+        Ok::<_, diesel::result::Error>(json.expect("plain structs serialize"))
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+    println!("{json}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use crate::schema::{conversions, link_events};
+
+    /// A link created by `ip` or `actor`, with one click that converted.
+    fn create_link(conn: &mut SqliteConnection, id: &str, actor: Option<&str>, ip: &str) {
+        diesel::insert_into(urls::table)
+            .values((urls::id.eq(id), urls::url.eq("https://example.com/"), urls::created_by.eq(actor), urls::creator_ip.eq(ip)))
+            .execute(conn)
+            .unwrap();
+        audit::record(conn, NewAuditEntry { actor, action: "create", target: Some(id), ip: Some(ip) }).unwrap();
+        diesel::insert_into(clicks::table)
+            .values(clicks::url_id.eq(id))
+            .execute(conn)
+            .unwrap();
+        let click: i32 = clicks::table.filter(clicks::url_id.eq(id)).select(clicks::id).first(conn).unwrap();
+        diesel::insert_into(conversions::table)
+            .values((conversions::click_id.eq(click), conversions::url_id.eq(id), conversions::event.eq("signup")))
+            .execute(conn)
+            .unwrap();
+    }
+
+    fn count_rows(conn: &mut SqliteConnection, id: &str) -> (i64, i64, i64, i64) {
+        (
+            urls::table.filter(urls::id.eq(id)).count().get_result(conn).unwrap(),
+            clicks::table.filter(clicks::url_id.eq(id)).count().get_result(conn).unwrap(),
+            conversions::table.filter(conversions::url_id.eq(id)).count().get_result(conn).unwrap(),
+            link_events::table.filter(link_events::url_id.eq(id)).count().get_result(conn).unwrap()
+        )
+    }
+
+    #[test]
+    fn erasing_deletes_only_the_subjects_data() {
+        let mut conn = database::test_database();
+        create_link(&mut conn, "theirs", None, "203.0.113.7");
+        create_link(&mut conn, "other", Some("slack:U2"), "198.51.100.2");
+
+        let subject = Subject::new(None, Some("203.0.113.7".into())).unwrap();
+        let erased = erase(&mut conn, &subject, true, Some(ADMIN_ACTOR), None).unwrap();
+        assert_eq!(erased.links_deleted, 1);
+        assert_eq!(erased.audit_entries_anonymized, 1);
+
+        assert_eq!(count_rows(&mut conn, "theirs"), (0, 0, 0, 0));
+        assert_eq!(count_rows(&mut conn, "other"), (1, 1, 1, 1));
+        let remaining: Vec<(Option<String>, Option<String>)> = audit_log::table
+            .filter(audit_log::action.eq("create"))
+            .order(audit_log::id)
+            .select((audit_log::actor, audit_log::ip))
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(remaining, [(None, None), (Some("slack:U2".into()), Some("198.51.100.2".into()))]);
+    }
+}
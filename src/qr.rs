@@ -0,0 +1,204 @@
+use std::{
+    io::{Cursor, Write}, sync::Arc
+};
+
+use axum::{
+    extract::State, http::{header, StatusCode}, response::IntoResponse, Extension, Json
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use qrcode::{render::svg, Color, QrCode};
+use serde::Deserialize;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::urls;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// The most codes one batch may render.
+const MAX_IDS: usize = 500;
+
+/// Pixels per module of PNG codes, which print sharply at a few centimetres.
+const PNG_SCALE: usize = 8;
+
+/// Blank modules around a code, which scanners need to find it.
+const QUIET_ZONE: usize = 4;
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg
+}
+
+impl QrFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            QrFormat::Png => "png",
+            QrFormat::Svg => "svg"
+        }
+    }
+}
+
+/// How [`embedded`] spells a QR code, e.g. in the creation response.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Embedding {
+    Svg,
+    PngBase64
+}
+
+fn encode(data: &str) -> Result<QrCode, ApiError> {
+    QrCode::new(data.as_bytes())
+        .map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, format!("Can't encode {data} as a QR code: {err}")).into())
+}
+
+pub fn svg(data: &str) -> Result<String, ApiError> {
+    Ok(encode(data)?
+        .render::<svg::Color>()
+        .quiet_zone(true)
+        .min_dimensions(256, 256)
+        .build())
+}
+
+pub fn png(data: &str) -> Result<Vec<u8>, ApiError> {
+    let code = encode(data)?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let side = (modules + 2 * QUIET_ZONE) * PNG_SCALE;
+    let mut pixels = vec![u8::MAX; side * side];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue
+        }
+        let (x, y) = ((index % modules + QUIET_ZONE) * PNG_SCALE, (index / modules + QUIET_ZONE) * PNG_SCALE);
+        for row in y..y + PNG_SCALE {
+            pixels[row * side + x..row * side + x + PNG_SCALE].fill(0);
+        }
+    }
+
+    let mut image = Vec::new();
+    let side = u32::try_from(side).map_err(internal_error)?;
+    let mut encoder = png::Encoder::new(&mut image, side, side);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(internal_error)?;
+    Ok(image)
+}
+
+/// The QR code of `data` as a string that fits in JSON: SVG markup, or a PNG
+/// encoded in base64.
+pub fn embedded(data: &str, embedding: Embedding) -> Result<String, ApiError> {
+    match embedding {
+        Embedding::Svg => svg(data),
+        Embedding::PngBase64 => Ok(STANDARD.encode(png(data)?))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    /// Links can't be tagged or grouped yet, so codes are picked by id.
+    ids: Vec<String>,
+    #[serde(default)]
+    format: QrFormat
+}
+
+/// A ZIP of QR codes for the short URLs of `ids`, one file per code named after
+/// it, e.g. `launch.png`, for print workflows. Fails if any id is unknown, so a
+/// batch is never printed with codes missing.
+pub async fn batch(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    Json(request): Json<BatchRequest>
+) -> Result<impl IntoResponse, ApiError> {
+    if request.ids.is_empty() {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, "No ids given".into()).into()
+        )
+    }
+    if request.ids.len() > MAX_IDS {
+        return Err(
+            (StatusCode::PAYLOAD_TOO_LARGE, format!("At most {MAX_IDS} codes can be rendered at once")).into()
+        )
+    }
+    let mut ids: Vec<String> = request.ids.into_iter().map(|id| links::normalize_code(&config, id)).collect();
+    ids.sort();
+    ids.dedup();
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let wanted = ids.clone();
+    let found: Vec<String> = conn.interact(move |conn| {
+        urls::table
+            .filter(urls::id.eq_any(wanted))
+            .select(urls::id)
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let missing: Vec<&str> = ids.iter()
+        .filter(|id| !found.contains(id))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(
+            (StatusCode::NOT_FOUND, format!("Short URLs not found: {}", missing.join(", "))).into()
+        )
+    }
+
+    let format = request.format;
+    // Rendering hundreds of codes takes a moment; it shouldn't hold up other requests.
+    let archive = tokio::task::spawn_blocking(move || {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for id in &ids {
+            let short_url = links::short_url(&config, id);
+            let image = match format {
+                QrFormat::Png => png(&short_url)?,
+                QrFormat::Svg => svg(&short_url)?.into_bytes()
+            };
+            // Vanity paths become folders, e.g. `go/product/launch.png`.
+            zip.start_file(format!("{id}.{}", format.extension()), SimpleFileOptions::default())
+                .map_err(internal_error)?;
+            zip.write_all(&image).map_err(internal_error)?;
+        }
+        zip.finish()
+            .map(Cursor::into_inner)
+            .map_err(internal_error)
+    })
+    .await
+    .map_err(internal_error)??;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"qr-codes.zip\"")
+        ],
+        archive
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_scannable_sizes() {
+        let image = png("https://sho.rt/abc").unwrap();
+        assert!(image.starts_with(b"\x89PNG"));
+        let code = QrCode::new(b"https://sho.rt/abc").unwrap();
+        let side = (code.width() + 2 * QUIET_ZONE) * PNG_SCALE;
+        let decoder = png::Decoder::new(image.as_slice());
+        let info = decoder.read_info().unwrap().info().clone();
+        assert_eq!(info.width as usize, side);
+        assert!(svg("https://sho.rt/abc").unwrap().starts_with("<?xml"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Request, http::{header, HeaderMap, Method, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Extension
+};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::{BigInt, Text}
+};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::AppConfig;
+
+const ACTOR: &str = "rebase";
+
+/// The columns that may hold absolute URLs on the instance itself, such as
+/// links to other short links or to a link's own screenshot.
+const COLUMNS: [(&str, &str); 4] = [
+    ("urls", "url"),
+    ("urls", "og_image"),
+    ("bundle_links", "url"),
+    ("bundle_links", "icon")
+];
+
+/// Matches values of `column` that are the old base, bound first, or start with
+/// it followed by a path, query or fragment, so `https://sho.rt` doesn't match
+/// `https://sho.rt.example`.
+fn matches(column: &str) -> String {
+    format!("(`{column}` = ?1 OR substr(`{column}`, 1, length(?1) + 1) IN (?1 || '/', ?1 || '?', ?1 || '#'))")
+}
+
+#[derive(QueryableByName)]
+struct Matched {
+    #[diesel(sql_type = BigInt)]
+    count: i64
+}
+
+/// Rewrites the URLs stored with base `old` to base `new` in every column of
+/// [`COLUMNS`], or only counts them on a dry run, returning the count per column.
+fn rewrite(conn: &mut SqliteConnection, old: &str, new: &str, dry_run: bool) -> QueryResult<Vec<(String, i64)>> {
+    conn.transaction(|conn| {
+        let mut rewritten = Vec::new();
+        for (table, column) in COLUMNS {
+            let matches = matches(column);
+            let count = if dry_run {
+                diesel::sql_query(format!("SELECT COUNT(*) AS count FROM `{table}` WHERE {matches}"))
+                    .bind::<Text, _>(old)
+                    .get_result::<Matched>(conn)?
+                    .count
+            } else {
+                diesel::sql_query(format!("UPDATE `{table}` SET `{column}` = ?2 || substr(`{column}`, length(?1) + 1) WHERE {matches}"))
+                    .bind::<Text, _>(old)
+                    .bind::<Text, _>(new)
+                    .execute(conn)? as i64
+            };
+            rewritten.push((format!("{table}.{column}"), count));
+        }
+        if !dry_run {
+            audit::record(conn, NewAuditEntry {
+                actor: Some(ACTOR),
+                action: "rebase",
+                target: Some(&format!("{old} -> {new}")),
+                ip: None
+            })?;
+        }
+        Ok(rewritten)
+    })
+}
+
+/// Runs `shorturl rebase <old-base> <new-base> [--dry-run]`, which rewrites the
+/// stored URLs pointing at the instance's old address when it moves. QR codes,
+/// exports and the short URLs the API answers with are built from `PUBLIC_ADDR`
+/// when requested, so they follow once it's changed.
+pub async fn run(pool: sqlite::Pool, args: &[String]) -> Result<(), String> {
+    let usage = "Usage: rebase <old-base> <new-base> [--dry-run], e.g. rebase https://old.example https://sho.rt";
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let bases: Vec<&String> = args.iter().filter(|arg| *arg != "--dry-run").collect();
+    let [old, new] = bases[..] else {
+        return Err(usage.into())
+    };
+    for base in [old, new] {
+        let parsed = url::Url::parse(base).map_err(|_| format!("{base} isn't an absolute URL; {usage}"))?;
+        if parsed.host_str().is_none() || base.ends_with('/') {
+            return Err(format!("{base} must be a scheme and host without a trailing slash; {usage}"))
+        }
+    }
+
+    let (old, new) = (old.clone(), new.clone());
+    let conn = pool.get()
+        .await
+        .map_err(|err| err.to_string())?;
+    let rewritten = conn.interact(move |conn| rewrite(conn, &old, &new, dry_run))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    for (column, count) in rewritten {
+        println!("{column}: {count} {}", if dry_run { "to rewrite" } else { "rewritten" });
+    }
+    Ok(())
+}
+
+/// The host a request was sent to, lowercased and without a port.
+pub fn request_host(headers: &HeaderMap) -> Option<String> {
+    let host = headers.get(header::HOST)?.to_str().ok()?;
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.is_empty() && port.bytes().all(|byte| byte.is_ascii_digit()) => name,
+        _ => host
+    };
+    Some(host.to_ascii_lowercase())
+}
+
+/// Answers requests for the hosts in `LEGACY_HOSTS`, which the instance was
+/// reachable at before it moved, with a permanent redirect to the same path at
+/// `PUBLIC_ADDR`, so short URLs handed out under the old domain keep working.
+/// Requests other than `GET` and `HEAD` get `308`, which keeps their method.
+pub async fn bridge(Extension(config): Extension<Arc<AppConfig>>, req: Request, next: Next) -> Response {
+    if config.legacy_hosts.is_empty() || !request_host(req.headers()).is_some_and(|host| config.legacy_hosts.contains(&host)) {
+        return next.run(req).await
+    }
+    // Without a scheme in `PUBLIC_ADDR`, the client keeps the one it used.
+    let base = if config.addr.contains("://") { config.addr.clone() } else { format!("//{}", config.addr) };
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    let status = match *req.method() {
+        Method::GET | Method::HEAD => StatusCode::MOVED_PERMANENTLY,
+        _ => StatusCode::PERMANENT_REDIRECT
+    };
+    (status, [(header::LOCATION, format!("{}{path}", base.trim_end_matches('/')))]).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(value: &str) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, value.parse().unwrap());
+        request_host(&headers)
+    }
+
+    #[test]
+    fn reads_the_host_without_its_port() {
+        assert_eq!(host("Old.Example").as_deref(), Some("old.example"));
+        assert_eq!(host("old.example:8080").as_deref(), Some("old.example"));
+        assert_eq!(host("[::1]:3000").as_deref(), Some("[::1]"));
+        assert_eq!(host("[::1]").as_deref(), Some("[::1]"));
+    }
+}
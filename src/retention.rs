@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use chrono::{Days, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::Timestamp
+};
+use metrics::counter;
+
+use crate::config::LiveConfig;
+use crate::telemetry;
+
+/// How often finished days are rolled up and expired clicks are pruned. Little
+/// happens between two runs on the same day, so this only bounds how late after
+/// midnight a day gets rolled up.
+const INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts rolling up the clicks of each finished UTC day into `click_stats_daily`,
+/// which long-range stats read instead of the raw clicks, and then deleting raw
+/// clicks older than `CLICK_RETENTION_DAYS`, if set. Daily counts are kept forever.
+pub fn spawn(pool: sqlite::Pool, live: LiveConfig) {
+    tokio::spawn(async move {
+        loop {
+            let retention_days = live.load().click_retention_days;
+            match run(&pool, retention_days).await {
+                Ok(0) => {}
+                Ok(pruned) => counter!("clicks_pruned_total").increment(pruned as u64),
+                Err(err) => println!("Click retention failed: {err}")
+            }
+            tokio::time::sleep(INTERVAL).await;
+        }
+    });
+}
+
+/// Rolls up the days before today and returns how many raw clicks were pruned.
+async fn run(pool: &sqlite::Pool, retention_days: u32) -> Result<usize, String> {
+    let today = Utc::now().date_naive().and_time(Default::default());
+    let cutoff = (retention_days > 0)
+        .then(|| today.checked_sub_days(Days::new(retention_days.into())))
+        .flatten();
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    conn.interact(move |conn| conn.transaction(|conn| {
+        roll_up(conn, today)?;
+        match cutoff {
+            Some(cutoff) => prune(conn, cutoff),
+            None => Ok(0)
+        }
+    }))
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+/// Counts the clicks of each link on the days after the last one rolled up for
+/// it and before `until`, which is where stats start reading raw clicks again.
+/// A finished day gets no more clicks, so rows once written are final.
+pub fn roll_up(conn: &mut SqliteConnection, until: NaiveDateTime) -> QueryResult<usize> {
+    diesel::sql_query(
+        "INSERT OR IGNORE INTO click_stats_daily (url_id, day, clicks, visitors) \
+         SELECT url_id, date(clicked_at), COUNT(*), COUNT(DISTINCT visitor) FROM clicks \
+         WHERE clicked_at >= COALESCE((SELECT date(MAX(day), '+1 day') FROM click_stats_daily s WHERE s.url_id = clicks.url_id), '') AND clicked_at < ? \
+         GROUP BY url_id, date(clicked_at)"
+    )
+    .bind::<Timestamp, _>(until)
+    .execute(conn)
+}
+
+/// Deletes the raw clicks before `cutoff`, which are rolled up by then. Clicks
+/// with a conversion are kept, so the conversion still points at its click.
+fn prune(conn: &mut SqliteConnection, cutoff: NaiveDateTime) -> QueryResult<usize> {
+    diesel::sql_query(
+        "DELETE FROM clicks WHERE clicked_at < ? AND id NOT IN (SELECT click_id FROM conversions)"
+    )
+    .bind::<Timestamp, _>(cutoff)
+    .execute(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+    use crate::database;
+    use crate::schema::{clicks, urls};
+    use crate::stats;
+
+    fn click(conn: &mut SqliteConnection, url_id: &str, days_ago: u64, visitor: &str) {
+        let clicked_at = Utc::now().naive_utc().checked_sub_days(Days::new(days_ago)).unwrap();
+        diesel::insert_into(clicks::table)
+            .values((clicks::url_id.eq(url_id), clicks::clicked_at.eq(clicked_at), clicks::visitor.eq(visitor)))
+            .execute(conn)
+            .unwrap();
+    }
+
+    fn totals(conn: &mut SqliteConnection, url_id: &str) -> (Vec<(String, i64, i64)>, i64) {
+        let since = Utc::now().date_naive().checked_sub_days(Days::new(7)).unwrap();
+        let daily = stats::daily_clicks(conn, url_id, since, Tz::UTC)
+            .unwrap()
+            .into_iter()
+            .map(|day| (day.day, day.clicks, day.visitors))
+            .collect();
+        (daily, stats::total_clicks(conn, url_id).unwrap())
+    }
+
+    #[test]
+    fn pruning_rolled_up_clicks_keeps_the_totals() {
+        let mut conn = database::test_database();
+        for id in ["busy", "late"] {
+            diesel::insert_into(urls::table)
+                .values((urls::id.eq(id), urls::url.eq("https://example.com/")))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        click(&mut conn, "busy", 3, "v1");
+        click(&mut conn, "busy", 3, "v1");
+        click(&mut conn, "busy", 2, "v2");
+        click(&mut conn, "busy", 0, "v3");
+        let today = Utc::now().date_naive().and_time(Default::default());
+        roll_up(&mut conn, today).unwrap();
+
+        // Clicks landing on days before another link's last rolled-up day, as
+        // imports do, are still rolled up before they're pruned.
+        click(&mut conn, "late", 5, "v4");
+        click(&mut conn, "late", 4, "v4");
+        let before = (totals(&mut conn, "busy"), totals(&mut conn, "late"));
+        assert_eq!(before.0.1, 4);
+        assert_eq!(before.1.1, 2);
+
+        roll_up(&mut conn, today).unwrap();
+        assert_eq!(prune(&mut conn, today).unwrap(), 5);
+        let remaining: i64 = clicks::table.count().get_result(&mut conn).unwrap();
+        assert_eq!(remaining, 1);
+        assert_eq!((totals(&mut conn, "busy"), totals(&mut conn, "late")), before);
+    }
+}
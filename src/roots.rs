@@ -0,0 +1,105 @@
+use std::{fs, sync::Arc};
+
+use axum::{
+    http::{header, HeaderMap, StatusCode}, response::{Html, IntoResponse, Response}, Extension
+};
+
+use crate::error_pages::{self, Unavailable};
+use crate::home;
+use crate::i18n::Locale;
+use crate::negotiate::Format;
+use crate::rebase;
+use crate::AppConfig;
+
+/// What `/` answers with on a host.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Root {
+    /// The built-in page for shortening links.
+    Home,
+    NotFound,
+    /// A temporary redirect, e.g. to the main site.
+    Redirect(String),
+    /// A page of the operator's own, e.g. a marketing page, read when loaded.
+    Page(String)
+}
+
+impl Root {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "home" => Ok(Root::Home),
+            "404" => Ok(Root::NotFound),
+            _ if value.starts_with("https://") || value.starts_with("http://") => Ok(Root::Redirect(value.to_owned())),
+            _ => match value.strip_prefix("file:") {
+                Some(path) => fs::read_to_string(path)
+                    .map(Root::Page)
+                    .map_err(|err| format!("Can't read HOST_ROOTS page {path}: {err}")),
+                None => Err(format!("Unknown HOST_ROOTS behavior {value}; expected home, 404, a URL or file:<path>"))
+            }
+        }
+    }
+}
+
+/// `HOST_ROOTS` rules, by lowercased host, where `*` stands for every host
+/// without a rule of its own. Hosts no rule covers get the built-in page.
+pub struct HostRoots(Vec<(String, Root)>);
+
+impl HostRoots {
+    /// Parses comma-separated `host=behavior` rules, e.g.
+    /// `go.example=https://example.com,sho.rt=home,*=404`.
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        value.unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let (host, behavior) = rule.split_once('=')
+                    .ok_or_else(|| format!("HOST_ROOTS rule {rule} lacks a behavior"))?;
+                Ok((host.trim().to_ascii_lowercase(), Root::parse(behavior.trim())?))
+            })
+            .collect::<Result<_, String>>()
+            .map(HostRoots)
+    }
+
+    fn root(&self, host: Option<&str>) -> &Root {
+        let rule = |wanted: &str| self.0.iter().find(|(host, _)| host == wanted).map(|(_, root)| root);
+        host.and_then(rule)
+            .or_else(|| rule("*"))
+            .unwrap_or(&Root::Home)
+    }
+}
+
+/// Answers `/` the way `HOST_ROOTS` says for the host it was requested on.
+pub async fn root(
+    Extension(config): Extension<Arc<AppConfig>>,
+    headers: HeaderMap,
+    format: Format
+) -> Response {
+    let host = rebase::request_host(&headers);
+    match config.host_roots.root(host.as_deref()) {
+        Root::Home => home::homepage(Extension(config.clone())).await.into_response(),
+        Root::NotFound => error_pages::respond(&config, format, Locale::from_headers(&headers), Unavailable::NotFound, ""),
+        Root::Redirect(url) => (StatusCode::FOUND, [(header::LOCATION, url.clone())]).into_response(),
+        Root::Page(page) => Html(page.clone()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_rule_of_the_host_then_the_wildcard() {
+        let roots = HostRoots::parse(Some("Go.example=https://example.com, sho.rt=home,*=404")).unwrap();
+        assert_eq!(roots.root(Some("go.example")), &Root::Redirect("https://example.com".into()));
+        assert_eq!(roots.root(Some("sho.rt")), &Root::Home);
+        assert_eq!(roots.root(Some("other.example")), &Root::NotFound);
+        assert_eq!(roots.root(None), &Root::NotFound);
+        assert_eq!(HostRoots::parse(None).unwrap().root(Some("sho.rt")), &Root::Home);
+    }
+
+    #[test]
+    fn rejects_unknown_behaviors() {
+        assert!(HostRoots::parse(Some("sho.rt=landing")).is_err());
+        assert!(HostRoots::parse(Some("sho.rt")).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap, net::{IpAddr, SocketAddr}, sync::{Arc, LazyLock, Mutex}, time::{Duration, Instant}
+};
+
+use axum::{
+    extract::{ConnectInfo, Request}, http::{header, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Extension
+};
+use metrics::counter;
+use percent_encoding::percent_decode_str;
+
+use crate::events::{self, EventKind};
+use crate::security_log;
+use crate::AppConfig;
+
+/// Beyond this many tracked addresses, those whose window has passed are forgotten.
+const MAX_TRACKED: usize = 10_000;
+
+/// When each address's current window started and how many unknown codes it asked for since.
+static MISSES: LazyLock<Mutex<HashMap<IpAddr, (Instant, u32)>>> = LazyLock::new(Default::default);
+
+/// When each address last asked for a honeypot code.
+static SCANNERS: LazyLock<Mutex<HashMap<IpAddr, Instant>>> = LazyLock::new(Default::default);
+
+/// Marks a successful response that nonetheless revealed a code to be unused,
+/// such as an alias availability check answering `free`.
+#[derive(Clone, Copy)]
+pub struct Miss;
+
+/// Guards the short code namespace against enumeration: addresses that ask for
+/// too many unknown codes within a window are answered with `429 Too Many Requests`
+/// until it passes, those that missed before are slowed down more with each miss,
+/// and responses are padded to a minimum latency so hits and misses can't be told
+/// apart by timing. Wraps every route that looks up a code.
+pub async fn guard(
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next
+) -> Response {
+    let started = Instant::now();
+    let ip = addr.ip();
+    let path = req.uri().path().to_owned();
+
+    let blocked = scanner_blocked_for(&config, ip).or_else(|| blocked_for(&config, ip));
+    // Trusted networks are spared the block and the tarpit, but the misses are still logged.
+    let trusted = config.trusts(ip);
+    // The hit itself is answered like any unknown code, so the scanner can't tell.
+    if requests_honeypot(&config, &path) {
+        counter!("honeypot_hits_total").increment(1);
+        security_log::record(&config.security_log, "honeypot", ip, &path);
+        mark_scanner(&config, ip);
+    }
+    if blocked.is_some() && trusted {
+        counter!("trusted_network_exemptions_total", "limit" => "scan").increment(1);
+        security_log::record(&config.security_log, "exempted", ip, &path);
+    } else if let Some(retry_after) = blocked {
+        counter!("redirect_scan_blocked_total").increment(1);
+        security_log::record(&config.security_log, "rate_limited", ip, &path);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            "Too many unknown short URLs requested"
+        ).into_response()
+    }
+    let delay = if trusted { Duration::ZERO } else { tarpit_delay(&config, ip) };
+    if !delay.is_zero() {
+        counter!("redirect_scan_tarpitted_total").increment(1);
+        tokio::time::sleep(delay).await;
+    }
+
+    let response = next.run(req).await;
+    // Pending links answer 404 with `Retry-After`; their codes exist, so they aren't misses.
+    let not_found = response.status() == StatusCode::NOT_FOUND && !response.headers().contains_key(header::RETRY_AFTER);
+    if not_found || response.extensions().get::<Miss>().is_some() {
+        counter!("redirect_not_found_total").increment(1);
+        security_log::record(&config.security_log, "not_found", ip, &path);
+        record_miss(&config, ip);
+        events::publish(None, EventKind::NotFound { path, ip: ip.to_string() });
+    }
+
+    tokio::time::sleep_until((started + config.redirect_min_latency).into()).await;
+    response
+}
+
+/// Whether `code` is one of `HONEYPOT_CODES`, which are never issued.
+pub fn is_honeypot(config: &AppConfig, code: &str) -> bool {
+    config.honeypot_codes.iter().any(|honeypot| honeypot.eq_ignore_ascii_case(code))
+}
+
+/// Whether `path` is the redirect of a honeypot code.
+fn requests_honeypot(config: &AppConfig, path: &str) -> bool {
+    !config.honeypot_codes.is_empty()
+        && percent_decode_str(path.trim_start_matches('/'))
+            .decode_utf8()
+            .is_ok_and(|code| is_honeypot(config, &code))
+}
+
+fn mark_scanner(config: &AppConfig, ip: IpAddr) {
+    let mut scanners = SCANNERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if scanners.len() >= MAX_TRACKED {
+        scanners.retain(|_, marked| marked.elapsed() < config.honeypot_block);
+    }
+    if scanners.insert(ip, Instant::now()).is_none() {
+        counter!("honeypot_scanners_total").increment(1);
+    }
+}
+
+/// Seconds until `ip`, caught asking for a honeypot code, may look up codes again.
+fn scanner_blocked_for(config: &AppConfig, ip: IpAddr) -> Option<u64> {
+    let scanners = SCANNERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let elapsed = scanners.get(&ip)?.elapsed();
+    (elapsed < config.honeypot_block)
+        .then(|| (config.honeypot_block - elapsed).as_secs().max(1))
+}
+
+/// Whether misses need counting, for the limit or the tarpit.
+fn counts_misses(config: &AppConfig) -> bool {
+    config.scan_max_misses > 0 || !config.scan_tarpit_step.is_zero()
+}
+
+/// How many unknown codes `ip` asked for in the current window, and how long ago it started.
+fn misses_of(config: &AppConfig, ip: IpAddr) -> Option<(u32, Duration)> {
+    let misses = MISSES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (window_start, count) = *misses.get(&ip)?;
+    let elapsed = window_start.elapsed();
+    (elapsed < config.scan_window).then_some((count, elapsed))
+}
+
+/// Seconds until `ip` may look up codes again, if it's over the limit.
+fn blocked_for(config: &AppConfig, ip: IpAddr) -> Option<u64> {
+    if config.scan_max_misses == 0 {
+        return None
+    }
+    let (count, elapsed) = misses_of(config, ip)?;
+    (count >= config.scan_max_misses)
+        .then(|| (config.scan_window - elapsed).as_secs().max(1))
+}
+
+/// How long to hold up a lookup by `ip`: `SCAN_TARPIT_STEP_MS` for each unknown
+/// code it asked for in the current window, up to `SCAN_TARPIT_MAX_MS`. Clients
+/// that never miss aren't slowed down at all.
+fn tarpit_delay(config: &AppConfig, ip: IpAddr) -> Duration {
+    if config.scan_tarpit_step.is_zero() {
+        return Duration::ZERO
+    }
+    misses_of(config, ip).map_or(Duration::ZERO, |(count, _)| {
+        config.scan_tarpit_step.saturating_mul(count).min(config.scan_tarpit_max)
+    })
+}
+
+fn record_miss(config: &AppConfig, ip: IpAddr) {
+    if !counts_misses(config) {
+        return
+    }
+    let mut misses = MISSES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if misses.len() >= MAX_TRACKED {
+        misses.retain(|_, (window_start, _)| window_start.elapsed() < config.scan_window);
+    }
+    let entry = misses.entry(ip).or_insert((Instant::now(), 0));
+    if entry.0.elapsed() >= config.scan_window {
+        *entry = (Instant::now(), 0);
+    }
+    entry.1 += 1;
+    if entry.1 == config.scan_max_misses {
+        counter!("redirect_scanners_total").increment(1);
+    }
+}
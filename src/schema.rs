@@ -1,8 +1,216 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_tokens (id) {
+        id -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        scopes -> Text,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    archived_links (id) {
+        id -> Text,
+        link -> Text,
+        daily_stats -> Text,
+        last_clicked_at -> Nullable<Timestamp>,
+        archived_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Integer,
+        actor -> Nullable<Text>,
+        action -> Text,
+        target -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    bundle_links (id) {
+        id -> Integer,
+        bundle_id -> Text,
+        position -> Integer,
+        title -> Text,
+        url -> Text,
+        icon -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    bundles (id) {
+        id -> Text,
+        title -> Text,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    click_stats_daily (url_id, day) {
+        url_id -> Text,
+        day -> Date,
+        clicks -> BigInt,
+        visitors -> BigInt,
+    }
+}
+
+diesel::table! {
+    clicks (id) {
+        id -> Integer,
+        url_id -> Text,
+        clicked_at -> Timestamp,
+        referrer -> Nullable<Text>,
+        visitor -> Nullable<Text>,
+        token -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    code_sequence (id) {
+        id -> Integer,
+        last_value -> BigInt,
+    }
+}
+
+diesel::table! {
+    conversions (id) {
+        id -> Integer,
+        click_id -> Integer,
+        url_id -> Text,
+        event -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (scope, key) {
+        scope -> Text,
+        key -> Text,
+        request_url -> Text,
+        url_id -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    link_checks (id) {
+        id -> Integer,
+        url_id -> Text,
+        checked_at -> Timestamp,
+        status -> Nullable<Integer>,
+        failure -> Nullable<Text>,
+        redirects -> Integer,
+        duration_ms -> Integer,
+        latest -> Bool,
+        final_url -> Nullable<Text>,
+        downgraded -> Bool,
+    }
+}
+
+diesel::table! {
+    link_events (id) {
+        id -> Integer,
+        url_id -> Text,
+        event -> Text,
+        url -> Text,
+        title -> Nullable<Text>,
+        disabled -> Bool,
+        at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    settings (name) {
+        name -> Text,
+        value -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     urls (id) {
         id -> Text,
         url -> Text,
+        disabled -> Bool,
+        public_stats -> Bool,
+        created_at -> Timestamp,
+        untracked_clicks -> Integer,
+        pixels -> Nullable<Text>,
+        interstitial -> Bool,
+        redirect_mode -> Text,
+        publish_at -> Nullable<Timestamp>,
+        archive_url -> Nullable<Text>,
+        screenshot -> Nullable<Text>,
+        failed_checks -> Integer,
+        broken_at -> Nullable<Timestamp>,
+        spam_score -> Integer,
+        quarantined -> Bool,
+        updated_at -> Timestamp,
+        created_by -> Nullable<Text>,
+        creator_ip -> Nullable<Text>,
+        title -> Nullable<Text>,
+        notes -> Nullable<Text>,
+        og_title -> Nullable<Text>,
+        og_description -> Nullable<Text>,
+        og_image -> Nullable<Text>,
+        indexable -> Bool,
+        anomaly -> Nullable<Text>,
+        anomaly_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    usage (actor, period) {
+        actor -> Text,
+        period -> Text,
+        links_created -> BigInt,
+        redirects -> BigInt,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Integer,
+        url -> Text,
+        secret -> Text,
+        types -> Nullable<Text>,
+        created_at -> Timestamp,
     }
 }
+
+diesel::joinable!(bundle_links -> bundles (bundle_id));
+diesel::joinable!(click_stats_daily -> urls (url_id));
+diesel::joinable!(clicks -> urls (url_id));
+diesel::joinable!(conversions -> clicks (click_id));
+diesel::joinable!(conversions -> urls (url_id));
+diesel::joinable!(idempotency_keys -> urls (url_id));
+diesel::joinable!(link_checks -> urls (url_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    archived_links,
+    audit_log,
+    bundle_links,
+    bundles,
+    click_stats_daily,
+    clicks,
+    code_sequence,
+    conversions,
+    idempotency_keys,
+    link_checks,
+    link_events,
+    settings,
+    urls,
+    usage,
+    webhooks,
+);
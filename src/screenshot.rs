@@ -0,0 +1,189 @@
+use std::{
+    path::PathBuf, sync::{Arc, OnceLock}, time::{Duration, Instant}
+};
+
+use axum::{
+    extract::{Path, State}, http::{header, StatusCode}, response::IntoResponse, Extension
+};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use metrics::counter;
+use nanoid::nanoid;
+use tokio::sync::mpsc;
+
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::urls;
+use crate::ssrf;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// Destinations waiting to be captured; beyond this, new ones are skipped rather than queued.
+const QUEUE_SIZE: usize = 1000;
+
+/// Failures are counted in metrics and logged at most this often.
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rendering a page in a headless browser takes a while.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Thumbnails bigger than this are discarded.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Image types accepted from the provider, with the extension they're stored under.
+const IMAGE_TYPES: [(&str, &str); 3] = [("image/png", "png"), ("image/jpeg", "jpg"), ("image/webp", "webp")];
+
+struct Job {
+    id: String,
+    url: String
+}
+
+static QUEUE: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+/// Starts capturing new destinations one at a time through `provider`, a URL in
+/// which `{url}` is replaced with the percent-encoded destination, storing each
+/// thumbnail in `dir` and its file name on the link. Destinations on internal
+/// addresses are skipped unless `allow_private` is set, as the provider may be
+/// running inside the same network.
+pub fn spawn(pool: sqlite::Pool, provider: String, dir: PathBuf, allow_private: bool) {
+    let (sender, mut jobs) = mpsc::channel(QUEUE_SIZE);
+    if QUEUE.set(sender).is_err() {
+        return
+    }
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("HTTP client can be built");
+
+    tokio::spawn(async move {
+        let mut last_report: Option<Instant> = None;
+        let mut unreported = 0;
+        while let Some(job) = jobs.recv().await {
+            let captured = match capture(&client, &provider, &dir, &job.url, allow_private).await {
+                Ok(file) => store(&pool, job.id, file).await,
+                Err(err) => Err(format!("capturing {} failed: {err}", job.url))
+            };
+            match captured {
+                Ok(()) => counter!("screenshot_captures_total").increment(1),
+                Err(err) => {
+                    counter!("screenshot_failures_total").increment(1);
+                    unreported += 1;
+                    if last_report.is_none_or(|reported| reported.elapsed() >= REPORT_INTERVAL) {
+                        println!("Screenshot capture: {err} ({unreported} failures since the last report)");
+                        last_report = Some(Instant::now());
+                        unreported = 0;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Queues a new link's destination for capture, if screenshots are enabled.
+pub fn enqueue(id: &str, url: &str) {
+    let Some(queue) = QUEUE.get() else {
+        return
+    };
+    let job = Job { id: id.to_owned(), url: url.to_owned() };
+    if queue.try_send(job).is_err() {
+        counter!("screenshot_skipped_total").increment(1);
+    }
+}
+
+/// Fetches a thumbnail of `url` from the provider and writes it to `dir`, returning its file name.
+async fn capture(client: &reqwest::Client, provider: &str, dir: &std::path::Path, url: &str, allow_private: bool) -> Result<String, String> {
+    // The host may have been pointed at an internal address since the link was created.
+    if !allow_private {
+        ssrf::check(url).await?;
+    }
+    let encoded: String = url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+    let response = client.get(provider.replace("{url}", &encoded))
+        .send()
+        .await
+        .map_err(|err| err.to_string())?
+        .error_for_status()
+        .map_err(|err| err.to_string())?;
+
+    let content_type = response.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let Some((_, extension)) = IMAGE_TYPES.iter().find(|(image_type, _)| content_type.starts_with(image_type)) else {
+        return Err(format!("unsupported content type {content_type:?}"))
+    };
+    if response.content_length().is_some_and(|length| length > MAX_IMAGE_BYTES as u64) {
+        return Err("image is too large".into())
+    }
+    let image = response.bytes()
+        .await
+        .map_err(|err| err.to_string())?;
+    if image.len() > MAX_IMAGE_BYTES {
+        return Err("image is too large".into())
+    }
+
+    // Codes may contain any character of the id alphabet, so files get names of their own.
+    let file = format!("{}.{extension}", nanoid!());
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|err| format!("can't create {}: {err}", dir.display()))?;
+    tokio::fs::write(dir.join(&file), image)
+        .await
+        .map_err(|err| format!("can't write {file}: {err}"))?;
+    Ok(file)
+}
+
+async fn store(pool: &sqlite::Pool, id: String, file: String) -> Result<(), String> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| format!("can't store screenshot: {err}"))?;
+    conn.interact(move |conn| {
+        diesel::update(urls::table.find(id))
+            .set(urls::screenshot.eq(file))
+            .execute(conn)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+/// Serves the thumbnail captured of a link's destination.
+pub async fn link_screenshot(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<impl IntoResponse, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let file = conn.interact(move |conn| {
+        urls::table
+            .find(id)
+            .filter(urls::disabled.eq(false))
+            .select(urls::screenshot)
+            .get_result::<Option<String>>(conn)
+            .optional()
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?
+    .flatten();
+
+    let Some(file) = file else {
+        return Err(
+            (StatusCode::NOT_FOUND, "No screenshot for this short URL".into()).into()
+        )
+    };
+    let image = tokio::fs::read(config.screenshot_dir.join(&file))
+        .await
+        .map_err(internal_error)?;
+    let content_type = IMAGE_TYPES.iter()
+        .find(|(_, extension)| file.ends_with(extension))
+        .map_or("application/octet-stream", |(image_type, _)| image_type);
+    Ok((
+        [(header::CONTENT_TYPE, content_type), (header::CACHE_CONTROL, "public, max-age=86400")],
+        image
+    ))
+}
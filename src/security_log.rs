@@ -0,0 +1,69 @@
+use std::{
+    fs::OpenOptions, io::Write, net::IpAddr, path::PathBuf
+};
+
+use chrono::Utc;
+
+/// Where security events are written, for fail2ban or CrowdSec to act on.
+pub enum SecurityLog {
+    Off,
+    Stdout,
+    File(PathBuf),
+    /// The local syslog daemon, under the `auth` facility.
+    #[cfg(unix)]
+    Syslog
+}
+
+/// `auth` facility, `warning` severity.
+#[cfg(unix)]
+const SYSLOG_PRIORITY: u8 = 4 * 8 + 4;
+
+impl SecurityLog {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        Ok(match value {
+            None | Some("" | "off") => SecurityLog::Off,
+            Some("stdout") => SecurityLog::Stdout,
+            #[cfg(unix)]
+            Some("syslog") => SecurityLog::Syslog,
+            #[cfg(not(unix))]
+            Some("syslog") => return Err("SECURITY_LOG=syslog is only supported on Unix".into()),
+            Some(path) => SecurityLog::File(path.into())
+        })
+    }
+}
+
+/// Writes one line per event in a fixed `key=value` format:
+///
+/// ```text
+/// 2024-05-01T12:00:00Z shorturl-security event=auth_failure ip=203.0.113.7 path="/api/admin/overview"
+/// ```
+///
+/// `event` is one of `auth_failure`, `rate_limited`, `honeypot`, `not_found` or
+/// `exempted`, for a limit a trusted network was let past.
+/// Failing to write is reported on stdout but never fails the request.
+pub fn record(log: &SecurityLog, event: &str, ip: IpAddr, path: &str) {
+    let message = format!("shorturl-security event={event} ip={ip} path={path:?}");
+    let written = match log {
+        SecurityLog::Off => return,
+        SecurityLog::Stdout => {
+            println!("{} {message}", timestamp());
+            Ok(())
+        }
+        SecurityLog::File(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{} {message}", timestamp())),
+        #[cfg(unix)]
+        SecurityLog::Syslog => std::os::unix::net::UnixDatagram::unbound()
+            .and_then(|socket| socket.send_to(format!("<{SYSLOG_PRIORITY}>{message}").as_bytes(), "/dev/log"))
+            .map(|_| ())
+    };
+    if let Err(err) = written {
+        println!("Can't write security log: {err}");
+    }
+}
+
+fn timestamp() -> String {
+    Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
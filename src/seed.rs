@@ -0,0 +1,147 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::links;
+use crate::schema::{clicks, urls};
+use crate::AppConfig;
+
+/// Rows per transaction; small enough to stay under SQLite's bound parameter limit.
+const BATCH_SIZE: usize = 1000;
+
+const HOSTS: [&str; 8] = [
+    "example.com", "docs.example.org", "blog.example.net", "shop.example.com",
+    "news.example.org", "video.example.com", "wiki.example.net", "cdn.example.org"
+];
+const WORDS: [&str; 12] = [
+    "release", "notes", "guide", "pricing", "2026", "spring", "sale", "intro",
+    "setup", "faq", "changelog", "events"
+];
+
+struct SeedOptions {
+    links: usize,
+    clicks: usize,
+    days: i64
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = urls)]
+struct SeedUrl {
+    id: String,
+    url: String,
+    public_stats: bool,
+    created_at: NaiveDateTime
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = clicks)]
+struct SeedClick {
+    url_id: String,
+    clicked_at: NaiveDateTime
+}
+
+fn parse_count(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    value
+        .map(|value| value.replace('_', ""))
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{flag} expects a number"))
+}
+
+fn parse_options(args: &[String]) -> Result<SeedOptions, String> {
+    let mut options = SeedOptions { links: 10_000, clicks: 0, days: 90 };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--links" => options.links = parse_count(flag, args.next())?,
+            "--clicks-per-link" => options.clicks = parse_count(flag, args.next())?,
+            "--days" => options.days = parse_count(flag, args.next())?.max(1) as i64,
+            _ => return Err(
+                format!("Unknown seed option {flag}; expected --links, --clicks-per-link or --days")
+            )
+        }
+    }
+    Ok(options)
+}
+
+fn random_url(rng: &mut impl Rng) -> String {
+    let host = HOSTS.choose(rng).expect("hosts are not empty");
+    let path: Vec<&str> = (0..rng.gen_range(1..=3))
+        .map(|_| *WORDS.choose(rng).expect("words are not empty"))
+        .collect();
+    format!("https://{host}/{}?ref={}", path.join("-"), rng.gen_range(1..10_000))
+}
+
+fn random_time(rng: &mut impl Rng, since: NaiveDateTime, now: NaiveDateTime) -> NaiveDateTime {
+    let span = (now - since).num_seconds().max(1);
+    since + Duration::seconds(rng.gen_range(0..span))
+}
+
+/// Fills the database with synthetic links and click history, for load-testing
+/// redirects and stats queries at a realistic scale.
+pub async fn run(pool: sqlite::Pool, config: &AppConfig, args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let now = Utc::now().naive_utc();
+    let since = now - Duration::days(options.days);
+    let mut inserted = 0;
+
+    while inserted < options.links {
+        let (links, clicks) = {
+            let mut rng = rand::thread_rng();
+            let links: Vec<SeedUrl> = (0..BATCH_SIZE.min(options.links - inserted))
+                .map(|_| {
+                    let url = random_url(&mut rng);
+                    SeedUrl {
+                        id: links::generate_id(config, config.id_strategy, &url, 0),
+                        url,
+                        public_stats: rng.gen_bool(0.1),
+                        created_at: random_time(&mut rng, since, now)
+                    }
+                })
+                .collect();
+            // Click counts follow a rough power law averaging `clicks` per link: most links
+            // get little traffic, a few get most of it.
+            let clicks: Vec<SeedClick> = links.iter()
+                .flat_map(|link| {
+                    let count = if options.clicks == 0 {
+                        0
+                    } else {
+                        (options.clicks as f64 / rng.gen_range(0.05f64..1.0).powi(2) * 0.05) as usize
+                    };
+                    (0..count)
+                        .map(|_| SeedClick {
+                            url_id: link.id.clone(),
+                            clicked_at: random_time(&mut rng, link.created_at, now)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            (links, clicks)
+        };
+        let batch = links.len();
+
+        let conn = pool.get()
+            .await
+            .map_err(|err| err.to_string())?;
+        conn.interact(move |conn| {
+            conn.immediate_transaction::<_, diesel::result::Error, _>(|conn| {
+                diesel::insert_or_ignore_into(urls::table)
+                    .values(&links)
+                    .execute(conn)?;
+                for chunk in clicks.chunks(BATCH_SIZE) {
+                    diesel::insert_into(clicks::table)
+                        .values(chunk)
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+
+        inserted += batch;
+        println!("Seeded {inserted}/{} links", options.links);
+    }
+    Ok(())
+}
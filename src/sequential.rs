@@ -0,0 +1,25 @@
+use diesel::prelude::*;
+
+use crate::bundles;
+use crate::schema::code_sequence;
+use crate::snowflake::base62;
+
+/// The next number of the sequence in base 62, skipping codes that are taken,
+/// e.g. by an alias, or `reserved`. Must run in a transaction holding the write
+/// lock, so no one else is handed the same number.
+pub fn next_code(conn: &mut SqliteConnection, reserved: &[String]) -> QueryResult<String> {
+    let mut number: i64 = code_sequence::table
+        .select(code_sequence::last_value)
+        .get_result(conn)?;
+    let code = loop {
+        number += 1;
+        let code = base62(number as u64);
+        if !reserved.iter().any(|reserved| reserved.eq_ignore_ascii_case(&code)) && !bundles::code_taken(conn, &code)? {
+            break code
+        }
+    };
+    diesel::update(code_sequence::table)
+        .set(code_sequence::last_value.eq(number))
+        .execute(conn)?;
+    Ok(code)
+}
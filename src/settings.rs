@@ -0,0 +1,271 @@
+use std::{
+    collections::{BTreeMap, HashMap}, net::SocketAddr, sync::{Arc, LazyLock, Mutex}, time::Duration
+};
+
+use axum::{
+    extract::{ConnectInfo, State}, http::StatusCode, Extension, Json
+};
+use chrono::Utc;
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::config::{self, LiveConfig};
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::schema::settings;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// Settings that are safe to change on a running instance, with the environment
+/// variable each one overrides.
+const SETTINGS: [(&str, &str); 7] = [
+    ("maintenance_mode", "MAINTENANCE_MODE"),
+    ("redirect_status", "REDIRECT_STATUS"),
+    ("redirect_max_age_secs", "REDIRECT_MAX_AGE_SECS"),
+    ("scan_max_not_found", "SCAN_MAX_NOT_FOUND"),
+    ("scan_window_secs", "SCAN_WINDOW_SECS"),
+    ("blocked_domains", "BLOCKED_DOMAINS"),
+    ("trusted_networks", "TRUSTED_NETWORKS")
+];
+
+/// The stored overrides by setting name, already validated. Mirrors the `settings`
+/// table, so configuration reloads don't need the database.
+static OVERRIDES: LazyLock<Mutex<BTreeMap<String, String>>> = LazyLock::new(Default::default);
+
+/// Checks a new value for `name`, returning the spelling it's stored in.
+fn normalize(name: &str, value: &Value) -> Result<String, String> {
+    let invalid = || format!("Invalid value for {name}: {value}");
+    match name {
+        "maintenance_mode" => value.as_bool().map(|flag| flag.to_string()).ok_or_else(invalid),
+        "redirect_status" => value.as_u64()
+            .and_then(|code| u16::try_from(code).ok())
+            .filter(|&code| config::redirect_status(code).is_ok())
+            .map(|code| code.to_string())
+            .ok_or_else(invalid),
+        "redirect_max_age_secs" | "scan_window_secs" => value.as_u64().map(|secs| secs.to_string()).ok_or_else(invalid),
+        "scan_max_not_found" => value.as_u64()
+            .filter(|&count| u32::try_from(count).is_ok())
+            .map(|count| count.to_string())
+            .ok_or_else(invalid),
+        "blocked_domains" => value.as_array()
+            .and_then(|domains| domains.iter()
+                .map(|domain| domain.as_str().map(|domain| domain.trim().to_ascii_lowercase()))
+                .collect::<Option<Vec<String>>>())
+            .filter(|domains| domains.iter().all(|domain| !domain.is_empty() && !domain.contains(',')))
+            .map(|domains| domains.join(","))
+            .ok_or_else(invalid),
+        "trusted_networks" => value.as_array()
+            .and_then(|ranges| ranges.iter()
+                .map(|range| range.as_str().map(|range| range.trim().to_owned()))
+                .collect::<Option<Vec<String>>>())
+            .ok_or_else(invalid)
+            .and_then(|ranges| config::networks(&ranges).map_err(|err| format!("Invalid value for {name}: {err}")))
+            .map(|networks| networks.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")),
+        _ => Err(format!("Unknown setting {name}"))
+    }
+}
+
+/// Sets one validated override on `config`.
+fn apply(config: &mut AppConfig, name: &str, value: &str) {
+    match name {
+        "maintenance_mode" => config.maintenance_mode = value == "true",
+        "redirect_status" => {
+            if let Some(status) = value.parse().ok().and_then(|code| config::redirect_status(code).ok()) {
+                config.redirect_status = status;
+            }
+        }
+        "redirect_max_age_secs" => {
+            if let Ok(secs) = value.parse() {
+                config.redirect_max_age = Duration::from_secs(secs);
+            }
+        }
+        "scan_max_not_found" => {
+            if let Ok(count) = value.parse() {
+                config.scan_max_misses = count;
+            }
+        }
+        "scan_window_secs" => {
+            if let Ok(secs) = value.parse() {
+                config.scan_window = Duration::from_secs(secs);
+            }
+        }
+        "blocked_domains" => config.blocked_domains = value.split(',').filter(|domain| !domain.is_empty()).map(String::from).collect(),
+        "trusted_networks" => {
+            let ranges: Vec<String> = value.split(',').filter(|range| !range.is_empty()).map(String::from).collect();
+            if let Ok(networks) = config::networks(&ranges) {
+                config.trusted_networks = networks;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The value `name` is in effect with, as shown by the admin API.
+fn current(config: &AppConfig, name: &str) -> Value {
+    match name {
+        "maintenance_mode" => config.maintenance_mode.into(),
+        "redirect_status" => config.redirect_status.as_u16().into(),
+        "redirect_max_age_secs" => config.redirect_max_age.as_secs().into(),
+        "scan_max_not_found" => config.scan_max_misses.into(),
+        "scan_window_secs" => config.scan_window.as_secs().into(),
+        "blocked_domains" => config.blocked_domains.clone().into(),
+        "trusted_networks" => config.trusted_networks.iter().map(ToString::to_string).collect::<Vec<_>>().into(),
+        _ => Value::Null
+    }
+}
+
+/// Applies the stored overrides on top of a configuration read from the environment.
+pub fn overlay(mut config: AppConfig) -> AppConfig {
+    let overrides = OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (name, value) in overrides.iter() {
+        apply(&mut config, name, value);
+    }
+    config
+}
+
+/// Reads the stored overrides at startup. Unknown ones, e.g. left behind by a newer
+/// version, are ignored.
+pub async fn load(pool: &sqlite::Pool) -> Result<(), String> {
+    let conn = pool.get()
+        .await
+        .map_err(|err| format!("Can't load settings: {err}"))?;
+    let stored = conn.interact(|conn| {
+        settings::table
+            .select((settings::name, settings::value))
+            .load::<(String, String)>(conn)
+    })
+    .await
+    .map_err(|err| format!("Can't load settings: {err}"))?
+    .map_err(|err| format!("Can't load settings: {err}"))?;
+
+    let mut overrides = OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    overrides.clear();
+    overrides.extend(stored.into_iter().filter(|(name, _)| SETTINGS.iter().any(|(known, _)| known == name)));
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct Setting {
+    name: &'static str,
+    /// The value in effect.
+    value: Value,
+    /// Whether the value comes from the settings table rather than the environment.
+    overridden: bool,
+    env: &'static str
+}
+
+pub async fn list_settings(
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Json<Vec<Setting>> {
+    let overrides = OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    Json(
+        SETTINGS.iter()
+            .map(|&(name, env)| Setting {
+                name,
+                value: current(&config, name),
+                overridden: overrides.contains_key(name),
+                env
+            })
+            .collect()
+    )
+}
+
+/// Stores the given overrides and applies them right away, re-reading the
+/// environment like a reload does. `null` removes an override, so the
+/// environment's value applies again.
+pub async fn update_settings(
+    Extension(live): Extension<LiveConfig>,
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(changes): Json<HashMap<String, Value>>
+) -> Result<Json<Vec<Setting>>, ApiError> {
+    if changes.is_empty() {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, "No settings given".into()).into()
+        )
+    }
+    let mut changes: Vec<(String, Option<String>)> = changes.into_iter()
+        .map(|(name, value)| match value {
+            Value::Null if SETTINGS.iter().any(|(known, _)| *known == name) => Ok((name, None)),
+            value => normalize(&name, &value).map(|value| (name, Some(value)))
+        })
+        .collect::<Result<_, _>>()
+        .map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err))?;
+    changes.sort();
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let stored = changes.clone();
+    conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let now = Utc::now().naive_utc();
+            for (name, value) in &stored {
+                match value {
+                    Some(value) => diesel::insert_into(settings::table)
+                        .values((settings::name.eq(name), settings::value.eq(value), settings::updated_at.eq(now)))
+                        .on_conflict(settings::name)
+                        .do_update()
+                        .set((settings::value.eq(value), settings::updated_at.eq(now)))
+                        .execute(conn)?,
+                    None => diesel::delete(settings::table.find(name)).execute(conn)?
+                };
+            }
+            let names: Vec<&str> = stored.iter().map(|(name, _)| name.as_str()).collect();
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "update_settings",
+                target: Some(&names.join(",")),
+                ip: Some(&addr.ip().to_string())
+            })
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    {
+        let mut overrides = OVERRIDES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (name, value) in changes {
+            match value {
+                Some(value) => overrides.insert(name, value),
+                None => overrides.remove(&name)
+            };
+        }
+    }
+    config::reload(&live)
+        .map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, format!("Settings were saved, but the configuration can't be reloaded: {err}")))?;
+
+    Ok(list_settings(Extension(live.load_full())).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn normalizes_valid_values() {
+        assert_eq!(normalize("maintenance_mode", &json!(true)).as_deref(), Ok("true"));
+        assert_eq!(normalize("redirect_status", &json!(308)).as_deref(), Ok("308"));
+        assert_eq!(normalize("scan_window_secs", &json!(120)).as_deref(), Ok("120"));
+        assert_eq!(normalize("blocked_domains", &json!([" Evil.example", "spam.test"])).as_deref(), Ok("evil.example,spam.test"));
+        assert_eq!(normalize("blocked_domains", &json!([])).as_deref(), Ok(""));
+        assert_eq!(normalize("trusted_networks", &json!(["10.8.0.0/16", " 192.0.2.7", "2001:db8::/32"])).as_deref(), Ok("10.8.0.0/16,192.0.2.7/32,2001:db8::/32"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(normalize("maintenance_mode", &json!("yes")).is_err());
+        assert!(normalize("redirect_status", &json!(200)).is_err());
+        assert!(normalize("scan_max_not_found", &json!(-1)).is_err());
+        assert!(normalize("scan_max_not_found", &json!(u64::MAX)).is_err());
+        assert!(normalize("blocked_domains", &json!(["a,b"])).is_err());
+        assert!(normalize("trusted_networks", &json!(["10.8.0.0/33"])).is_err());
+        assert!(normalize("admin_token", &json!("x")).is_err());
+    }
+}
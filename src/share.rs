@@ -0,0 +1,135 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::StatusCode, Extension, Json
+};
+use chrono::{DateTime, Duration, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::urls;
+use crate::telemetry;
+use crate::AppConfig;
+
+const DEFAULT_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Preview links can't be revoked one by one, so they don't live long.
+const MAX_TTL_SECS: i64 = 90 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+pub struct PreviewRequest {
+    expires_in_secs: Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct PreviewLink {
+    preview_url: String,
+    expires_at: DateTime<Utc>
+}
+
+fn mac(key: &str, id: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(id.as_bytes());
+    mac.update(b":");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+/// A token granting a look at the stats of link `id` until the Unix time `expires`.
+fn token(key: &str, id: &str, expires: i64) -> String {
+    format!("{expires}.{}", hex::encode(mac(key, id, expires).finalize().into_bytes()))
+}
+
+/// Whether `token` was issued for link `id` under `key` and hasn't expired.
+pub fn verify(key: &str, id: &str, token: &str, now: DateTime<Utc>) -> bool {
+    let Some((expires, signature)) = token.split_once('.') else {
+        return false
+    };
+    let (Ok(expires), Ok(signature)) = (expires.parse::<i64>(), hex::decode(signature)) else {
+        return false
+    };
+    expires > now.timestamp() && mac(key, id, expires).verify_slice(&signature).is_ok()
+}
+
+/// Issues a URL showing the destination and stats of a link whose stats aren't
+/// public, for sharing them without credentials. It works until it expires or
+/// `PREVIEW_SIGNING_KEY` changes, which invalidates every preview link at once.
+pub async fn create_preview_link(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<PreviewRequest>
+) -> Result<(StatusCode, Json<PreviewLink>), ApiError> {
+    let Some(key) = config.preview_signing_key.clone() else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Preview links are not configured".into()).into()
+        )
+    };
+    let ttl = request.expires_in_secs.unwrap_or(DEFAULT_TTL_SECS);
+    if !(1..=MAX_TTL_SECS).contains(&ttl) {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("expires_in_secs must be between 1 and {MAX_TTL_SECS}")).into()
+        )
+    }
+    let expires_at = Utc::now() + Duration::seconds(ttl);
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let lookup = id.clone();
+    let exists = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let exists = diesel::select(diesel::dsl::exists(urls::table.find(&lookup))).get_result::<bool>(conn)?;
+            if exists {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "create_preview_link",
+                    target: Some(&lookup),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(exists)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if !exists {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    let token = token(&key, &id, expires_at.timestamp());
+    let preview_url = format!("{}/stats?token={token}", links::short_url(&config, &id));
+    Ok((StatusCode::CREATED, Json(PreviewLink { preview_url, expires_at })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_tokens_for_their_link_until_they_expire() {
+        let now = Utc::now();
+        let token = token("key", "abc", now.timestamp() + 60);
+        assert!(verify("key", "abc", &token, now));
+        assert!(!verify("key", "abd", &token, now));
+        assert!(!verify("other key", "abc", &token, now));
+        assert!(!verify("key", "abc", &token, now + Duration::seconds(61)));
+        let extended = token.replacen(&(now.timestamp() + 60).to_string(), &(now.timestamp() + 600).to_string(), 1);
+        assert!(!verify("key", "abc", &extended, now));
+        assert!(!verify("key", "abc", "garbage", now));
+    }
+}
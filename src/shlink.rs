@@ -0,0 +1,501 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, Request, State}, http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next, response::{IntoResponse, Response}, routing::get, Extension, Json, Router
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tower_http::cors::{Any, CorsLayer};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::{Actor, Creator};
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::idempotency;
+use crate::links::{self, CreateLink};
+use crate::schema::{clicks, urls};
+use crate::stats;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// The header Shlink clients send their API key in.
+const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+const DEFAULT_ITEMS_PER_PAGE: i64 = 10;
+const MAX_ITEMS_PER_PAGE: i64 = 1000;
+
+/// Shlink's REST API (`/rest/v{1,2,3}/...`), for its web client and mobile apps.
+/// API keys are this instance's admin or API tokens.
+pub fn router() -> Router<sqlite::Pool> {
+    Router::new()
+        .route("/rest/:version/short-urls", get(list_short_urls).post(create_short_url))
+        .route("/rest/:version/short-urls/:code", get(get_short_url).patch(edit_short_url).delete(delete_short_url))
+        .route("/rest/:version/short-urls/:code/visits", get(short_url_visits))
+        .route("/rest/health", get(health))
+        // The web client is a single-page app served from its own origin.
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+                .allow_headers([header::CONTENT_TYPE, API_KEY_HEADER])
+        )
+}
+
+/// Passes an `X-Api-Key` sent to the Shlink API on as the bearer token the rest
+/// of the service expects, before the route policy looks for one.
+pub async fn api_key(mut req: Request, next: Next) -> Response {
+    if req.uri().path().starts_with("/rest/") && !req.headers().contains_key(header::AUTHORIZATION) {
+        let bearer = req.headers()
+            .get(&API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .and_then(|key| HeaderValue::from_str(&format!("Bearer {key}")).ok());
+        if let Some(bearer) = bearer {
+            req.headers_mut().insert(header::AUTHORIZATION, bearer);
+        }
+    }
+    next.run(req).await
+}
+
+/// Errors as the RFC 7807 problem details Shlink answers with.
+pub struct ShlinkError(StatusCode, String);
+
+impl From<(StatusCode, String)> for ShlinkError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        ShlinkError(status, message)
+    }
+}
+
+impl From<ApiError> for ShlinkError {
+    fn from(err: ApiError) -> Self {
+        ShlinkError(err.status, err.message)
+    }
+}
+
+impl IntoResponse for ShlinkError {
+    fn into_response(self) -> Response {
+        let ShlinkError(status, detail) = self;
+        let kind = match status {
+            StatusCode::NOT_FOUND => "short-url-not-found",
+            StatusCode::CONFLICT => "non-unique-slug",
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "invalid-api-key",
+            status if status.is_client_error() => "invalid-data",
+            _ => "internal-server-error"
+        };
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(json!({
+                "type": format!("https://shlink.io/api/error/{kind}"),
+                "title": status.canonical_reason().unwrap_or("Error"),
+                "detail": detail,
+                "status": status.as_u16()
+            })).to_string()
+        ).into_response()
+    }
+}
+
+fn not_found() -> ShlinkError {
+    ShlinkError(StatusCode::NOT_FOUND, "No short URL found with that short code".into())
+}
+
+pub async fn health() -> Json<serde_json::Value> {
+    Json(json!({ "status": "pass", "version": env!("CARGO_PKG_VERSION") }))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Pagination {
+    current_page: i64,
+    pages_count: i64,
+    items_per_page: i64,
+    items_in_current_page: usize,
+    total_items: i64
+}
+
+impl Pagination {
+    fn new(page: i64, items_per_page: i64, items_in_current_page: usize, total_items: i64) -> Self {
+        Pagination {
+            current_page: page,
+            pages_count: (total_items + items_per_page - 1) / items_per_page,
+            items_per_page,
+            items_in_current_page,
+            total_items
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageQuery {
+    page: Option<i64>,
+    items_per_page: Option<i64>,
+    search_term: Option<String>
+}
+
+impl PageQuery {
+    /// The page asked for and its size. Shlink's `-1` lists everything, up to the cap here.
+    fn page(&self) -> (i64, i64) {
+        let items_per_page = match self.items_per_page.unwrap_or(DEFAULT_ITEMS_PER_PAGE) {
+            size if size < 1 => MAX_ITEMS_PER_PAGE,
+            size => size.min(MAX_ITEMS_PER_PAGE)
+        };
+        (self.page.unwrap_or(1).max(1), items_per_page)
+    }
+}
+
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = urls)]
+struct Link {
+    id: String,
+    url: String,
+    title: Option<String>,
+    created_at: NaiveDateTime,
+    publish_at: Option<NaiveDateTime>,
+    indexable: bool
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortUrl {
+    short_code: String,
+    short_url: String,
+    long_url: String,
+    date_created: String,
+    visits_count: i64,
+    visits_summary: serde_json::Value,
+    tags: Vec<String>,
+    meta: serde_json::Value,
+    domain: Option<String>,
+    title: Option<String>,
+    crawlable: bool,
+    forward_query: bool
+}
+
+fn timestamp(at: NaiveDateTime) -> String {
+    at.and_utc().to_rfc3339()
+}
+
+fn short_url(config: &AppConfig, link: Link, visits: i64) -> ShortUrl {
+    ShortUrl {
+        short_url: links::short_url(config, &link.id),
+        short_code: link.id,
+        long_url: link.url,
+        date_created: timestamp(link.created_at),
+        visits_count: visits,
+        visits_summary: json!({ "total": visits, "nonBots": visits, "bots": 0 }),
+        tags: Vec::new(),
+        meta: json!({ "validSince": link.publish_at.map(timestamp), "validUntil": null, "maxVisits": null }),
+        domain: None,
+        title: link.title,
+        crawlable: link.indexable,
+        forward_query: false
+    }
+}
+
+fn load(conn: &mut SqliteConnection, id: &str) -> QueryResult<Option<(Link, i64)>> {
+    let Some(link) = urls::table
+        .find(id)
+        .select(Link::as_select())
+        .get_result(conn)
+        .optional()? else {
+        return Ok(None)
+    };
+    let visits = stats::total_clicks(conn, id)?;
+    Ok(Some((link, visits)))
+}
+
+pub async fn list_short_urls(
+    Query(query): Query<PageQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<serde_json::Value>, ShlinkError> {
+    let (page, items_per_page) = query.page();
+    let search_term = query.search_term.unwrap_or_default();
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let (found, total) = conn.interact(move |conn| {
+        let matching = || {
+            let mut statement = urls::table.into_boxed();
+            if let Some(search) = links::search(&search_term) {
+                statement = statement.filter(search);
+            }
+            statement
+        };
+        let total: i64 = matching().count().get_result(conn)?;
+        let found = matching()
+            .select(Link::as_select())
+            .order((urls::created_at.desc(), urls::id.desc()))
+            .limit(items_per_page)
+            .offset((page - 1).saturating_mul(items_per_page))
+            .load::<Link>(conn)?
+            .into_iter()
+            .map(|link| stats::total_clicks(conn, &link.id).map(|visits| (link, visits)))
+            .collect::<QueryResult<Vec<_>>>()?;
+        Ok::<_, diesel::result::Error>((found, total))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let pagination = Pagination::new(page, items_per_page, found.len(), total);
+    let data: Vec<ShortUrl> = found.into_iter()
+        .map(|(link, visits)| short_url(&config, link, visits))
+        .collect();
+    Ok(Json(json!({ "shortUrls": { "data": data, "pagination": pagination } })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateShortUrl {
+    long_url: String,
+    custom_slug: Option<String>,
+    title: Option<String>,
+    valid_since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    crawlable: bool
+}
+
+pub async fn create_short_url(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    creator: Creator,
+    headers: HeaderMap,
+    Json(request): Json<CreateShortUrl>
+) -> Result<Json<ShortUrl>, ShlinkError> {
+    let created = links::create(&pool, &config, CreateLink {
+        url: request.long_url,
+        alias: request.custom_slug,
+        public_stats: false,
+        publish_at: request.valid_since.map(|valid_since| valid_since.naive_utc()),
+        title: request.title,
+        notes: None,
+        id_strategy: None,
+        idempotency_key: idempotency::key_from_headers(&headers)?,
+        actor: creator.actor,
+        ip: Some(creator.ip.to_string())
+    })
+    .await?;
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let crawlable = request.crawlable;
+    let found = conn.interact(move |conn| {
+        if crawlable {
+            diesel::update(urls::table.find(&created.id))
+                .set(urls::indexable.eq(true))
+                .execute(conn)?;
+        }
+        load(conn, &created.id)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let (link, visits) = found.ok_or_else(not_found)?;
+    Ok(Json(short_url(&config, link, visits)))
+}
+
+pub async fn get_short_url(
+    Path((_, code)): Path<(String, String)>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<ShortUrl>, ShlinkError> {
+    let id = links::normalize_code(&config, code);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let found = conn.interact(move |conn| load(conn, &id))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    let (link, visits) = found.ok_or_else(not_found)?;
+    Ok(Json(short_url(&config, link, visits)))
+}
+
+/// The fields of `PATCH /short-urls/{code}` that can be changed here. Destinations
+/// are fixed once created, so `longUrl` may only repeat the current one, as the
+/// web client's edit form does.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditShortUrl {
+    long_url: Option<String>,
+    #[serde(default, deserialize_with = "links::present")]
+    title: Option<Option<String>>,
+    #[serde(default, deserialize_with = "links::present")]
+    valid_since: Option<Option<DateTime<Utc>>>,
+    crawlable: Option<bool>
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = urls)]
+struct LinkChanges {
+    title: Option<Option<String>>,
+    publish_at: Option<Option<NaiveDateTime>>,
+    indexable: Option<bool>
+}
+
+pub async fn edit_short_url(
+    Path((_, code)): Path<(String, String)>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<EditShortUrl>
+) -> Result<Json<ShortUrl>, ShlinkError> {
+    let changes = LinkChanges {
+        title: request.title
+            .map(|title| links::annotation(title, "Title", links::MAX_TITLE_LENGTH))
+            .transpose()?,
+        publish_at: request.valid_since.map(|valid_since| valid_since.map(|valid_since| valid_since.naive_utc())),
+        indexable: request.crawlable
+    };
+    let long_url = request.long_url.map(|long_url| links::normalize_url(&long_url));
+    let id = links::normalize_code(&config, code);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let edited = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let Some((link, _)) = load(conn, &id)? else {
+                return Ok(Err(not_found()))
+            };
+            if long_url.is_some_and(|long_url| long_url != link.url) {
+                return Ok(Err(ShlinkError(StatusCode::UNPROCESSABLE_ENTITY, "The long URL of a short URL can't be changed".into())))
+            }
+            if changes.title.is_some() || changes.publish_at.is_some() || changes.indexable.is_some() {
+                diesel::update(urls::table.find(&id))
+                    .set(&changes)
+                    .execute(conn)?;
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "update",
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            load(conn, &id).map(|found| found.ok_or_else(not_found))
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let (link, visits) = edited?;
+    Ok(Json(short_url(&config, link, visits)))
+}
+
+pub async fn delete_short_url(
+    Path((_, code)): Path<(String, String)>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ShlinkError> {
+    let id = links::normalize_code(&config, code);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let deleted = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let deleted = links::delete(conn, std::slice::from_ref(&id))?;
+            if deleted > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "delete",
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(deleted)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if deleted == 0 {
+        return Err(not_found())
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = clicks)]
+struct Visit {
+    clicked_at: NaiveDateTime,
+    referrer: Option<String>
+}
+
+/// A link's clicks, most recent first. Only the referring host is stored, and
+/// neither user agents nor locations are.
+pub async fn short_url_visits(
+    Path((_, code)): Path<(String, String)>,
+    Query(query): Query<PageQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<serde_json::Value>, ShlinkError> {
+    let (page, items_per_page) = query.page();
+    let id = links::normalize_code(&config, code);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let found = conn.interact(move |conn| {
+        let exists: bool = diesel::select(diesel::dsl::exists(urls::table.find(&id))).get_result(conn)?;
+        if !exists {
+            return Ok(None)
+        }
+        let total: i64 = clicks::table
+            .filter(clicks::url_id.eq(&id))
+            .count()
+            .get_result(conn)?;
+        let visits = clicks::table
+            .filter(clicks::url_id.eq(&id))
+            .select(Visit::as_select())
+            .order((clicks::clicked_at.desc(), clicks::id.desc()))
+            .limit(items_per_page)
+            .offset((page - 1).saturating_mul(items_per_page))
+            .load(conn)?;
+        Ok::<_, diesel::result::Error>(Some((visits, total)))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let (visits, total) = found.ok_or_else(not_found)?;
+    let pagination = Pagination::new(page, items_per_page, visits.len(), total);
+    let data: Vec<serde_json::Value> = visits.into_iter()
+        .map(|visit| json!({
+            "referer": visit.referrer.unwrap_or_default(),
+            "date": timestamp(visit.clicked_at),
+            "userAgent": "",
+            "visitLocation": null,
+            "potentialBot": false
+        }))
+        .collect();
+    Ok(Json(json!({ "visits": { "data": data, "pagination": pagination } })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_are_bounded() {
+        let query = |page, items_per_page| PageQuery { page, items_per_page, search_term: None };
+        assert_eq!(query(None, None).page(), (1, DEFAULT_ITEMS_PER_PAGE));
+        assert_eq!(query(Some(0), Some(-1)).page(), (1, MAX_ITEMS_PER_PAGE));
+        assert_eq!(query(Some(3), Some(1_000_000)).page(), (3, MAX_ITEMS_PER_PAGE));
+        assert_eq!(Pagination::new(1, 10, 10, 21).pages_count, 3);
+        assert_eq!(Pagination::new(1, 10, 0, 0).pages_count, 0);
+    }
+}
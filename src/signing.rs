@@ -0,0 +1,68 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::auth;
+
+/// The longest signature the digest has bytes for.
+pub const MAX_LENGTH: usize = 32;
+
+/// A `length`-character HMAC-SHA256 of `code` under `key`, spelled in `alphabet` so
+/// the signed code is still a valid code. Each character carries a byte of the
+/// digest, so a signature has roughly `length * log2(alphabet size)` bits.
+pub fn sign(key: &[u8], alphabet: &[char], code: &str, length: usize) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(code.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .take(length)
+        .map(|&byte| alphabet[byte as usize % alphabet.len()])
+        .collect()
+}
+
+/// Whether the last `length` characters of `signed` are the signature of the rest.
+pub fn verify(key: &[u8], alphabet: &[char], signed: &str, length: usize) -> bool {
+    let Some((split, _)) = signed.char_indices().rev().nth(length - 1) else {
+        return false
+    };
+    let (code, signature) = signed.split_at(split);
+    // Hashing both sides keeps the comparison's timing independent of where they differ.
+    auth::constant_time_eq(
+        &Sha256::digest(sign(key, alphabet, code, length).as_bytes()),
+        &Sha256::digest(signature.as_bytes())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: [char; 16] = ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0', 'a', 'b', 'c', 'd', 'e', 'f'];
+
+    #[test]
+    fn signatures_are_stable_and_drawn_from_the_alphabet() {
+        let signature = sign(b"key", &HEX, "0f9e8d", 8);
+        assert_eq!(signature, sign(b"key", &HEX, "0f9e8d", 8));
+        assert_eq!(signature.chars().count(), 8);
+        assert!(signature.chars().all(|c| HEX.contains(&c)));
+        assert_ne!(signature, sign(b"other key", &HEX, "0f9e8d", 8));
+    }
+
+    #[test]
+    fn verifies_only_untampered_codes() {
+        let signed = format!("0f9e8d{}", sign(b"key", &HEX, "0f9e8d", 8));
+        assert!(verify(b"key", &HEX, &signed, 8));
+        assert!(!verify(b"other key", &HEX, &signed, 8));
+        assert!(!verify(b"key", &HEX, &signed.replacen('0', "1", 1), 8));
+        assert!(!verify(b"key", &HEX, &signed[..signed.len() - 1], 8));
+        assert!(!verify(b"key", &HEX, "abc", 8));
+    }
+
+    #[test]
+    fn handles_multi_byte_alphabets() {
+        let emoji: Vec<char> = "🚀🔥🎉🌈🍕".chars().collect();
+        let signed = format!("🚀🔥{}", sign(b"key", &emoji, "🚀🔥", 6));
+        assert!(verify(b"key", &emoji, &signed, 6));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::{header, StatusCode}, response::IntoResponse, Extension, Json
+};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html::escape;
+use crate::links;
+use crate::schema::urls;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// The most URLs a single sitemap may list.
+const MAX_ENTRIES: i64 = 50_000;
+
+/// Live links flagged as indexable, with when each last changed.
+fn indexable_links(conn: &mut SqliteConnection) -> QueryResult<Vec<(String, NaiveDateTime)>> {
+    let now = Utc::now().naive_utc();
+    urls::table
+        .filter(urls::indexable.eq(true))
+        .filter(urls::disabled.eq(false))
+        .filter(urls::quarantined.eq(false))
+        .filter(urls::publish_at.is_null().or(urls::publish_at.le(now)))
+        .order(urls::updated_at.desc())
+        .limit(MAX_ENTRIES)
+        .select((urls::id, urls::updated_at))
+        .load(conn)
+}
+
+async fn load(pool: &sqlite::Pool) -> Result<Vec<(String, NaiveDateTime)>, ApiError> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(unavailable)?;
+    conn.interact(indexable_links)
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)
+}
+
+/// Lists the indexable links when `SITEMAP` is set.
+pub async fn sitemap_xml(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<impl IntoResponse, ApiError> {
+    if !config.sitemap {
+        return Err(
+            (StatusCode::NOT_FOUND, "No sitemap".into()).into()
+        )
+    }
+    let entries: String = load(&pool).await?
+        .iter()
+        .map(|(id, updated_at)| format!(
+            "<url><loc>{}</loc><lastmod>{}</lastmod></url>\n",
+            escape(&links::short_url(&config, id)),
+            updated_at.and_utc().to_rfc3339()
+        ))
+        .collect();
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{entries}</urlset>\n"
+        )
+    ))
+}
+
+/// A robots.txt letting crawlers reach only the indexable links. `$` ends a rule,
+/// so allowing one code doesn't allow every longer code starting with it.
+pub async fn robots_txt(pool: &sqlite::Pool, config: &AppConfig) -> Result<String, ApiError> {
+    let mut robots = String::from("User-agent: *\n");
+    for (id, _) in load(pool).await? {
+        robots.push_str(&format!("Allow: /{id}$\n"));
+    }
+    robots.push_str(&format!("Disallow: /\n\nSitemap: {}/sitemap.xml\n", config.addr));
+    Ok(robots)
+}
+
+#[derive(Deserialize)]
+pub struct IndexableRequest {
+    indexable: bool
+}
+
+/// Lists a link in the sitemap, or takes it out.
+pub async fn set_indexable(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<IndexableRequest>
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set(urls::indexable.eq(request.indexable))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: if request.indexable { "make_indexable" } else { "make_unindexable" },
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,127 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::Bytes, extract::{ConnectInfo, State}, http::{HeaderMap, StatusCode}, Extension, Json
+};
+use chrono::Utc;
+use deadpool_diesel::sqlite;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::errors::ApiError;
+use crate::links::{self, CreateLink};
+use crate::security_log;
+use crate::AppConfig;
+
+/// Slack recommends rejecting requests whose timestamp is more than five minutes off.
+const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+#[derive(Deserialize)]
+pub struct SlashCommand {
+    text: String,
+    user_id: String
+}
+
+#[derive(Serialize)]
+pub struct SlackMessage {
+    response_type: &'static str,
+    text: String
+}
+
+impl SlackMessage {
+    fn ephemeral(text: String) -> Self {
+        SlackMessage { response_type: "ephemeral", text }
+    }
+
+    fn in_channel(text: String) -> Self {
+        SlackMessage { response_type: "in_channel", text }
+    }
+}
+
+pub async fn slash_command(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes
+) -> Result<Json<SlackMessage>, ApiError> {
+    let Some(secret) = config.slack_signing_secret.as_deref() else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Slack integration is not configured".into()).into()
+        )
+    };
+    if !verify_signature(secret, &headers, &body) {
+        security_log::record(&config.security_log, "auth_failure", addr.ip(), "/api/integrations/slack");
+        return Err(
+            (StatusCode::UNAUTHORIZED, "Invalid Slack signature".into()).into()
+        )
+    }
+
+    let command: SlashCommand = serde_urlencoded::from_bytes(&body)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    let mut args = command.text.split_whitespace();
+    let Some(url) = args.next().map(unwrap_link) else {
+        return Ok(Json(SlackMessage::ephemeral("Usage: /shorten <url> [alias]".into())))
+    };
+
+    let created = links::create(&pool, &config, CreateLink {
+        url: url.to_owned(),
+        alias: args.next().map(String::from),
+        public_stats: false,
+        publish_at: None,
+        title: None,
+        notes: None,
+        id_strategy: None,
+        idempotency_key: None,
+        actor: Some(format!("slack:{}", command.user_id)),
+        ip: None
+    })
+    .await;
+
+    // Slack only shows the body of successful responses, so failures are reported as messages.
+    Ok(Json(match created {
+        Ok(created) => SlackMessage::in_channel(format!(
+            "{} → {}",
+            links::short_url(&config, &created.id),
+            created.url
+        )),
+        Err(ApiError { message: reason, .. }) => SlackMessage::ephemeral(format!("Couldn't shorten {url}: {reason}"))
+    }))
+}
+
+/// Slack sends links as `<url>` or `<url|label>` when link escaping is enabled for the command.
+fn unwrap_link(text: &str) -> &str {
+    text.strip_prefix('<')
+        .and_then(|text| text.strip_suffix('>'))
+        .map(|text| text.split('|').next().unwrap_or(text))
+        .unwrap_or(text)
+}
+
+fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let (Some(timestamp), Some(signature)) = (
+        header("X-Slack-Request-Timestamp"),
+        header("X-Slack-Signature")
+    ) else {
+        return false
+    };
+
+    let fresh = timestamp.parse::<i64>()
+        .is_ok_and(|timestamp| (Utc::now().timestamp() - timestamp).abs() <= MAX_CLOCK_SKEW_SECS);
+    let Some(signature) = signature.strip_prefix("v0=").and_then(|hex| hex::decode(hex).ok()) else {
+        return false
+    };
+    if !fresh {
+        return false
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(b"v0:");
+    mac.update(timestamp.as_bytes());
+    mac.update(b":");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
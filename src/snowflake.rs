@@ -0,0 +1,90 @@
+use std::{
+    sync::Mutex, time::{SystemTime, UNIX_EPOCH}
+};
+
+/// 2024-01-01T00:00:00Z; timestamps count milliseconds from here to keep codes short.
+const EPOCH_MS: u64 = 1_704_067_200_000;
+const NODE_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+pub const MAX_NODE_ID: u16 = (1 << NODE_BITS) - 1;
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+
+const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The last timestamp handed out and the sequence number used within it.
+static STATE: Mutex<(u64, u64)> = Mutex::new((0, 0));
+
+/// A 63-bit id made of the milliseconds since [`EPOCH_MS`], the node id and a
+/// per-millisecond sequence, so instances with distinct node ids never collide.
+///
+/// Ids keep increasing even if the clock steps back or more than 4096 ids are
+/// needed within a millisecond, by running ahead of the clock until it catches up.
+pub fn next_id(node_id: u16) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+        .saturating_sub(EPOCH_MS);
+
+    let mut state = STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let (last, sequence) = *state;
+    *state = if now > last {
+        (now, 0)
+    } else if sequence < MAX_SEQUENCE {
+        (last, sequence + 1)
+    } else {
+        (last + 1, 0)
+    };
+
+    let (timestamp, sequence) = *state;
+    timestamp << (NODE_BITS + SEQUENCE_BITS)
+        | u64::from(node_id & MAX_NODE_ID) << SEQUENCE_BITS
+        | sequence
+}
+
+/// [`next_id`] in base 62, which fits in 11 characters.
+pub fn next_code(node_id: u16) -> String {
+    base62(next_id(node_id))
+}
+
+/// `id` in base 62, most significant digit first.
+pub fn base62(mut id: u64) -> String {
+    let mut code = Vec::new();
+    loop {
+        code.push(ALPHABET[(id % 62) as usize]);
+        id /= 62;
+        if id == 0 {
+            break
+        }
+    }
+    code.reverse();
+    String::from_utf8(code).expect("alphabet is ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_keep_increasing() {
+        let ids: Vec<u64> = (0..10_000).map(|_| next_id(1)).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn ids_carry_the_node_id() {
+        let id = next_id(MAX_NODE_ID);
+        assert_eq!(id >> SEQUENCE_BITS & u64::from(MAX_NODE_ID), u64::from(MAX_NODE_ID));
+        let id = next_id(MAX_NODE_ID + 1);
+        assert_eq!(id >> SEQUENCE_BITS & u64::from(MAX_NODE_ID), 0);
+    }
+
+    #[test]
+    fn codes_are_base_62() {
+        let code = next_code(7);
+        assert!(code.len() <= 11);
+        assert!(code.bytes().all(|c| ALPHABET.contains(&c)));
+        assert_eq!(base62(0), "0");
+        assert_eq!(base62(61), "z");
+        assert_eq!(base62(62), "10");
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::HashSet, net::SocketAddr, sync::Arc
+};
+
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::StatusCode, Extension, Json
+};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::archive;
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::links;
+use crate::schema::{audit_log, urls};
+use crate::screenshot;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// Actor recorded in the audit log for links held back for review.
+pub const ACTOR: &str = "spam_filter";
+
+/// Points each signal adds to a link's score.
+const NEW_DOMAIN: i32 = 3;
+const SHORTENER: i32 = 2;
+const SPAM_TLD: i32 = 2;
+const BURST: i32 = 2;
+
+/// Scores anonymously created links on signals common to spam, holding those
+/// that reach `quarantine_score` back until an admin approves them.
+pub struct SpamPolicy {
+    /// Zero turns scoring off.
+    pub quarantine_score: i32,
+    /// Top-level domains mostly seen in spam, without the dot.
+    pub tlds: Vec<String>,
+    /// Other URL shorteners, whose links hide the real destination.
+    pub shorteners: Vec<String>,
+    /// Recently registered domains, e.g. from a newly registered domains feed.
+    pub new_domains: HashSet<String>,
+    /// Creations from one address within `burst_window` beyond which it's bursting. Zero turns this off.
+    pub burst_limit: i64,
+    pub burst_window: chrono::Duration
+}
+
+/// Why a link scored what it did.
+pub struct Assessment {
+    pub score: i32,
+    pub reasons: Vec<&'static str>
+}
+
+/// `host` and each domain it's a subdomain of, e.g. `a.b.c`, `b.c` and `c`.
+pub fn domains(host: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(host), |domain| domain.split_once('.').map(|(_, parent)| parent))
+}
+
+impl SpamPolicy {
+    pub fn enabled(&self) -> bool {
+        self.quarantine_score > 0
+    }
+
+    /// Scores what can be told from the destination alone.
+    pub fn assess(&self, destination: &str) -> Assessment {
+        let mut assessment = Assessment { score: 0, reasons: Vec::new() };
+        let host = url::Url::parse(destination)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.trim_end_matches('.').to_ascii_lowercase()))
+            .unwrap_or_default();
+        if domains(&host).any(|domain| self.new_domains.contains(domain)) {
+            assessment.add(NEW_DOMAIN, "new_domain");
+        }
+        if domains(&host).any(|domain| self.shorteners.iter().any(|shortener| shortener == domain)) {
+            assessment.add(SHORTENER, "shortener");
+        }
+        let tld = host.rsplit('.').next().unwrap_or_default();
+        if self.tlds.iter().any(|spam_tld| spam_tld == tld) {
+            assessment.add(SPAM_TLD, "spam_tld");
+        }
+        assessment
+    }
+
+    /// Adds the burst signal if `ip` created too many links lately, as told by the audit log.
+    pub fn check_burst(&self, conn: &mut SqliteConnection, assessment: &mut Assessment, ip: Option<&str>) -> QueryResult<()> {
+        let Some(ip) = ip.filter(|_| self.burst_limit > 0) else {
+            return Ok(())
+        };
+        let recent: i64 = audit_log::table
+            .filter(audit_log::action.eq("create"))
+            .filter(audit_log::ip.eq(ip))
+            .filter(audit_log::created_at.gt(Utc::now().naive_utc() - self.burst_window))
+            .count()
+            .get_result(conn)?;
+        if recent >= self.burst_limit {
+            assessment.add(BURST, "burst");
+        }
+        Ok(())
+    }
+
+    pub fn quarantines(&self, assessment: &Assessment) -> bool {
+        self.enabled() && assessment.score >= self.quarantine_score
+    }
+}
+
+impl Assessment {
+    fn add(&mut self, points: i32, reason: &'static str) {
+        self.score += points;
+        self.reasons.push(reason);
+    }
+}
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = urls)]
+pub struct QuarantinedLink {
+    id: String,
+    url: String,
+    spam_score: i32,
+    created_at: NaiveDateTime
+}
+
+/// Links held back for review, oldest first.
+pub async fn list_quarantine(
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Vec<QuarantinedLink>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let held = conn.interact(|conn| {
+        urls::table
+            .filter(urls::quarantined.eq(true))
+            .order(urls::created_at)
+            .select(QuarantinedLink::as_select())
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(held))
+}
+
+/// Releases a quarantined link, which then redirects like any other.
+pub async fn approve_link(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let approved = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .filter(urls::quarantined.eq(true))
+                .set(urls::quarantined.eq(false))
+                .execute(conn)?;
+            if updated == 0 {
+                return Ok(None)
+            }
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "approve",
+                target: Some(&id),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            urls::table
+                .find(&id)
+                .select((urls::id, urls::url, urls::publish_at))
+                .get_result::<(String, String, Option<NaiveDateTime>)>(conn)
+                .map(Some)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some((id, url, publish_at)) = approved else {
+        return Err(
+            (StatusCode::NOT_FOUND, "No such quarantined short URL".into()).into()
+        )
+    };
+    // Held links were kept from the archive and screenshot provider until now.
+    if publish_at.is_none_or(|publish_at| publish_at <= Utc::now().naive_utc()) {
+        archive::enqueue(&id, &url);
+        screenshot::enqueue(&id, &url);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> SpamPolicy {
+        SpamPolicy {
+            quarantine_score: 3,
+            tlds: vec!["zip".into()],
+            shorteners: vec!["bit.ly".into()],
+            new_domains: HashSet::from(["fresh.example".into()]),
+            burst_limit: 10,
+            burst_window: chrono::Duration::minutes(10)
+        }
+    }
+
+    #[test]
+    fn scores_destination_signals() {
+        let policy = policy();
+        let clean = policy.assess("https://example.com/page");
+        assert_eq!(clean.score, 0);
+        assert!(!policy.quarantines(&clean));
+
+        let chained = policy.assess("https://bit.ly/abc");
+        assert_eq!(chained.reasons, ["shortener"]);
+        assert!(!policy.quarantines(&chained));
+
+        let fresh = policy.assess("https://login.fresh.example/");
+        assert_eq!(fresh.reasons, ["new_domain"]);
+        assert!(policy.quarantines(&fresh));
+
+        let spelled_oddly = policy.assess("https://BIT.LY./x");
+        assert_eq!(spelled_oddly.score, SHORTENER);
+        assert_eq!(policy.assess("http://invoice.zip/").reasons, ["spam_tld"]);
+    }
+
+    #[test]
+    fn matches_whole_domain_labels() {
+        assert_eq!(domains("www.bit.ly").collect::<Vec<_>>(), ["www.bit.ly", "bit.ly", "ly"]);
+        assert!(policy().assess("https://www.bit.ly/").score > 0);
+        assert_eq!(policy().assess("https://rabbit.ly/").score, 0);
+    }
+
+    #[test]
+    fn disabled_policies_quarantine_nothing() {
+        let policy = SpamPolicy { quarantine_score: 0, ..policy() };
+        assert!(!policy.quarantines(&policy.assess("https://fresh.example/")));
+    }
+}
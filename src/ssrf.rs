@@ -0,0 +1,141 @@
+use std::{
+    error::Error, fmt, net::{IpAddr, Ipv4Addr, SocketAddr}
+};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::Host;
+
+/// Why a request the service was about to make itself was refused.
+#[derive(Debug)]
+pub struct Refused(String);
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is an internal address", self.0)
+    }
+}
+
+impl Error for Refused {}
+
+/// Whether an error, or anything that caused it, is a [`Refused`].
+pub fn is_refused(err: &(dyn Error + 'static)) -> bool {
+    let mut cause = Some(err);
+    while let Some(err) = cause {
+        if err.is::<Refused>() {
+            return true
+        }
+        cause = err.source();
+    }
+    false
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // Link-local, which is where AWS, GCP and Azure serve instance metadata.
+        || ip.is_link_local()
+        // Shared address space, where Alibaba Cloud serves its metadata.
+        || (first == 100 && (64..128).contains(&second))
+}
+
+/// Loopback, private, link-local and cloud metadata addresses, in either family.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal_v4(ip),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local addresses, fc00::/7.
+                    || first & 0xfe00 == 0xfc00
+                    // Link-local addresses, fe80::/10.
+                    || first & 0xffc0 == 0xfe80
+            }
+        }
+    }
+}
+
+/// Whether `url` names an internal host outright, by its address or a name that
+/// always means one. Other names are only known once they're resolved.
+pub fn names_internal_host(url: &url::Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => is_internal(ip.into()),
+        Some(Host::Ipv6(ip)) => is_internal(ip.into()),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost") || domain == "metadata.google.internal"
+        }
+        None => true
+    }
+}
+
+/// Refuses `url` if its host is, or currently resolves to, an internal address.
+/// For URLs handed to another service to fetch; requests made here should go
+/// through [`PublicResolver`] instead, which can't be raced.
+pub async fn check(url: &str) -> Result<(), String> {
+    let url = url::Url::parse(url).map_err(|err| err.to_string())?;
+    let host = url.host_str().unwrap_or_default();
+    if names_internal_host(&url) {
+        return Err(Refused(host.to_owned()).to_string())
+    }
+    if let Some(Host::Domain(domain)) = url.host() {
+        let port = url.port_or_known_default().unwrap_or(80);
+        let mut addrs = tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|err| format!("can't resolve {domain}: {err}"))?;
+        if addrs.any(|addr| is_internal(addr.ip())) {
+            return Err(Refused(host.to_owned()).to_string())
+        }
+    }
+    Ok(())
+}
+
+/// Resolves host names for the HTTP client, failing the connection when a name
+/// resolves to an internal address. The check happens on the addresses actually
+/// connected to, so a name can't be rebound to an internal one after it passed.
+/// Hosts given as addresses aren't resolved and need [`names_internal_host`].
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if addrs.iter().any(|addr| is_internal(addr.ip())) {
+                return Err(Box::new(Refused(name.as_str().to_owned())) as Box<dyn Error + Send + Sync>)
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_internal_addresses() {
+        for internal in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.100.100.200", "0.0.0.0", "::1", "fd00:ec2::254", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(is_internal(internal.parse().unwrap()), "{internal}");
+        }
+        for public in ["93.184.216.34", "172.32.0.1", "100.128.0.1", "2606:2800:220:1::1"] {
+            assert!(!is_internal(public.parse().unwrap()), "{public}");
+        }
+    }
+
+    #[test]
+    fn recognizes_internal_hosts_in_urls() {
+        let internal = |url: &str| names_internal_host(&url::Url::parse(url).unwrap());
+        assert!(internal("http://localhost:8080/"));
+        assert!(internal("http://api.LOCALHOST./"));
+        assert!(internal("http://[::1]/"));
+        // Spelled as a single number, which parsing turns into 127.0.0.1.
+        assert!(internal("http://2130706433/"));
+        assert!(internal("http://metadata.google.internal/computeMetadata/v1/"));
+        assert!(!internal("https://example.com/"));
+    }
+}
@@ -0,0 +1,164 @@
+use std::{fs, path::Path};
+
+use deadpool_diesel::sqlite;
+
+use crate::config::IdStrategy;
+use crate::AppConfig;
+
+/// Fewer possible generated codes than this fail the checks, as they'd soon collide.
+const MIN_CODE_SPACE: f64 = 1e6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail
+}
+
+/// The outcome of the checks run before serving, printed as one line each.
+#[derive(Default)]
+pub struct Report(Vec<(Status, String, String)>);
+
+impl Report {
+    pub fn ok(&mut self, check: impl Into<String>, detail: impl Into<String>) {
+        self.0.push((Status::Ok, check.into(), detail.into()));
+    }
+
+    pub fn warn(&mut self, check: impl Into<String>, detail: impl Into<String>) {
+        self.0.push((Status::Warn, check.into(), detail.into()));
+    }
+
+    pub fn fail(&mut self, check: impl Into<String>, detail: impl Into<String>) {
+        self.0.push((Status::Fail, check.into(), detail.into()));
+    }
+
+    fn render(&self) -> String {
+        let width = self.0.iter().map(|(_, check, _)| check.len()).max().unwrap_or(0);
+        self.0.iter()
+            .map(|(status, check, detail)| {
+                let status = match status {
+                    Status::Ok => "ok",
+                    Status::Warn => "warn",
+                    Status::Fail => "FAIL"
+                };
+                format!("  {status:<4}  {check:<width$}  {detail}\n")
+            })
+            .collect()
+    }
+
+    /// Prints the report, and exits if any check failed.
+    pub fn conclude(self) {
+        print!("Startup checks:\n{}", self.render());
+        if self.0.iter().any(|(status, _, _)| *status == Status::Fail) {
+            exit("startup checks failed")
+        }
+    }
+}
+
+/// Gives up before serving, with a message saying what to fix.
+pub fn exit(message: &str) -> ! {
+    println!("Can't start: {message}");
+    std::process::exit(1)
+}
+
+/// Whether a connection can be made and can take the write lock.
+pub async fn check_database(pool: &sqlite::Pool, report: &mut Report) {
+    let conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(err) => return report.fail("database", format!("{err}; check DATABASE_URL"))
+    };
+    let writable = conn.interact(|conn| conn.immediate_transaction(|_| Ok::<_, diesel::result::Error>(())))
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|locked| locked.map_err(|err| err.to_string()));
+    match writable {
+        Ok(()) => report.ok("database", "connected and writable"),
+        Err(err) => report.fail("database", format!("can't write: {err}; check the permissions of the file and its directory"))
+    }
+}
+
+/// Whether the directories the service writes to can be written.
+pub fn check_paths(config: &AppConfig, report: &mut Report) {
+    if config.screenshot_url.is_none() {
+        return
+    }
+    let dir = &config.screenshot_dir;
+    let check = format!("SCREENSHOT_DIR {}", dir.display());
+    match probe(dir) {
+        Ok(()) => report.ok(check, "writable"),
+        Err(err) => report.fail(check, format!("not writable: {err}; create it or point SCREENSHOT_DIR elsewhere"))
+    }
+}
+
+fn probe(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".write-check");
+    fs::write(&probe, b"")?;
+    fs::remove_file(probe)
+}
+
+/// Whether generated codes have room enough not to collide soon.
+pub fn check_codes(config: &AppConfig, report: &mut Report) {
+    match config.id_strategy {
+        IdStrategy::Snowflake { node_id } => return report.ok("codes", format!("snowflake ids of node {node_id}")),
+        IdStrategy::Sequential => return report.ok("codes", "sequential base-62 numbers"),
+        IdStrategy::Random | IdStrategy::Hash => {}
+    }
+    check_code_space(config.id_length, config.nano_id_alphabet.len(), report);
+}
+
+fn check_code_space(length: usize, symbols: usize, report: &mut Report) {
+    let detail = format!("{length} characters of {symbols} symbols");
+    if length == 0 || symbols == 0 {
+        return report.fail("codes", format!("{detail} make no codes; set ID_LENGTH and ID_ALPHABET"))
+    }
+    let space = (symbols as f64).powi(length as i32);
+    if space < MIN_CODE_SPACE {
+        report.fail("codes", format!("{detail} make only {space} codes; raise ID_LENGTH or widen ID_ALPHABET"));
+    } else {
+        report.ok("codes", detail);
+    }
+}
+
+/// Whether the host of the address short URLs are built with resolves. It only
+/// warns, as split-horizon DNS may hide the public name from the server itself.
+pub async fn check_public_addr(config: &AppConfig, report: &mut Report) {
+    let check = format!("PUBLIC_ADDR {}", config.addr);
+    let spelled = if config.addr.contains("://") { config.addr.clone() } else { format!("http://{}", config.addr) };
+    let Some((host, port)) = url::Url::parse(&spelled)
+        .ok()
+        .and_then(|url| Some((url.host_str()?.to_owned(), url.port_or_known_default()?))) else {
+        return report.fail(check, "isn't an address; set PUBLIC_ADDR to e.g. https://sho.rt")
+    };
+    let resolved = tokio::net::lookup_host((host.trim_matches(['[', ']']), port)).await;
+    match resolved {
+        Ok(_) => report.ok(check, "resolves"),
+        Err(err) => report.warn(check, format!("doesn't resolve: {err}; short URLs may not work unless PUBLIC_ADDR is right"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_aligned_lines() {
+        let mut report = Report::default();
+        report.ok("database", "connected and writable");
+        report.fail("listen 0.0.0.0:3000", "address in use");
+        assert_eq!(
+            report.render(),
+            "  ok    database             connected and writable\n  FAIL  listen 0.0.0.0:3000  address in use\n"
+        );
+    }
+
+    #[test]
+    fn fails_on_small_code_spaces() {
+        let mut report = Report::default();
+        check_code_space(0, 64, &mut report);
+        check_code_space(3, 64, &mut report);
+        check_code_space(10, 64, &mut report);
+        let statuses: Vec<Status> = report.0.iter().map(|(status, _, _)| *status).collect();
+        assert!(statuses == [Status::Fail, Status::Fail, Status::Ok]);
+    }
+}
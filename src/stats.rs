@@ -0,0 +1,638 @@
+use std::{
+    collections::HashMap, net::{IpAddr, SocketAddr}, sync::{Arc, LazyLock, Mutex, OnceLock}, time::Instant
+};
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State}, http::{header, HeaderMap, StatusCode}, response::Html, Extension, Json
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use deadpool_diesel::sqlite;
+use diesel::{
+    prelude::*, sql_types::{BigInt, Date, Nullable, Text, Timestamp}
+};
+use metrics::counter;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::conversions;
+use crate::cursor::{self, Page};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::html;
+use crate::links;
+use crate::preview;
+use crate::schema::{click_stats_daily, clicks, urls};
+use crate::share;
+use crate::telemetry;
+use crate::AppConfig;
+
+const PUBLIC_STATS_DAYS: i64 = 30;
+
+/// At most this many clicks are remembered for deduplication; beyond it, clicks
+/// count without being remembered until a sweep makes room.
+const MAX_RECENT_CLICKS: usize = 10_000;
+
+static RECENT_CLICKS: LazyLock<Mutex<RecentClicks>> = LazyLock::new(Default::default);
+const TOP_REFERRERS: i64 = 10;
+
+#[derive(Insertable)]
+#[diesel(table_name = clicks)]
+struct NewClick<'a> {
+    url_id: &'a str,
+    referrer: Option<&'a str>,
+    visitor: Option<&'a str>,
+    token: Option<&'a str>
+}
+
+#[derive(Serialize, QueryableByName)]
+pub struct DailyClicks {
+    #[diesel(sql_type = Text)]
+    pub day: String,
+    #[diesel(sql_type = BigInt)]
+    pub clicks: i64,
+    #[diesel(sql_type = BigInt)]
+    pub visitors: i64
+}
+
+#[derive(Serialize, Queryable)]
+pub struct ReferrerClicks {
+    pub referrer: Option<String>,
+    pub clicks: i64
+}
+
+/// The host of the page a click came from. Only the host is kept, which is what
+/// the breakdown needs and leaves out paths that may identify the visitor.
+pub fn referrer_host(headers: &HeaderMap) -> Option<String> {
+    headers.get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| url::Url::parse(referer).ok())
+        .and_then(|referer| referer.host_str().map(str::to_ascii_lowercase))
+}
+
+/// Salt used when `VISITOR_SALT` isn't configured, so uniques only hold within one run.
+static RANDOM_SALT: OnceLock<String> = OnceLock::new();
+
+/// Whether `visitor` already clicked `url_id` within `window`, as double clicks
+/// and link prefetchers do, in which case the click shouldn't count again. Each
+/// click counted starts a new window. Always false for a zero window.
+pub fn is_repeat_click(window: std::time::Duration, url_id: &str, visitor: &str) -> bool {
+    if window.is_zero() {
+        return false
+    }
+    let mut recent = RECENT_CLICKS.lock().expect("recent clicks lock poisoned");
+    let repeat = recent.is_repeat((url_id.to_owned(), visitor.to_owned()), Instant::now(), window, MAX_RECENT_CLICKS);
+    if repeat {
+        counter!("clicks_deduplicated_total").increment(1);
+    }
+    repeat
+}
+
+/// When each visitor last clicked each link, by link and visitor hash.
+#[derive(Default)]
+struct RecentClicks {
+    clicked: HashMap<(String, String), Instant>,
+    swept_at: Option<Instant>
+}
+
+impl RecentClicks {
+    /// Whether `key` was clicked within `window` of `now`, remembering this click
+    /// otherwise. Clicks whose window has passed are swept out once per window, so
+    /// a sweep is never repeated on every click, and at most `max` are kept.
+    fn is_repeat(&mut self, key: (String, String), now: Instant, window: std::time::Duration, max: usize) -> bool {
+        if self.clicked.get(&key).is_some_and(|clicked| now.duration_since(*clicked) < window) {
+            return true
+        }
+        if self.swept_at.is_none_or(|swept_at| now.duration_since(swept_at) >= window) {
+            self.clicked.retain(|_, clicked| now.duration_since(*clicked) < window);
+            self.swept_at = Some(now);
+        }
+        if self.clicked.len() < max || self.clicked.contains_key(&key) {
+            self.clicked.insert(key, now);
+        }
+        false
+    }
+}
+
+/// A pseudonymous visitor id for counting uniques: a salted hash of the IP
+/// address and user agent that also covers the current day, so the same visitor
+/// can't be followed from one day to the next.
+pub fn visitor_hash(config: &AppConfig, ip: IpAddr, user_agent: Option<&str>) -> String {
+    let salt = config.visitor_salt
+        .as_deref()
+        .unwrap_or_else(|| RANDOM_SALT.get_or_init(|| nanoid!(32)));
+    let digest = Sha256::new()
+        .chain_update(salt)
+        .chain_update([0])
+        .chain_update(Utc::now().date_naive().to_string())
+        .chain_update([0])
+        .chain_update(ip.to_string())
+        .chain_update([0])
+        .chain_update(user_agent.unwrap_or_default())
+        .finalize();
+    hex::encode(&digest[..16])
+}
+
+/// Logs a click. `token` is the unguessable id conversions are later reported with.
+pub fn record_click(
+    conn: &mut SqliteConnection,
+    url_id: &str,
+    referrer: Option<&str>,
+    visitor: Option<&str>,
+    token: Option<&str>
+) -> QueryResult<()> {
+    diesel::insert_into(clicks::table)
+        .values(NewClick { url_id, referrer, visitor, token })
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Counts a click only towards the link's total, for visitors who opted out of tracking.
+pub fn record_untracked_click(conn: &mut SqliteConnection, url_id: &str) -> QueryResult<()> {
+    diesel::update(urls::table.find(url_id))
+        .set(urls::untracked_clicks.eq(urls::untracked_clicks + 1))
+        .execute(conn)
+        .map(|_| ())
+}
+
+/// Whether the request carries a Do-Not-Track or Global Privacy Control signal.
+pub fn opted_out(headers: &HeaderMap) -> bool {
+    ["DNT", "Sec-GPC"].iter().any(|name| {
+        headers.get(*name).is_some_and(|value| value.as_bytes().trim_ascii() == b"1")
+    })
+}
+
+/// Requests for a link no one asked to visit yet.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Automated {
+    /// The browser fetching a link ahead of time, in case it's followed.
+    Prefetch,
+    /// A chat app or social site fetching a link to show a preview card of it.
+    Preview
+}
+
+impl Automated {
+    pub fn name(self) -> &'static str {
+        match self {
+            Automated::Prefetch => "prefetch",
+            Automated::Preview => "preview"
+        }
+    }
+}
+
+/// Whether a request was made by the browser or a link previewer rather than a
+/// visitor, going by the headers browsers mark prefetches with and by the user
+/// agents of previewers.
+pub fn automated(headers: &HeaderMap) -> Option<Automated> {
+    let marks = |name: &str, purposes: &[&str]| headers.get_all(name).iter().any(|value| {
+        value.to_str().unwrap_or_default().split([',', ';']).any(|token| {
+            purposes.iter().any(|purpose| token.trim().eq_ignore_ascii_case(purpose))
+        })
+    });
+    if marks("Sec-Purpose", &["prefetch", "prerender"]) || marks("Purpose", &["prefetch"]) || marks("X-Moz", &["prefetch"]) {
+        Some(Automated::Prefetch)
+    } else if marks("X-Purpose", &["preview"]) || preview::is_crawler(headers) {
+        Some(Automated::Preview)
+    } else {
+        None
+    }
+}
+
+#[derive(QueryableByName)]
+struct ClickCount {
+    #[diesel(sql_type = BigInt)]
+    clicks: i64
+}
+
+/// Where a link's raw clicks start counting: after the last day rolled up into
+/// `click_stats_daily`, which already counts the clicks before.
+fn unrolled_since(conn: &mut SqliteConnection, url_id: &str) -> QueryResult<Option<NaiveDateTime>> {
+    let rolled_up_to: Option<NaiveDate> = click_stats_daily::table
+        .filter(click_stats_daily::url_id.eq(url_id))
+        .select(diesel::dsl::max(click_stats_daily::day))
+        .get_result(conn)?;
+    Ok(rolled_up_to.and_then(|day| day.succ_opt()).map(|day| day.and_time(Default::default())))
+}
+
+pub fn total_clicks(conn: &mut SqliteConnection, url_id: &str) -> QueryResult<i64> {
+    let rolled_up: Option<i64> = click_stats_daily::table
+        .filter(click_stats_daily::url_id.eq(url_id))
+        // `sum` would read the total as a decimal.
+        .select(diesel::dsl::sql::<Nullable<BigInt>>("SUM(clicks)"))
+        .get_result(conn)?;
+    let mut raw = clicks::table
+        .filter(clicks::url_id.eq(url_id))
+        .into_boxed();
+    if let Some(since) = unrolled_since(conn, url_id)? {
+        raw = raw.filter(clicks::clicked_at.ge(since));
+    }
+    let tracked: i64 = raw.count().get_result(conn)?;
+    let untracked: i32 = urls::table
+        .find(url_id)
+        .select(urls::untracked_clicks)
+        .get_result(conn)
+        .optional()?
+        .unwrap_or(0);
+    Ok(rolled_up.unwrap_or(0) + tracked + i64::from(untracked))
+}
+
+/// Tracked clicks on all links, rolled up or not.
+pub fn all_tracked_clicks(conn: &mut SqliteConnection) -> QueryResult<i64> {
+    let count: ClickCount = diesel::sql_query(
+        "SELECT (SELECT COALESCE(SUM(clicks), 0) FROM click_stats_daily) + (SELECT COUNT(*) FROM clicks \
+         WHERE clicked_at >= COALESCE((SELECT date(MAX(day), '+1 day') FROM click_stats_daily s WHERE s.url_id = clicks.url_id), '')) \
+         AS clicks"
+    )
+    .get_result(conn)?;
+    Ok(count.clicks)
+}
+
+/// Total clicks of a link, or `None` when the link doesn't exist or its stats aren't public.
+pub fn public_total_clicks(conn: &mut SqliteConnection, url_id: &str) -> QueryResult<Option<i64>> {
+    let public = urls::table
+        .find(url_id)
+        .select(urls::public_stats)
+        .get_result::<bool>(conn)
+        .optional()?;
+    match public {
+        Some(true) => total_clicks(conn, url_id).map(Some),
+        _ => Ok(None)
+    }
+}
+
+/// When `day` starts in `tz`, in UTC like the clicks. Where clocks skip
+/// midnight, the day starts at the first hour that exists.
+pub fn start_of_day(day: NaiveDate, tz: Tz) -> NaiveDateTime {
+    let midnight = day.and_time(Default::default());
+    tz.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(midnight + Duration::hours(1))).earliest())
+        .map_or(midnight, |start| start.naive_utc())
+}
+
+/// The time zone a `?tz=` parameter names, UTC if it's missing.
+pub fn time_zone(tz: Option<&str>) -> Result<Tz, ApiError> {
+    match tz {
+        Some(tz) => tz.parse::<Tz>().map_err(|_| (
+            StatusCode::UNPROCESSABLE_ENTITY, "tz must be an IANA time zone, e.g. Europe/Berlin".to_owned()
+        ).into()),
+        None => Ok(Tz::UTC)
+    }
+}
+
+/// The day it is now in `tz`.
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// Click and unique visitor counts per day in `tz` from `since` onwards, one
+/// entry for every day including empty ones. Days rolled up by the retention job
+/// come from `click_stats_daily`, which counts them in UTC, later ones from the
+/// raw clicks; a local day overlapping a rolled-up one adds up both.
+pub fn daily_clicks(conn: &mut SqliteConnection, url_id: &str, since: NaiveDate, tz: Tz) -> QueryResult<Vec<DailyClicks>> {
+    let start = start_of_day(since, tz);
+    let raw_since = unrolled_since(conn, url_id)?.map_or(start, |unrolled| unrolled.max(start));
+    let counted: Vec<DailyClicks> = diesel::sql_query(
+        "SELECT day, SUM(clicks) AS clicks, SUM(visitors) AS visitors FROM ( \
+         SELECT day, clicks, visitors FROM click_stats_daily WHERE url_id = ? AND day >= ? \
+         UNION ALL \
+         SELECT local_date(clicked_at, ?) AS day, COUNT(*) AS clicks, COUNT(DISTINCT visitor) AS visitors FROM clicks \
+         WHERE url_id = ? AND clicked_at >= ? GROUP BY 1 \
+         ) GROUP BY day ORDER BY day"
+    )
+    .bind::<Text, _>(url_id)
+    .bind::<Date, _>(since)
+    .bind::<Text, _>(tz.name())
+    .bind::<Text, _>(url_id)
+    .bind::<Timestamp, _>(raw_since)
+    .load(conn)?;
+
+    let today = today(tz);
+    let mut counted = counted.into_iter().peekable();
+    Ok(since.iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| {
+            let day = day.to_string();
+            let (clicks, visitors) = counted.next_if(|counted| counted.day == day)
+                .map_or((0, 0), |counted| (counted.clicks, counted.visitors));
+            DailyClicks { day, clicks, visitors }
+        })
+        .collect())
+}
+
+/// The referring hosts with the most clicks since the day `since` began in `tz`;
+/// `None` groups clicks without a referrer.
+pub fn top_referrers(conn: &mut SqliteConnection, url_id: &str, since: NaiveDate, tz: Tz, limit: i64) -> QueryResult<Vec<ReferrerClicks>> {
+    clicks::table
+        .filter(clicks::url_id.eq(url_id))
+        .filter(clicks::clicked_at.ge(start_of_day(since, tz)))
+        .group_by(clicks::referrer)
+        .select((clicks::referrer, diesel::dsl::count_star()))
+        .order(diesel::dsl::count_star().desc())
+        .limit(limit)
+        .load(conn)
+}
+
+#[derive(Deserialize)]
+pub struct StatsPageQuery {
+    /// A preview token, which shows the page for a link without public stats.
+    token: Option<String>,
+    /// The IANA time zone days are counted in, e.g. `Europe/Berlin`. Defaults to UTC.
+    tz: Option<String>
+}
+
+/// Daily clicks, unique visitors, conversions and top referrers of a link whose
+/// stats were made public with [`set_public_stats`], or of any link along with
+/// its destination given a preview token, with days counted in the time zone
+/// `?tz=` names. There is no country breakdown, as
+/// clicks don't store IP addresses and no GeoIP database is configured.
+pub async fn public_stats_page(
+    Path(id): Path<String>,
+    Query(query): Query<StatsPageQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Html<String>, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let tz = time_zone(query.tz.as_deref())?;
+    let preview = query.token
+        .as_deref()
+        .zip(config.preview_signing_key.as_deref())
+        .is_some_and(|(token, key)| share::verify(key, &id, token, Utc::now()));
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let stats = conn.interact(move |conn| {
+        let destination = match preview {
+            true => urls::table.find(&id).select(urls::url).get_result::<String>(conn).optional()?,
+            false => None
+        };
+        let total = match &destination {
+            Some(_) => Some(total_clicks(conn, &id)?),
+            None => public_total_clicks(conn, &id)?
+        };
+        let Some(total) = total else {
+            return Ok(None)
+        };
+        let conversions = conversions::total_conversions(conn, &id)?;
+        let screenshot = urls::table
+            .find(&id)
+            .select(urls::screenshot.is_not_null())
+            .get_result::<bool>(conn)?;
+
+        let since = today(tz) - Duration::days(PUBLIC_STATS_DAYS - 1);
+        let daily = daily_clicks(conn, &id, since, tz)?;
+        let referrers = top_referrers(conn, &id, since, tz, TOP_REFERRERS)?;
+        Ok::<_, diesel::result::Error>(Some((id, destination, total, conversions, screenshot, daily, referrers)))
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    // Links without public stats are indistinguishable from missing ones.
+    let Some((id, destination, total, conversions, screenshot, daily, referrers)) = stats else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    };
+
+    let busiest = daily.iter()
+        .map(|day| day.clicks)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let rows: String = daily.iter()
+        .rev()
+        .map(|day| format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td><div class=\"bar\" style=\"width: {}%\"></div></td></tr>\n",
+            day.day,
+            day.clicks,
+            day.visitors,
+            day.clicks * 100 / busiest
+        ))
+        .collect();
+    let referrer_rows: String = referrers.iter()
+        .map(|referrer| format!(
+            "<tr><td>{}</td><td>{}</td></tr>\n",
+            referrer.referrer.as_deref().map_or("Direct or hidden".into(), html::escape),
+            referrer.clicks
+        ))
+        .collect();
+
+    let title = format!("Stats for /{id}");
+    let thumbnail = match screenshot {
+        true => format!("<p><img src=\"/{}/screenshot\" alt=\"Screenshot of the destination\" width=\"320\"></p>\n", html::escape(&id)),
+        false => String::new()
+    };
+    let destination = destination
+        .map(|destination| format!("<p>Goes to {}</p>\n", html::link(&destination)))
+        .unwrap_or_default();
+    let body = format!(
+        "<h1>{}</h1>\n{destination}{thumbnail}<p>{total} clicks and {conversions} conversions in total.</p>\n<h2>Last {PUBLIC_STATS_DAYS} days ({})</h2>\n\
+         <table>\n<tr><th>Day</th><th>Clicks</th><th>Visitors</th><th></th></tr>\n{rows}</table>\n\
+         <h2>Top referrers</h2>\n<table>\n<tr><th>Referrer</th><th>Clicks</th></tr>\n{referrer_rows}</table>",
+        html::escape(&title),
+        tz.name()
+    );
+    // The token is in the page's URL, which mustn't leak to the destination or search engines.
+    let head = match preview {
+        true => "<meta name=\"referrer\" content=\"no-referrer\">\n<meta name=\"robots\" content=\"noindex\">\n",
+        false => ""
+    };
+    Ok(Html(html::page_with_head("en", &title, head, &body)))
+}
+
+#[derive(Deserialize)]
+pub struct PublicStatsRequest {
+    public: bool
+}
+
+const DEFAULT_HISTORY_LIMIT: i64 = 100;
+const MAX_HISTORY_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct ClickHistoryQuery {
+    /// The `next` cursor of the previous page.
+    after: Option<String>,
+    /// Clicks to skip instead, for jumping to a page. Gets slower the further in it goes.
+    offset: Option<i64>,
+    limit: Option<i64>
+}
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = clicks)]
+pub struct Click {
+    id: i32,
+    clicked_at: NaiveDateTime,
+    referrer: Option<String>
+}
+
+/// The clicks on a link, most recent first.
+pub async fn click_history(
+    Path(id): Path<String>,
+    Query(query): Query<ClickHistoryQuery>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<Json<Page<Click>>, ApiError> {
+    if query.after.is_some() && query.offset.is_some() {
+        return Err(
+            (StatusCode::BAD_REQUEST, "Use either after or offset, not both".into()).into()
+        )
+    }
+    let after = query.after.as_deref()
+        .map(|after| cursor::decode(after)
+            .and_then(|(clicked_at, id)| id.parse::<i32>()
+                .map(|id| (clicked_at, id))
+                .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid cursor".to_owned()).into())))
+        .transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let clicks = conn.interact(move |conn| {
+        let exists: bool = diesel::select(diesel::dsl::exists(urls::table.find(&id))).get_result(conn)?;
+        if !exists {
+            return Ok(None)
+        }
+        // One row beyond the page tells whether there's another one.
+        let mut statement = clicks::table
+            .filter(clicks::url_id.eq(&id))
+            .select(Click::as_select())
+            .order((clicks::clicked_at.desc(), clicks::id.desc()))
+            .limit(limit + 1)
+            .into_boxed();
+
+        if let Some((clicked_at, click_id)) = after {
+            statement = statement.filter(
+                clicks::clicked_at.lt(clicked_at)
+                    .or(clicks::clicked_at.eq(clicked_at).and(clicks::id.lt(click_id)))
+            );
+        }
+        if let Some(offset) = query.offset {
+            statement = statement.offset(offset.max(0));
+        }
+
+        statement.load(conn).map(Some)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(clicks) = clicks else {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    };
+    Ok(Json(cursor::paginate(clicks, limit, |click| (click.clicked_at, click.id.to_string()))))
+}
+
+pub async fn set_public_stats(
+    Path(id): Path<String>,
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<PublicStatsRequest>
+) -> Result<StatusCode, ApiError> {
+    let id = links::normalize_code(&config, id);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let updated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let updated = diesel::update(urls::table.find(&id))
+                .set(urls::public_stats.eq(request.public))
+                .execute(conn)?;
+            if updated > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: if request.public { "publish_stats" } else { "unpublish_stats" },
+                    target: Some(&id),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(updated)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if updated == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Short URL not found".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn recognizes_prefetches_and_previews() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.append(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        assert!(automated(&headers(&[("sec-purpose", "prefetch")])) == Some(Automated::Prefetch));
+        assert!(automated(&headers(&[("sec-purpose", "prefetch;prerender")])) == Some(Automated::Prefetch));
+        assert!(automated(&headers(&[("x-moz", "prefetch")])) == Some(Automated::Prefetch));
+        assert!(automated(&headers(&[("x-purpose", "preview")])) == Some(Automated::Preview));
+        assert!(automated(&headers(&[("user-agent", "Slackbot-LinkExpanding 1.0")])) == Some(Automated::Preview));
+        assert!(automated(&headers(&[("user-agent", "Mozilla/5.0"), ("sec-purpose", "prefetcher")])).is_none());
+    }
+
+    #[test]
+    fn repeat_clicks_count_once_per_window() {
+        let window = std::time::Duration::from_secs(60);
+        assert!(!is_repeat_click(window, "repeat", "visitor"));
+        assert!(is_repeat_click(window, "repeat", "visitor"));
+        assert!(!is_repeat_click(window, "repeat", "other visitor"));
+        assert!(!is_repeat_click(window, "other link", "visitor"));
+        assert!(!is_repeat_click(std::time::Duration::ZERO, "repeat", "visitor"));
+    }
+
+    #[test]
+    fn remembers_a_bounded_number_of_clicks() {
+        let window = std::time::Duration::from_secs(60);
+        let start = Instant::now();
+        let key = |n: usize| (format!("link{n}"), "visitor".to_owned());
+        let mut recent = RecentClicks::default();
+        for n in 0..5 {
+            assert!(!recent.is_repeat(key(n), start, window, 3));
+        }
+        assert_eq!(recent.clicked.len(), 3);
+        assert!(recent.is_repeat(key(0), start, window, 3));
+        assert!(!recent.is_repeat(key(4), start, window, 3));
+
+        // Once the window has passed, the next click sweeps out the expired ones.
+        let later = start + window;
+        assert!(!recent.is_repeat(key(4), later, window, 3));
+        assert_eq!(recent.clicked.len(), 1);
+        assert!(recent.is_repeat(key(4), later, window, 3));
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        let day = |text| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+        assert_eq!(start_of_day(day("2024-03-10"), Tz::UTC), at("2024-03-10 00:00"));
+        assert_eq!(start_of_day(day("2024-03-10"), Tz::America__New_York), at("2024-03-10 05:00"));
+        assert_eq!(start_of_day(day("2024-01-01"), Tz::Asia__Kolkata), at("2023-12-31 18:30"));
+        // Chile moves its clocks from midnight to one o'clock.
+        assert_eq!(start_of_day(day("2024-09-08"), Tz::America__Santiago), at("2024-09-08 04:00"));
+    }
+}
@@ -0,0 +1,52 @@
+use std::{env, io, net::TcpListener};
+
+use listenfd::ListenFd;
+
+/// `FileDescriptorName=` of the sockets that serve the admin API, like `ADMIN_LISTEN_ADDRS`.
+const ADMIN_SOCKET_NAME: &str = "admin";
+
+/// Listening sockets systemd passed through socket activation, split into the
+/// public ones and the ones named `admin`, in the order they were configured.
+pub fn activated_listeners() -> io::Result<(Vec<TcpListener>, Vec<TcpListener>)> {
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':');
+    let mut fds = ListenFd::from_env();
+    let (mut public, mut admin) = (Vec::new(), Vec::new());
+    for index in 0..fds.len() {
+        let name = names.next().unwrap_or_default();
+        let Some(listener) = fds.take_tcp_listener(index)? else {
+            continue
+        };
+        listener.set_nonblocking(true)?;
+        if name == ADMIN_SOCKET_NAME {
+            admin.push(listener);
+        } else {
+            public.push(listener);
+        }
+    }
+    Ok((public, admin))
+}
+
+/// Tells systemd the service is up, for `Type=notify` units, and keeps its
+/// watchdog fed when the unit sets `WatchdogSec=`. Does nothing outside systemd.
+pub fn notify_ready() {
+    #[cfg(unix)]
+    {
+        use sd_notify::NotifyState;
+
+        if let Err(err) = sd_notify::notify(false, &[NotifyState::Ready]) {
+            println!("Can't notify systemd: {err}");
+        }
+        let mut usec = 0;
+        if sd_notify::watchdog_enabled(false, &mut usec) {
+            // Pinging at half the timeout leaves room for a slow tick.
+            let interval = std::time::Duration::from_micros(usec / 2);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    sd_notify::notify(false, &[NotifyState::Watchdog]).ok();
+                }
+            });
+        }
+    }
+}
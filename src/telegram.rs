@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use deadpool_diesel::sqlite;
+use serde::{de::{DeserializeOwned, IgnoredAny}, Deserialize, Serialize};
+
+use crate::errors::ApiError;
+use crate::links::{self, CreateLink};
+use crate::stats;
+use crate::config::{AppConfig, LiveConfig};
+use crate::telemetry;
+
+const API_BASE: &str = "https://api.telegram.org";
+const POLL_TIMEOUT_SECS: u64 = 30;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+const HELP: &str = "Send me a link and I'll shorten it.\n\
+    /shorten <url> [alias] - shorten with a custom alias\n\
+    /stats <code> - show clicks of a link with public stats";
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    from: Option<User>,
+    text: Option<String>
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: i64
+}
+
+#[derive(Serialize)]
+struct GetUpdates {
+    offset: i64,
+    timeout: u64
+}
+
+#[derive(Serialize)]
+struct SendMessage<'a> {
+    chat_id: i64,
+    text: &'a str
+}
+
+struct Bot {
+    client: reqwest::Client,
+    token: String
+}
+
+impl Bot {
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: &impl Serialize) -> Result<T, String> {
+        let response: ApiResponse<T> = self.client
+            .post(format!("{API_BASE}/bot{}/{method}", self.token))
+            .timeout(Duration::from_secs(POLL_TIMEOUT_SECS + 10))
+            .json(params)
+            .send()
+            .await
+            .map_err(|err| err.without_url().to_string())?
+            .json()
+            .await
+            .map_err(|err| err.without_url().to_string())?;
+
+        match response {
+            ApiResponse { ok: true, result: Some(result), .. } => Ok(result),
+            response => Err(response.description.unwrap_or_else(|| format!("{method} failed")))
+        }
+    }
+}
+
+/// Starts long-polling Telegram for messages to the bot identified by `token`.
+pub fn spawn(token: String, pool: sqlite::Pool, config: LiveConfig) {
+    let bot = Bot {
+        client: reqwest::Client::new(),
+        token
+    };
+    tokio::spawn(async move {
+        let mut offset = 0;
+        loop {
+            let params = GetUpdates { offset, timeout: POLL_TIMEOUT_SECS };
+            let updates: Vec<Update> = match bot.call("getUpdates", &params).await {
+                Ok(updates) => updates,
+                Err(err) => {
+                    println!("Telegram polling failed: {err}");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue
+                }
+            };
+
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+                let Some(message) = update.message else {
+                    continue
+                };
+                let Some(text) = message.text.as_deref() else {
+                    continue
+                };
+
+                let from = message.from.map(|user| user.id);
+                let reply = respond(&pool, &config.load(), text, from).await;
+                let sent = bot.call::<IgnoredAny>("sendMessage", &SendMessage {
+                    chat_id: message.chat.id,
+                    text: &reply
+                })
+                .await;
+                if let Err(err) = sent {
+                    println!("Telegram reply failed: {err}");
+                }
+            }
+        }
+    });
+}
+
+async fn respond(pool: &sqlite::Pool, config: &AppConfig, text: &str, from: Option<i64>) -> String {
+    let mut args = text.split_whitespace();
+    let first = args.next().unwrap_or_default();
+    // Commands may be addressed to a specific bot in groups, e.g. `/stats@my_bot`.
+    let command = first.split('@').next().unwrap_or(first);
+
+    match command {
+        "/start" | "/help" => HELP.into(),
+        "/stats" => match args.next() {
+            Some(id) => link_stats(pool, config, id).await,
+            None => "Usage: /stats <code>".into()
+        },
+        "/shorten" => match args.next() {
+            Some(url) => shorten(pool, config, url, args.next(), from).await,
+            None => "Usage: /shorten <url> [alias]".into()
+        },
+        _ if command.starts_with('/') => HELP.into(),
+        url => shorten(pool, config, url, None, from).await
+    }
+}
+
+async fn shorten(
+    pool: &sqlite::Pool,
+    config: &AppConfig,
+    url: &str,
+    alias: Option<&str>,
+    from: Option<i64>
+) -> String {
+    let created = links::create(pool, config, CreateLink {
+        url: url.to_owned(),
+        alias: alias.map(String::from),
+        public_stats: false,
+        publish_at: None,
+        title: None,
+        notes: None,
+        id_strategy: None,
+        idempotency_key: None,
+        actor: from.map(|id| format!("telegram:{id}")),
+        ip: None
+    })
+    .await;
+
+    match created {
+        Ok(created) => links::short_url(config, &created.id),
+        Err(ApiError { message: reason, .. }) => format!("Couldn't shorten {url}: {reason}")
+    }
+}
+
+async fn link_stats(pool: &sqlite::Pool, config: &AppConfig, id: &str) -> String {
+    let Ok(conn) = telemetry::checkout(pool).await else {
+        return "Stats are unavailable right now".into()
+    };
+
+    let lookup = links::normalize_code(config, id.to_owned());
+    let total = conn.interact(move |conn| {
+        stats::public_total_clicks(conn, &lookup)
+    })
+    .await;
+
+    match total {
+        Ok(Ok(Some(total))) => format!("/{id} has been clicked {total} times"),
+        Ok(Ok(None)) => format!("No public stats for /{id}"),
+        _ => "Stats are unavailable right now".into()
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::HashSet, sync::{LazyLock, Mutex}, time::Instant
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State}, middleware::Next, response::Response, Extension
+};
+use deadpool_diesel::{sqlite, PoolError};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+/// Bucket bounds in seconds, fine-grained at the low end where redirects should land.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0
+];
+
+/// The actors, `admin` and `token:<id>`, that got a label of their own so far.
+static LABELED_ACTORS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// Installs the process-wide Prometheus recorder and returns a handle for rendering it.
+pub fn install() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets(&LATENCY_BUCKETS)
+        .expect("latency buckets are not empty")
+        .install_recorder()
+        .expect("no other metrics recorder is installed")
+}
+
+/// Takes a connection from the pool, recording how long the request had to wait for it.
+pub async fn checkout(pool: &sqlite::Pool) -> Result<sqlite::Connection, PoolError> {
+    let requested = Instant::now();
+    let conn = pool.get().await;
+    histogram!("db_pool_wait_seconds").record(requested.elapsed().as_secs_f64());
+    conn
+}
+
+/// Counts requests and records their latency per route.
+pub async fn track(req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    // Label by route template rather than raw path, so short codes don't explode cardinality.
+    let route = req.extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned());
+
+    let response = next.run(req).await;
+    let status = response.status().as_u16().to_string();
+
+    histogram!("http_request_duration_seconds", "method" => method.clone(), "route" => route.clone())
+        .record(started.elapsed().as_secs_f64());
+    counter!("http_requests_total", "method" => method, "route" => route, "status" => status)
+        .increment(1);
+    response
+}
+
+/// The `actor` label for metrics per API token: the actor, `anonymous` without
+/// one, or `other` once `max` actors have a label already, as every label value
+/// makes new series that Prometheus keeps until the process restarts.
+pub fn actor_label(max: usize, actor: Option<&str>) -> String {
+    let Some(actor) = actor else {
+        return "anonymous".into()
+    };
+    let mut labeled = LABELED_ACTORS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if labeled.contains(actor) {
+        return actor.into()
+    }
+    if labeled.len() >= max {
+        return "other".into()
+    }
+    labeled.insert(actor.into());
+    actor.into()
+}
+
+pub async fn render_metrics(
+    State(pool): State<sqlite::Pool>,
+    Extension(handle): Extension<PrometheusHandle>
+) -> String {
+    let status = pool.status();
+    gauge!("db_pool_max_size").set(status.max_size as f64);
+    gauge!("db_pool_size").set(status.size as f64);
+    gauge!("db_pool_available").set(status.available as f64);
+    gauge!("db_pool_waiting").set(status.waiting as f64);
+    handle.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_actors_up_to_the_limit() {
+        assert_eq!(actor_label(1, None), "anonymous");
+        assert_eq!(actor_label(1, Some("token:1")), "token:1");
+        assert_eq!(actor_label(1, Some("token:2")), "other");
+        assert_eq!(actor_label(1, Some("token:1")), "token:1");
+    }
+}
@@ -0,0 +1,48 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{MatchedPath, Request}, http::{header, StatusCode}, middleware::Next, response::{IntoResponse, Response}, Extension
+};
+use metrics::counter;
+
+use crate::AppConfig;
+
+/// Routes working through many rows at once, which get `SLOW_REQUEST_TIMEOUT_SECS`,
+/// matched at the end of the route so every API version is covered.
+const SLOW_ROUTES: [&str; 8] = [
+    "/urls/bulk-delete",
+    "/urls/bulk-disable",
+    "/urls/bulk-enable",
+    "/admin/privacy",
+    "/admin/privacy/export",
+    "/admin/overview",
+    "/admin/database/maintenance",
+    "/qr/batch"
+];
+
+fn limit(config: &AppConfig, route: Option<&str>) -> Duration {
+    match route {
+        Some(route) if SLOW_ROUTES.iter().any(|slow| route.ends_with(slow)) => config.slow_request_timeout,
+        _ => config.request_timeout
+    }
+}
+
+/// Answers `503 Service Unavailable` for requests whose handler doesn't finish in
+/// time, e.g. because it's stuck waiting on the database, rather than keeping the
+/// client waiting. Work already handed to a database connection still runs to its
+/// end; the response just no longer waits for it.
+pub async fn enforce(Extension(config): Extension<Arc<AppConfig>>, req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(|path| path.as_str().to_owned());
+    let limit = limit(&config, route.as_deref());
+    match tokio::time::timeout(limit, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            counter!("http_request_timeouts_total", "route" => route.unwrap_or_else(|| "unmatched".into())).increment(1);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, "1")],
+                "Request timed out"
+            ).into_response()
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::StatusCode, Json
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::schema::api_tokens;
+use crate::telemetry;
+
+/// Marks issued tokens, so secret scanners and people can recognize leaked ones.
+const PREFIX: &str = "shorturl_";
+
+/// `last_used_at` is only written when it's at least this out of date, so
+/// busy CI jobs don't turn every request into a write.
+const LAST_USED_RESOLUTION: chrono::Duration = chrono::Duration::minutes(1);
+
+/// What an API token may be used for.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Shortening links.
+    Create,
+    /// The admin API's `GET` endpoints.
+    Read,
+    /// The whole admin API, like `ADMIN_TOKEN`.
+    Admin
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Create => "create",
+            Scope::Read => "read",
+            Scope::Admin => "admin"
+        }
+    }
+
+    fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "create" => Some(Scope::Create),
+            "read" => Some(Scope::Read),
+            "admin" => Some(Scope::Admin),
+            _ => None
+        }
+    }
+}
+
+#[derive(Selectable, Queryable)]
+#[diesel(table_name = api_tokens)]
+struct TokenRow {
+    id: i32,
+    name: String,
+    scopes: String,
+    expires_at: Option<NaiveDateTime>,
+    last_used_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>
+}
+
+/// An API token's details. The token itself is only shown when issued.
+#[derive(Serialize)]
+pub struct ApiToken {
+    pub id: i32,
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<NaiveDateTime>,
+    last_used_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>
+}
+
+impl From<TokenRow> for ApiToken {
+    fn from(row: TokenRow) -> Self {
+        ApiToken {
+            id: row.id,
+            name: row.name,
+            scopes: row.scopes.split(',').filter_map(Scope::parse).collect(),
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+            created_at: row.created_at,
+            revoked_at: row.revoked_at
+        }
+    }
+}
+
+impl ApiToken {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// How links created and changes made with this token show up in the audit log.
+    pub fn actor(&self) -> String {
+        format!("token:{}", self.id)
+    }
+}
+
+/// Only a hash is stored, so a leaked database doesn't leak working tokens.
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn issue() -> (String, String) {
+    let token = format!("{PREFIX}{}", nanoid!(32));
+    let hash = hash(&token);
+    (token, hash)
+}
+
+/// The live token `token` belongs to, noting that it was used. Revoked and expired
+/// tokens are treated as unknown.
+pub fn authenticate(conn: &mut SqliteConnection, token: &str) -> QueryResult<Option<ApiToken>> {
+    let now = Utc::now().naive_utc();
+    let found = api_tokens::table
+        .filter(api_tokens::token_hash.eq(hash(token)))
+        .filter(api_tokens::revoked_at.is_null())
+        .filter(api_tokens::expires_at.is_null().or(api_tokens::expires_at.gt(now)))
+        .select(TokenRow::as_select())
+        .get_result(conn)
+        .optional()?;
+    let Some(found) = found else {
+        return Ok(None)
+    };
+    if found.last_used_at.is_none_or(|last_used_at| now - last_used_at >= LAST_USED_RESOLUTION) {
+        diesel::update(api_tokens::table.find(found.id))
+            .set(api_tokens::last_used_at.eq(now))
+            .execute(conn)?;
+    }
+    Ok(Some(found.into()))
+}
+
+#[derive(Deserialize)]
+pub struct NewTokenRequest {
+    name: String,
+    scopes: Vec<Scope>,
+    expires_at: Option<DateTime<Utc>>
+}
+
+#[derive(Serialize)]
+pub struct IssuedToken {
+    /// The secret to authenticate with. It can't be retrieved again.
+    token: String,
+    #[serde(flatten)]
+    details: ApiToken
+}
+
+pub async fn create_token(
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<NewTokenRequest>
+) -> Result<(StatusCode, Json<IssuedToken>), ApiError> {
+    if request.name.trim().is_empty() {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, "Tokens need a name".into()).into()
+        )
+    }
+    if request.scopes.is_empty() {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, "Tokens need at least one scope".into()).into()
+        )
+    }
+    let mut scopes: Vec<&str> = request.scopes.iter().map(|scope| scope.as_str()).collect();
+    scopes.sort_unstable();
+    scopes.dedup();
+    let scopes = scopes.join(",");
+
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let (token, token_hash) = issue();
+    let details = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let row = diesel::insert_into(api_tokens::table)
+                .values((
+                    api_tokens::name.eq(request.name.trim()),
+                    api_tokens::token_hash.eq(token_hash),
+                    api_tokens::scopes.eq(scopes),
+                    api_tokens::expires_at.eq(request.expires_at.map(|expires_at| expires_at.naive_utc()))
+                ))
+                .execute(conn)
+                .and_then(|_| api_tokens::table
+                    .order(api_tokens::id.desc())
+                    .select(TokenRow::as_select())
+                    .first(conn))?;
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "create_token",
+                target: Some(&row.id.to_string()),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            Ok::<_, diesel::result::Error>(ApiToken::from(row))
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(IssuedToken { token, details })))
+}
+
+pub async fn list_tokens(
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Vec<ApiToken>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let rows = conn.interact(|conn| {
+        api_tokens::table
+            .order(api_tokens::id)
+            .select(TokenRow::as_select())
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(rows.into_iter().map(ApiToken::from).collect()))
+}
+
+pub async fn revoke_token(
+    Path(id): Path<i32>,
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let revoked = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let revoked = diesel::update(api_tokens::table.find(id))
+                .filter(api_tokens::revoked_at.is_null())
+                .set(api_tokens::revoked_at.eq(Utc::now().naive_utc()))
+                .execute(conn)?;
+            if revoked > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "revoke_token",
+                    target: Some(&id.to_string()),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(revoked)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if revoked == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "No such active token".into()).into()
+        )
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replaces a token's secret, keeping its name, scopes and expiry. The old secret
+/// stops working right away.
+pub async fn rotate_token(
+    Path(id): Path<i32>,
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<Json<IssuedToken>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let (token, token_hash) = issue();
+    let rotated = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let rotated = diesel::update(api_tokens::table.find(id))
+                .filter(api_tokens::revoked_at.is_null())
+                .set((api_tokens::token_hash.eq(token_hash), api_tokens::last_used_at.eq(None::<NaiveDateTime>)))
+                .execute(conn)?;
+            if rotated == 0 {
+                return Ok(None)
+            }
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "rotate_token",
+                target: Some(&id.to_string()),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            api_tokens::table
+                .find(id)
+                .select(TokenRow::as_select())
+                .get_result(conn)
+                .map(|row| Some(ApiToken::from(row)))
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    let Some(details) = rotated else {
+        return Err(
+            (StatusCode::NOT_FOUND, "No such active token".into()).into()
+        )
+    };
+    Ok(Json(IssuedToken { token, details }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issued_tokens_are_prefixed_and_stored_hashed() {
+        let (token, token_hash) = issue();
+        assert!(token.starts_with(PREFIX));
+        assert_eq!(token_hash, hash(&token));
+        assert_ne!(token_hash, token);
+        assert_ne!(issue().0, token);
+    }
+
+    #[test]
+    fn scopes_round_trip() {
+        for scope in [Scope::Create, Scope::Read, Scope::Admin] {
+            assert!(Scope::parse(scope.as_str()) == Some(scope));
+        }
+        assert!(Scope::parse("write").is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State, http::StatusCode, Extension, Json
+};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::auth::{Actor, ADMIN_ACTOR};
+use crate::errors::{self, ApiError, internal_error, unavailable};
+use crate::schema::usage;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// What's metered per actor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Meter {
+    LinksCreated,
+    /// Redirects of the links the actor created.
+    Redirects
+}
+
+/// The billing period `at` falls in: its UTC calendar month, e.g. `2026-10`.
+fn period(at: NaiveDateTime) -> String {
+    at.format("%Y-%m").to_string()
+}
+
+/// When the period `at` falls in ends and usage starts over.
+fn period_end(at: NaiveDateTime) -> NaiveDateTime {
+    let start = NaiveDate::from_ymd_opt(at.year(), at.month(), 1).expect("months have a first day");
+    (start + Months::new(1)).and_time(Default::default())
+}
+
+/// The quota `config` sets for `meter`, where 0 means unlimited.
+pub fn configured(config: &AppConfig, meter: Meter) -> i64 {
+    match meter {
+        Meter::LinksCreated => config.link_quota,
+        Meter::Redirects => config.redirect_quota
+    }
+}
+
+/// The quota `actor` has of a `configured` one, or `None` if they're
+/// unlimited. `ADMIN_TOKEN` is metered but never limited.
+fn quota(configured: i64, actor: &str) -> Option<i64> {
+    (configured > 0 && actor != ADMIN_ACTOR).then_some(configured)
+}
+
+#[derive(Default, Queryable)]
+struct Used {
+    links_created: i64,
+    redirects: i64
+}
+
+fn used(conn: &mut SqliteConnection, actor: &str, at: NaiveDateTime) -> QueryResult<Used> {
+    usage::table
+        .find((actor, period(at)))
+        .select((usage::links_created, usage::redirects))
+        .first(conn)
+        .optional()
+        .map(Option::unwrap_or_default)
+}
+
+/// Counts one use of `meter` by `actor`, unless their `configured` quota for
+/// the period is used up, which is answered with the error to give. Run it in
+/// the transaction making the change, so concurrent requests can't overshoot.
+pub fn count(conn: &mut SqliteConnection, actor: &str, meter: Meter, configured: i64) -> QueryResult<Result<(), ApiError>> {
+    let now = Utc::now().naive_utc();
+    if let Some(quota) = quota(configured, actor) {
+        let used = used(conn, actor, now)?;
+        let (used, status, kind) = match meter {
+            Meter::LinksCreated => (used.links_created, StatusCode::TOO_MANY_REQUESTS, "link"),
+            Meter::Redirects => (used.redirects, StatusCode::PAYMENT_REQUIRED, "redirect")
+        };
+        if used >= quota {
+            let resets = period_end(now).date();
+            return Ok(Err(
+                errors::coded("quota_exceeded", (status, format!("The quota of {quota} {kind}s a month is used up; it resets on {resets}")))
+            ))
+        }
+    }
+    let (links_created, redirects) = match meter {
+        Meter::LinksCreated => (1, 0),
+        Meter::Redirects => (0, 1)
+    };
+    diesel::insert_into(usage::table)
+        .values((
+            usage::actor.eq(actor),
+            usage::period.eq(period(now)),
+            usage::links_created.eq(links_created),
+            usage::redirects.eq(redirects)
+        ))
+        .on_conflict((usage::actor, usage::period))
+        .do_update()
+        .set((
+            usage::links_created.eq(usage::links_created + links_created),
+            usage::redirects.eq(usage::redirects + redirects)
+        ))
+        .execute(conn)?;
+    Ok(Ok(()))
+}
+
+/// Deletes the usage of `actor`, for erasing their data on request.
+pub fn delete(conn: &mut SqliteConnection, actor: &str) -> QueryResult<usize> {
+    diesel::delete(usage::table.filter(usage::actor.eq(actor))).execute(conn)
+}
+
+#[derive(Serialize)]
+pub struct UsageReport {
+    actor: String,
+    period: String,
+    resets_at: NaiveDateTime,
+    links_created: i64,
+    /// `None` when unlimited.
+    link_quota: Option<i64>,
+    redirects: i64,
+    redirect_quota: Option<i64>
+}
+
+/// The caller's usage in the current period, along with their quotas.
+pub async fn my_usage(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    actor: Actor
+) -> Result<Json<UsageReport>, ApiError> {
+    let Some(actor) = actor.0 else {
+        return Err(
+            errors::coded("token_required", (StatusCode::UNAUTHORIZED, "An API token is required".into()))
+        )
+    };
+    let now = Utc::now().naive_utc();
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let metered = actor.clone();
+    let used = conn.interact(move |conn| used(conn, &metered, now))
+        .await
+        .map_err(internal_error)?
+        .map_err(internal_error)?;
+
+    Ok(Json(UsageReport {
+        link_quota: quota(configured(&config, Meter::LinksCreated), &actor),
+        redirect_quota: quota(configured(&config, Meter::Redirects), &actor),
+        actor,
+        period: period(now),
+        resets_at: period_end(now),
+        links_created: used.links_created,
+        redirects: used.redirects
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_admins_are_unlimited() {
+        assert_eq!(quota(100, "token:3"), Some(100));
+        assert_eq!(quota(100, ADMIN_ACTOR), None);
+        assert_eq!(quota(0, "token:3"), None);
+    }
+
+    #[test]
+    fn periods_are_calendar_months() {
+        let at = NaiveDate::from_ymd_opt(2026, 12, 31).unwrap().and_hms_opt(23, 59, 0).unwrap();
+        assert_eq!(period(at), "2026-12");
+        assert_eq!(period_end(at), NaiveDate::from_ymd_opt(2027, 1, 1).unwrap().and_time(Default::default()));
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    net::SocketAddr, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}
+};
+
+use axum::{
+    extract::{ConnectInfo, State}, http::StatusCode, Json
+};
+use chrono::{NaiveTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use metrics::counter;
+use serde::Serialize;
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::database;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::telemetry;
+
+/// Set while a run is going, so scheduled and requested runs don't overlap.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+pub struct MaintenanceReport {
+    bytes_before: i64,
+    bytes_after: i64,
+    duration_ms: u128
+}
+
+/// Runs maintenance every day at `hour` o'clock UTC, which should be when the
+/// service is least busy: writes wait while the database is vacuumed.
+pub fn spawn(pool: sqlite::Pool, hour: u32) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(until_next(hour)).await;
+            match run(&pool).await {
+                Ok(Some(report)) => println!(
+                    "Database maintenance took {} ms and took the database from {} to {} bytes",
+                    report.duration_ms, report.bytes_before, report.bytes_after
+                ),
+                Ok(None) => {}
+                Err(err) => println!("Database maintenance failed: {err}")
+            }
+        }
+    });
+}
+
+/// The time from now until the next `hour` o'clock UTC.
+fn until_next(hour: u32) -> Duration {
+    let now = Utc::now().naive_utc();
+    let at = now.date().and_time(NaiveTime::from_hms_opt(hour, 0, 0).expect("hour is below 24"));
+    let next = if at > now { at } else { at + chrono::Duration::days(1) };
+    (next - now).to_std().unwrap_or_default()
+}
+
+/// Rebuilds the database file without free pages with `VACUUM`, then refreshes
+/// the statistics the query planner goes by with `ANALYZE` and `PRAGMA optimize`.
+/// Returns `None` if another run is already going.
+async fn run(pool: &sqlite::Pool) -> Result<Option<MaintenanceReport>, String> {
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return Ok(None)
+    }
+    let outcome = maintain(pool).await;
+    RUNNING.store(false, Ordering::Release);
+    counter!("database_maintenance_runs_total", "outcome" => if outcome.is_ok() { "ok" } else { "failed" }).increment(1);
+    outcome.map(Some)
+}
+
+async fn maintain(pool: &sqlite::Pool) -> Result<MaintenanceReport, String> {
+    let started = Instant::now();
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    let (bytes_before, bytes_after) = conn.interact(|conn| {
+        let before = database::size(conn)?;
+        diesel::sql_query("VACUUM").execute(conn)?;
+        // VACUUM may renumber the rowids the search index refers to.
+        diesel::sql_query("INSERT INTO urls_search(urls_search) VALUES ('rebuild')").execute(conn)?;
+        diesel::sql_query("ANALYZE").execute(conn)?;
+        diesel::sql_query("PRAGMA optimize").execute(conn)?;
+        Ok::<_, diesel::result::Error>((before, database::size(conn)?))
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+
+    Ok(MaintenanceReport { bytes_before, bytes_after, duration_ms: started.elapsed().as_millis() })
+}
+
+/// Runs maintenance right away, e.g. after deleting many links.
+pub async fn run_maintenance(
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<Json<MaintenanceReport>, ApiError> {
+    let Some(report) = run(&pool).await.map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))? else {
+        return Err(
+            (StatusCode::CONFLICT, "Database maintenance is already running".into()).into()
+        )
+    };
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    conn.interact(move |conn| {
+        audit::record(conn, NewAuditEntry {
+            actor: actor.name(),
+            action: "database_maintenance",
+            target: None,
+            ip: Some(&addr.ip().to_string())
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(report))
+}
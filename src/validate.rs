@@ -0,0 +1,150 @@
+use std::sync::OnceLock;
+
+use axum::http::{StatusCode, Uri};
+
+use crate::errors::{self, ApiError};
+use crate::links;
+use crate::spam;
+use crate::ssrf;
+use crate::AppConfig;
+
+/// A check every destination goes through before a link is created. Validators
+/// run in order, each receiving what the previous one returned, so they can
+/// rewrite the URL as well as reject it.
+pub trait Validator: Send + Sync {
+    fn validate(&self, config: &AppConfig, url: String) -> Result<String, ApiError>;
+}
+
+/// Rejects destinations longer than `MAX_URL_LENGTH`.
+pub struct MaxLength;
+
+impl Validator for MaxLength {
+    fn validate(&self, config: &AppConfig, url: String) -> Result<String, ApiError> {
+        if url.len() > config.max_url_length {
+            return Err(
+                errors::invalid("url", "url_too_long", (StatusCode::UNPROCESSABLE_ENTITY, format!("URL is longer than {} bytes", config.max_url_length)))
+            )
+        }
+        Ok(url)
+    }
+}
+
+/// Only lets through absolute `http` and `https` URLs.
+pub struct WebScheme;
+
+impl Validator for WebScheme {
+    fn validate(&self, _: &AppConfig, url: String) -> Result<String, ApiError> {
+        if url.parse::<Uri>().is_err() {
+            return Err(
+                errors::invalid("url", "url_invalid", (StatusCode::BAD_REQUEST, "Not a valid URL".into()))
+            )
+        }
+        if !links::is_web_url(&url) {
+            return Err(
+                errors::invalid("url", "scheme_forbidden", (StatusCode::BAD_REQUEST, "Only http and https URLs can be shortened".into()))
+            )
+        }
+        Ok(url)
+    }
+}
+
+/// Rewrites destinations into their canonical spelling, see [`links::normalize_url`].
+pub struct Normalize;
+
+impl Validator for Normalize {
+    fn validate(&self, _: &AppConfig, url: String) -> Result<String, ApiError> {
+        Ok(links::normalize_url(&url))
+    }
+}
+
+/// Refuses destinations on any of `BLOCKED_DOMAINS` or their subdomains.
+pub struct Blocklist;
+
+/// Whether `url` points to one of `blocked` (lowercase) or a subdomain of one.
+fn blocks(blocked: &[String], url: &str) -> bool {
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(|host| host.trim_end_matches('.').to_ascii_lowercase()))
+        .unwrap_or_default();
+    let blocked = spam::domains(&host).any(|domain| blocked.iter().any(|blocked| blocked == domain));
+    blocked
+}
+
+impl Validator for Blocklist {
+    fn validate(&self, config: &AppConfig, url: String) -> Result<String, ApiError> {
+        if blocks(&config.blocked_domains, &url) {
+            return Err(
+                errors::invalid("url", "domain_blocked", (StatusCode::FORBIDDEN, "Links to this domain are not allowed".into()))
+            )
+        }
+        Ok(url)
+    }
+}
+
+/// Refuses destinations on loopback, private, link-local and cloud metadata
+/// addresses, which the service's own fetches could otherwise be pointed at,
+/// unless `ALLOW_PRIVATE_DESTINATIONS` is set. Only hosts that are addresses or
+/// names like `localhost` are known here; the rest are checked when fetched.
+pub struct PublicHosts;
+
+impl Validator for PublicHosts {
+    fn validate(&self, config: &AppConfig, url: String) -> Result<String, ApiError> {
+        let internal = url::Url::parse(&url).is_ok_and(|parsed| ssrf::names_internal_host(&parsed));
+        if internal && !config.allow_private_destinations {
+            return Err(
+                errors::invalid("url", "destination_internal", (StatusCode::FORBIDDEN, "Links to internal addresses are not allowed".into()))
+            )
+        }
+        Ok(url)
+    }
+}
+
+/// The validators a destination goes through, in order.
+pub struct Pipeline {
+    validators: Vec<Box<dyn Validator>>
+}
+
+impl Pipeline {
+    /// The checks every instance needs: a bounded, `http(s)`, normalized URL.
+    pub fn standard() -> Self {
+        Pipeline { validators: vec![Box::new(MaxLength), Box::new(WebScheme), Box::new(Normalize)] }
+    }
+
+    /// Adds `validator` at the end, after normalization.
+    pub fn with(mut self, validator: impl Validator + 'static) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    pub fn run(&self, config: &AppConfig, url: String) -> Result<String, ApiError> {
+        self.validators
+            .iter()
+            .try_fold(url, |url, validator| validator.validate(config, url))
+    }
+}
+
+static PIPELINE: OnceLock<Pipeline> = OnceLock::new();
+
+/// Sets the pipeline links are created with. Only the first call has an effect.
+pub fn install(pipeline: Pipeline) {
+    PIPELINE.set(pipeline).ok();
+}
+
+/// The installed pipeline, or the standard one if none was.
+pub fn pipeline() -> &'static Pipeline {
+    PIPELINE.get_or_init(Pipeline::standard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocklist_matches_domains_and_subdomains() {
+        let blocked = ["evil.example".to_owned()];
+        assert!(blocks(&blocked, "https://evil.example/"));
+        assert!(blocks(&blocked, "http://login.EVIL.example./reset"));
+        assert!(!blocks(&blocked, "https://notevil.example/"));
+        assert!(!blocks(&blocked, "https://example.com/?next=evil.example"));
+    }
+}
@@ -0,0 +1,31 @@
+use axum::Json;
+use chrono::DateTime;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct VersionInfo {
+    version: &'static str,
+    git_commit: &'static str,
+    built_at: String,
+    features: Vec<&'static str>
+}
+
+/// What exactly is deployed, as embedded by `build.rs`.
+pub async fn version() -> Json<VersionInfo> {
+    let built_at = env!("SHORTURL_BUILT_AT")
+        .parse()
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default();
+
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("SHORTURL_GIT_COMMIT"),
+        built_at,
+        features: env!("SHORTURL_FEATURES")
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .collect()
+    })
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Request, http::{header, HeaderName, HeaderValue}, middleware::{self, Next}, response::Response, Extension, Router
+};
+use deadpool_diesel::sqlite;
+
+use crate::AppConfig;
+
+/// The version new clients should use, and the one served at the unversioned paths.
+pub const CURRENT: &str = "v1";
+
+/// When the unversioned paths were deprecated: the day `/api/v1` was introduced,
+/// in seconds since the epoch.
+const DEPRECATED_AT: i64 = 1_791_936_000;
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Serves the JSON API at `/api/v1`, and at the unversioned `/api` paths it used
+/// to have, which answer the same but are marked as deprecated. A later version
+/// gets nested at its own prefix next to these.
+pub fn mount(v1: Router<sqlite::Pool>) -> Router<sqlite::Pool> {
+    Router::new()
+        .nest(&format!("/api/{CURRENT}"), v1.clone())
+        .nest("/api", v1.layer(middleware::from_fn(deprecated)))
+}
+
+/// Announces the deprecation (RFC 9745), the endpoint replacing the one requested
+/// and, with `API_SUNSET` set, the day the unversioned paths go away (RFC 8594).
+async fn deprecated(Extension(config): Extension<Arc<AppConfig>>, req: Request, next: Next) -> Response {
+    // Nesting under `/api` stripped the prefix, leaving the path within the API.
+    let successor = format!("</api/{CURRENT}{}>; rel=\"successor-version\"", req.uri().path());
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION, HeaderValue::from_str(&format!("@{DEPRECATED_AT}")).expect("valid header"));
+    if let Ok(successor) = HeaderValue::from_str(&successor) {
+        headers.append(header::LINK, successor);
+    }
+    if let Some(sunset) = config.api_sunset {
+        let sunset = sunset.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+        headers.insert(
+            SUNSET,
+            HeaderValue::from_str(&sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).expect("valid header")
+        );
+    }
+    response
+}
@@ -0,0 +1,375 @@
+use std::{
+    io::Read, net::SocketAddr, sync::{Arc, LazyLock}, time::Duration
+};
+
+use arc_swap::ArcSwap;
+use axum::{
+    extract::{ConnectInfo, Path, State}, http::StatusCode, Extension, Json
+};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_diesel::sqlite;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::{broadcast::error::RecvError, Semaphore};
+
+use crate::audit::{self, NewAuditEntry};
+use crate::auth::Actor;
+use crate::errors::{ApiError, internal_error, unavailable};
+use crate::events::{self, Event};
+use crate::links;
+use crate::schema::webhooks;
+use crate::ssrf;
+use crate::telemetry;
+use crate::AppConfig;
+
+/// The version of the payload schema, bumped whenever a field changes meaning or goes away.
+const SCHEMA_VERSION: u32 = 1;
+
+/// The header carrying the delivery's timestamp and signature.
+const SIGNATURE_HEADER: &str = "X-Shorturl-Signature";
+
+/// How far a delivery's timestamp may be off the receiver's clock for it to be accepted.
+pub const TOLERANCE_SECS: i64 = 5 * 60;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Attempts per delivery, the later ones after [`RETRY_DELAY`], then twice that.
+const ATTEMPTS: u32 = 3;
+
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Deliveries under way at once; events beyond that, as in a click flood, are dropped.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// The endpoints events are delivered to, reloaded whenever they change.
+static ENDPOINTS: LazyLock<ArcSwap<Vec<Webhook>>> = LazyLock::new(|| ArcSwap::from_pointee(Vec::new()));
+
+#[derive(Clone, Selectable, Queryable)]
+#[diesel(table_name = webhooks)]
+struct Webhook {
+    id: i32,
+    url: String,
+    secret: String,
+    types: Option<String>
+}
+
+impl Webhook {
+    fn wants(&self, event: &Event) -> bool {
+        self.types
+            .as_deref()
+            .is_none_or(|types| types.split(',').any(|name| name == event.name()))
+    }
+}
+
+#[derive(Serialize, Selectable, Queryable)]
+#[diesel(table_name = webhooks)]
+pub struct WebhookSummary {
+    id: i32,
+    url: String,
+    types: Option<String>,
+    created_at: NaiveDateTime
+}
+
+#[derive(Deserialize)]
+pub struct NewWebhookRequest {
+    url: String,
+    /// The event types to deliver, all of them when absent.
+    types: Option<Vec<String>>
+}
+
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    id: i32,
+    url: String,
+    /// Signs every delivery; only shown here.
+    secret: String
+}
+
+/// What is posted to the endpoint, in version [`SCHEMA_VERSION`] of the schema.
+#[derive(Serialize)]
+struct Delivery<'a> {
+    version: u32,
+    /// Unique per delivery and kept across its retries, so receivers can drop replays.
+    nonce: &'a str,
+    /// Unix time the delivery was signed at.
+    timestamp: i64,
+    event: &'a Event
+}
+
+/// The HMAC-SHA256 of `timestamp` and `body`, joined by a dot, under `secret`.
+fn mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// The value of the signature header, e.g. `t=1717243200,v1=5257a8…`.
+fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    format!("t={timestamp},v1={}", hex::encode(mac(secret, timestamp, body).finalize().into_bytes()))
+}
+
+/// Whether `header` is a valid signature of `body` under `secret`, made no more
+/// than [`TOLERANCE_SECS`] before `now`. Receivers should also remember the
+/// `nonce` of each delivery accepted within that time and refuse repeats.
+pub fn verify(secret: &str, header: &str, body: &[u8], now: i64) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+            _ => {}
+        }
+    }
+    let Some(timestamp) = timestamp else {
+        return false
+    };
+    let fresh = (now - TOLERANCE_SECS..=now + TOLERANCE_SECS).contains(&timestamp);
+    fresh && signatures.iter().any(|signature| mac(secret, timestamp, body).verify_slice(signature).is_ok())
+}
+
+async fn reload(pool: &sqlite::Pool) -> Result<(), String> {
+    let conn = telemetry::checkout(pool)
+        .await
+        .map_err(|err| err.to_string())?;
+    let endpoints = conn.interact(|conn| webhooks::table.select(Webhook::as_select()).load(conn))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    ENDPOINTS.store(Arc::new(endpoints));
+    Ok(())
+}
+
+/// Starts posting every event to the webhooks that want it, signed with each
+/// webhook's secret. Internal addresses are refused unless `allow_private` is set.
+pub fn spawn(pool: sqlite::Pool, allow_private: bool) {
+    let mut client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none());
+    if !allow_private {
+        client = client.dns_resolver(Arc::new(ssrf::PublicResolver));
+    }
+    let client = client.build().expect("HTTP client can be built");
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+    let mut events = events::subscribe();
+
+    tokio::spawn(async move {
+        if let Err(err) = reload(&pool).await {
+            println!("Loading webhooks failed: {err}");
+        }
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => Arc::new(event),
+                Err(RecvError::Lagged(missed)) => {
+                    counter!("webhook_deliveries_total", "outcome" => "dropped").increment(missed);
+                    continue
+                }
+                Err(RecvError::Closed) => return
+            };
+            for webhook in ENDPOINTS.load().iter().filter(|webhook| webhook.wants(&event)) {
+                let Ok(permit) = in_flight.clone().try_acquire_owned() else {
+                    counter!("webhook_deliveries_total", "outcome" => "dropped").increment(1);
+                    continue
+                };
+                let (client, webhook, event) = (client.clone(), webhook.clone(), event.clone());
+                tokio::spawn(async move {
+                    let outcome = match deliver(&client, &webhook, &event).await {
+                        Ok(()) => "delivered",
+                        Err(err) => {
+                            println!("Webhook {} at {} failed: {err}", webhook.id, webhook.url);
+                            "failed"
+                        }
+                    };
+                    counter!("webhook_deliveries_total", "outcome" => outcome).increment(1);
+                    drop(permit);
+                });
+            }
+        }
+    });
+}
+
+async fn deliver(client: &reqwest::Client, webhook: &Webhook, event: &Event) -> Result<(), String> {
+    let nonce = nanoid!();
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let timestamp = Utc::now().timestamp();
+        let body = serde_json::to_vec(&Delivery { version: SCHEMA_VERSION, nonce: &nonce, timestamp, event })
+            .expect("events serialize");
+        let sent = client.post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature(&webhook.secret, timestamp, &body))
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|response| match response.status().is_success() {
+                true => Ok(()),
+                false => Err(format!("answered {}", response.status()))
+            });
+        match sent {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt == ATTEMPTS => return Err(err),
+            Err(_) => {}
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+pub async fn list_webhooks(
+    State(pool): State<sqlite::Pool>
+) -> Result<Json<Vec<WebhookSummary>>, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let webhooks = conn.interact(|conn| {
+        webhooks::table
+            .order(webhooks::id)
+            .select(WebhookSummary::as_select())
+            .load(conn)
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    Ok(Json(webhooks))
+}
+
+pub async fn create_webhook(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor,
+    Json(request): Json<NewWebhookRequest>
+) -> Result<(StatusCode, Json<CreatedWebhook>), ApiError> {
+    let internal = url::Url::parse(&request.url).is_ok_and(|url| ssrf::names_internal_host(&url));
+    if !links::is_web_url(&request.url) || (internal && !config.allow_private_destinations) {
+        return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, "Webhooks need a public http or https URL".into()).into()
+        )
+    }
+    let types = match request.types {
+        Some(types) if types.is_empty() || types.iter().any(|name| !events::TYPES.contains(&name.as_str())) => return Err(
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("Event types must be some of {}", events::TYPES.join(", "))).into()
+        ),
+        Some(types) => Some(types.join(",")),
+        None => None
+    };
+    let secret = nanoid!(32);
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+
+    let (url, stored_secret) = (request.url.clone(), secret.clone());
+    let id = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            diesel::insert_into(webhooks::table)
+                .values((
+                    webhooks::url.eq(&url),
+                    webhooks::secret.eq(&stored_secret),
+                    webhooks::types.eq(&types)
+                ))
+                .execute(conn)?;
+            let id = webhooks::table
+                .order(webhooks::id.desc())
+                .select(webhooks::id)
+                .first::<i32>(conn)?;
+            audit::record(conn, NewAuditEntry {
+                actor: actor.name(),
+                action: "create_webhook",
+                target: Some(&id.to_string()),
+                ip: Some(&addr.ip().to_string())
+            })?;
+            Ok::<_, diesel::result::Error>(id)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    reload(&pool).await.map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    Ok((StatusCode::CREATED, Json(CreatedWebhook { id, url: request.url, secret })))
+}
+
+pub async fn delete_webhook(
+    Path(id): Path<i32>,
+    State(pool): State<sqlite::Pool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    actor: Actor
+) -> Result<StatusCode, ApiError> {
+    let conn = telemetry::checkout(&pool)
+        .await
+        .map_err(unavailable)?;
+    let deleted = conn.interact(move |conn| {
+        conn.transaction(|conn| {
+            let deleted = diesel::delete(webhooks::table.find(id)).execute(conn)?;
+            if deleted > 0 {
+                audit::record(conn, NewAuditEntry {
+                    actor: actor.name(),
+                    action: "delete_webhook",
+                    target: Some(&id.to_string()),
+                    ip: Some(&addr.ip().to_string())
+                })?;
+            }
+            Ok::<_, diesel::result::Error>(deleted)
+        })
+    })
+    .await
+    .map_err(internal_error)?
+    .map_err(internal_error)?;
+
+    if deleted == 0 {
+        return Err(
+            (StatusCode::NOT_FOUND, "Webhook not found".into()).into()
+        )
+    }
+    reload(&pool).await.map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `shorturl webhook verify <secret> <signature>`, which checks a delivery's
+/// body, read from standard input, the way receivers should.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let usage = "Usage: webhook verify <secret> <signature header> < body";
+    let [command, secret, header] = args else {
+        return Err(usage.into())
+    };
+    if command != "verify" {
+        return Err(usage.into())
+    }
+    let mut body = Vec::new();
+    std::io::stdin().read_to_end(&mut body).map_err(|err| err.to_string())?;
+    if !verify(secret, header, &body, Utc::now().timestamp()) {
+        return Err("Invalid or expired signature".into())
+    }
+    println!("Valid signature");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_fresh_untampered_deliveries() {
+        let now = Utc::now().timestamp();
+        let body = br#"{"version":1}"#;
+        let header = signature("secret", now, body);
+        assert!(verify("secret", &header, body, now));
+        assert!(verify("secret", &header, body, now + TOLERANCE_SECS));
+        assert!(!verify("secret", &header, body, now + TOLERANCE_SECS + 1));
+        assert!(!verify("other", &header, body, now));
+        assert!(!verify("secret", &header, br#"{"version":2}"#, now));
+        assert!(!verify("secret", &header.replace(&format!("t={now}"), &format!("t={}", now + 1)), body, now));
+        assert!(!verify("secret", "v1=abcd", body, now));
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    path::{Component, Path as FsPath}, sync::Arc
+};
+
+use axum::{
+    extract::{Path, State}, http::{header, StatusCode}, response::{IntoResponse, Response}, Extension
+};
+
+use deadpool_diesel::sqlite;
+
+use crate::errors::ApiError;
+use crate::sitemap;
+use crate::AppConfig;
+
+const DEFAULT_ROBOTS_TXT: &str = "User-agent: *\nDisallow: /api/\n";
+
+fn content_type(path: &FsPath) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("ico") => "image/x-icon",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("json") => "application/json",
+        Some("html") => "text/html; charset=utf-8",
+        _ => "text/plain; charset=utf-8"
+    }
+}
+
+/// Static files browsers and crawlers ask for, answered without a short code lookup.
+pub struct StaticFiles {
+    favicon: Option<(&'static str, Vec<u8>)>,
+    /// The operator's own `ROBOTS_TXT_PATH`, served as is.
+    robots_txt: Option<String>,
+    well_known_dir: Option<String>
+}
+
+impl StaticFiles {
+    pub fn load(favicon_path: Option<&str>, robots_txt_path: Option<&str>, well_known_dir: Option<String>) -> Result<Self, String> {
+        let read = |name: &str, path: &str| {
+            std::fs::read(path).map_err(|err| format!("Can't read {name} {path}: {err}"))
+        };
+        let favicon = favicon_path
+            .map(|path| read("FAVICON_PATH", path).map(|bytes| (content_type(FsPath::new(path)), bytes)))
+            .transpose()?;
+        let robots_txt = robots_txt_path
+            .map(|path| read("ROBOTS_TXT_PATH", path).map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+            .transpose()?;
+        Ok(StaticFiles { favicon, robots_txt, well_known_dir })
+    }
+}
+
+pub async fn favicon(Extension(config): Extension<Arc<AppConfig>>) -> Response {
+    match &config.static_files.favicon {
+        Some((content_type, bytes)) => ([(header::CONTENT_TYPE, *content_type)], bytes.clone()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+/// With `SITEMAP` set and no robots.txt of the operator's, the rules are generated
+/// to match the sitemap.
+pub async fn robots_txt(
+    State(pool): State<sqlite::Pool>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Result<String, ApiError> {
+    match &config.static_files.robots_txt {
+        Some(robots_txt) => Ok(robots_txt.clone()),
+        None if config.sitemap => sitemap::robots_txt(&pool, &config).await,
+        None => Ok(DEFAULT_ROBOTS_TXT.into())
+    }
+}
+
+/// Serves `/.well-known/*` from `WELL_KNOWN_DIR`, e.g. for ACME challenges or
+/// `security.txt`. Paths climbing out of the directory are refused.
+pub async fn well_known(
+    Path(path): Path<String>,
+    Extension(config): Extension<Arc<AppConfig>>
+) -> Response {
+    let Some(dir) = config.static_files.well_known_dir.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response()
+    };
+    let relative = FsPath::new(&path);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return StatusCode::NOT_FOUND.into_response()
+    }
+
+    let file = FsPath::new(dir).join(relative);
+    match tokio::fs::read(&file).await {
+        Ok(bytes) => ([(header::CONTENT_TYPE, content_type(&file))], bytes).into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response()
+    }
+}